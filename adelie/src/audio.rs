@@ -0,0 +1,322 @@
+//! Audio resampling for frontends.
+//!
+//! The APU generates a sample at 2 MiHz, which is far too fast to hand to an audio device. An
+//! [`AudioResampler`] converts these samples to stereo at a host rate (such as 44.1 kHz or 48 kHz)
+//! and buffers them so that they can be drained after each frame.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+use crate::instance::AudioSample;
+use crate::util::{ceil, cos, floor, sin};
+
+/// Rate at which the APU generates samples, in Hz.
+pub const APU_SAMPLE_RATE: u32 = 1024 * 1024 * 2;
+
+/// Number of kernel table entries per input sample for [`ResamplerQuality::WindowedSinc`].
+const SINC_TABLE_RESOLUTION: usize = 64;
+
+/// Minimum ratio between the intermediate (pre-decimated) rate and the output rate.
+const INTERMEDIATE_OVERSAMPLING: u32 = 4;
+
+/// Quality to use for resampling.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ResamplerQuality {
+    /// Average samples down to a few times the output rate, then linearly interpolate.
+    ///
+    /// This is the cheapest method, but some aliasing may be audible.
+    Linear,
+
+    /// Average samples down to a few times the output rate, then interpolate with a
+    /// Blackman-windowed sinc low-pass filter at the output rate's Nyquist frequency.
+    ///
+    /// `zero_crossings` is the number of zero crossings on each side of the kernel. Higher values
+    /// give a sharper cutoff at the cost of CPU time. 8 is a reasonable value, and 32 is very high
+    /// quality.
+    WindowedSinc { zero_crossings: u16 }
+}
+
+/// Sample format used when draining an [`AudioResampler`].
+pub trait OutputSample: Copy {
+    fn from_f32(sample: f32) -> Self;
+}
+
+impl OutputSample for f32 {
+    fn from_f32(sample: f32) -> Self {
+        sample
+    }
+}
+
+impl OutputSample for i16 {
+    fn from_f32(sample: f32) -> Self {
+        (sample * 32767.0).clamp(-32768.0, 32767.0) as i16
+    }
+}
+
+/// Resamples APU output to interleaved stereo at a host rate.
+pub struct AudioResampler {
+    decimation: u32,
+    decimation_count: u32,
+    decimation_sum: (i64, i64),
+
+    /// Intermediate samples, with `history_start` being the index of the first one.
+    history: VecDeque<(f32, f32)>,
+    history_start: u64,
+
+    /// Position of the next output sample, in intermediate samples.
+    output_position: f64,
+    output_step: f64,
+
+    /// Kernel half width in intermediate samples, and kernel table if using sinc.
+    kernel_radius: usize,
+    kernel: Option<Vec<f32>>,
+
+    output: VecDeque<f32>,
+    max_buffered_frames: usize
+}
+
+impl AudioResampler {
+    /// Create a new resampler.
+    ///
+    /// `input_rate` is normally [`APU_SAMPLE_RATE`]. At most `max_buffered_frames` stereo frames
+    /// are buffered; if they are not drained in time, the oldest frames are discarded.
+    ///
+    /// Panics if either rate or `max_buffered_frames` is 0. Any other rates are accepted; if the
+    /// output rate is close to or above the input rate, samples are just not decimated.
+    pub fn new(input_rate: u32, output_rate: u32, quality: ResamplerQuality, max_buffered_frames: usize) -> Self {
+        assert!(input_rate > 0 && output_rate > 0, "sample rates must be non-zero");
+        assert!(max_buffered_frames > 0, "at least one frame must be buffered");
+
+        let decimation = (input_rate as u64 / (output_rate as u64 * INTERMEDIATE_OVERSAMPLING as u64)).max(1) as u32;
+        let intermediate_rate = input_rate as f64 / decimation as f64;
+        let output_step = intermediate_rate / output_rate as f64;
+
+        let (kernel_radius, kernel) = match quality {
+            ResamplerQuality::Linear => (1, None),
+            ResamplerQuality::WindowedSinc { zero_crossings } => {
+                let zero_crossings = zero_crossings.max(1) as f64;
+
+                // Cutoff relative to the intermediate rate (never above its own Nyquist frequency)
+                let cutoff = (0.5 / output_step).min(0.5);
+                let radius = ceil(zero_crossings / (2.0 * cutoff)) as usize;
+                (radius, Some(Self::generate_sinc_kernel(radius, cutoff)))
+            }
+        };
+
+        Self {
+            decimation,
+            decimation_count: 0,
+            decimation_sum: (0, 0),
+            history: VecDeque::new(),
+            history_start: 0,
+            output_position: 0.0,
+            output_step,
+            kernel_radius,
+            kernel,
+            output: VecDeque::with_capacity(max_buffered_frames * 2),
+            max_buffered_frames
+        }
+    }
+
+    /// Generate a Blackman-windowed sinc table from `-radius` to `radius` intermediate samples.
+    fn generate_sinc_kernel(radius: usize, cutoff: f64) -> Vec<f32> {
+        let length = radius * 2 * SINC_TABLE_RESOLUTION + 1;
+        (0..length).map(|i| {
+            let t = (i as f64 / SINC_TABLE_RESOLUTION as f64) - radius as f64;
+            let x = 2.0 * cutoff * t;
+            let sinc = if x == 0.0 { 1.0 } else { sin(PI * x) / (PI * x) };
+            let w = (t / radius as f64 + 1.0) / 2.0;
+            let window = 0.42 - 0.5 * cos(2.0 * PI * w) + 0.08 * cos(4.0 * PI * w);
+            (2.0 * cutoff * sinc * window) as f32
+        }).collect()
+    }
+
+    /// Push a single sample from the APU, such as [`APUSamples::mixed`](crate::instance::APUSamples::mixed).
+    pub fn push(&mut self, sample: AudioSample) {
+        self.decimation_sum.0 += sample.left as i64 - 0x8000;
+        self.decimation_sum.1 += sample.right as i64 - 0x8000;
+        self.decimation_count += 1;
+        if self.decimation_count < self.decimation {
+            return
+        }
+
+        let scale = (self.decimation as f32) * 32768.0;
+        self.history.push_back((self.decimation_sum.0 as f32 / scale, self.decimation_sum.1 as f32 / scale));
+        self.decimation_sum = (0, 0);
+        self.decimation_count = 0;
+
+        self.generate_output();
+    }
+
+    /// Number of stereo frames ready to be drained.
+    pub fn available_frames(&self) -> usize {
+        self.output.len() / 2
+    }
+
+    /// Drain interleaved stereo samples (left first) into `output`, returning the number of
+    /// samples (not frames) written.
+    pub fn drain<T: OutputSample>(&mut self, output: &mut [T]) -> usize {
+        let count = (output.len() & !1).min(self.output.len());
+        for (o, i) in output.iter_mut().zip(self.output.drain(..count)) {
+            *o = T::from_f32(i);
+        }
+        count
+    }
+
+    /// Drain all buffered interleaved stereo samples (left first) into a new vector.
+    pub fn drain_all<T: OutputSample>(&mut self) -> Vec<T> {
+        self.output.drain(..).map(T::from_f32).collect()
+    }
+
+    /// Discard all buffered input and output.
+    pub fn clear(&mut self) {
+        self.decimation_count = 0;
+        self.decimation_sum = (0, 0);
+        self.history_start += self.history.len() as u64;
+        self.history.clear();
+        self.output_position = self.history_start as f64;
+        self.output.clear();
+    }
+
+    fn generate_output(&mut self) {
+        let radius = self.kernel_radius as f64;
+        let history_end = self.history_start + self.history.len() as u64;
+
+        while self.output_position + radius < history_end as f64 {
+            let (left, right) = match &self.kernel {
+                None => self.interpolate_linear(),
+                Some(kernel) => self.interpolate_sinc(kernel)
+            };
+
+            if self.output.len() >= self.max_buffered_frames * 2 {
+                self.output.drain(..2);
+            }
+            self.output.push_back(left);
+            self.output.push_back(right);
+            self.output_position += self.output_step;
+        }
+
+        // Anything before the kernel's reach will never be needed again.
+        let first_needed = floor(self.output_position - radius).max(0.0) as u64;
+        while self.history_start < first_needed && !self.history.is_empty() {
+            self.history.pop_front();
+            self.history_start += 1;
+        }
+    }
+
+    fn history_at(&self, index: i64) -> (f32, f32) {
+        let relative = index - self.history_start as i64;
+        if relative < 0 {
+            return (0.0, 0.0)
+        }
+        self.history.get(relative as usize).copied().unwrap_or((0.0, 0.0))
+    }
+
+    fn interpolate_linear(&self) -> (f32, f32) {
+        let index = floor(self.output_position);
+        let fraction = (self.output_position - index) as f32;
+        let (l0, r0) = self.history_at(index as i64);
+        let (l1, r1) = self.history_at(index as i64 + 1);
+        (l0 + (l1 - l0) * fraction, r0 + (r1 - r0) * fraction)
+    }
+
+    fn interpolate_sinc(&self, kernel: &[f32]) -> (f32, f32) {
+        let radius = self.kernel_radius as i64;
+        let center = floor(self.output_position) as i64;
+        let fraction = self.output_position - center as f64;
+
+        let mut left = 0.0f32;
+        let mut right = 0.0f32;
+        for index in (center - radius + 1)..=(center + radius) {
+            // Distance from the output position, in table entries
+            let t = ((index - center) as f64 - fraction + radius as f64) * SINC_TABLE_RESOLUTION as f64;
+            let table_index = floor(t) as usize;
+            let Some(&a) = kernel.get(table_index) else { continue };
+            let b = kernel.get(table_index + 1).copied().unwrap_or(0.0);
+            let weight = a + (b - a) * (t - table_index as f64) as f32;
+
+            let (l, r) = self.history_at(index);
+            left += l * weight;
+            right += r * weight;
+        }
+        (left, right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Push `count` samples at the given level, from -1.0 to 1.0.
+    fn push_level(resampler: &mut AudioResampler, level: f32, count: u32) {
+        let value = (0x8000 as f32 + level * 32767.0) as u16;
+        for _ in 0..count {
+            resampler.push(AudioSample { left: value, right: 0x8000 });
+        }
+    }
+
+    #[test]
+    fn converts_to_the_output_rate() {
+        for quality in [ResamplerQuality::Linear, ResamplerQuality::WindowedSinc { zero_crossings: 8 }] {
+            let mut resampler = AudioResampler::new(APU_SAMPLE_RATE, 48000, quality, 48000);
+
+            // A tenth of a second.
+            push_level(&mut resampler, 0.5, APU_SAMPLE_RATE / 10);
+            let frames = resampler.available_frames();
+            assert!((4790..=4800).contains(&frames), "{quality:?}: {frames} frames");
+
+            // Once past the start, a constant level comes out unchanged.
+            let samples: Vec<f32> = resampler.drain_all();
+            assert_eq!(samples.len(), frames * 2);
+            for frame in samples[200..].chunks_exact(2) {
+                assert!((frame[0] - 0.5).abs() < 0.01, "{quality:?}: {}", frame[0]);
+                assert!(frame[1].abs() < 0.01, "{quality:?}: {}", frame[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn drain_writes_whole_frames() {
+        let mut resampler = AudioResampler::new(APU_SAMPLE_RATE, 48000, ResamplerQuality::Linear, 1024);
+        push_level(&mut resampler, -0.25, APU_SAMPLE_RATE / 100);
+        let available = resampler.available_frames();
+
+        let mut output = [0i16; 7];
+        assert_eq!(resampler.drain(&mut output), 6);
+        assert_eq!(resampler.available_frames(), available - 3);
+        assert_eq!(output[6], 0);
+    }
+
+    #[test]
+    fn overflow_discards_the_oldest_frames() {
+        let mut resampler = AudioResampler::new(APU_SAMPLE_RATE, 48000, ResamplerQuality::Linear, 16);
+        push_level(&mut resampler, 0.0, APU_SAMPLE_RATE / 100);
+        push_level(&mut resampler, 0.75, APU_SAMPLE_RATE / 100);
+        assert_eq!(resampler.available_frames(), 16);
+
+        let samples: Vec<f32> = resampler.drain_all();
+        assert!(samples.chunks_exact(2).all(|frame| (frame[0] - 0.75).abs() < 0.01));
+        assert_eq!(resampler.available_frames(), 0);
+    }
+
+    #[test]
+    fn buffering_one_frame_keeps_the_newest() {
+        let mut resampler = AudioResampler::new(APU_SAMPLE_RATE, 48000, ResamplerQuality::Linear, 1);
+        push_level(&mut resampler, 0.5, APU_SAMPLE_RATE / 100);
+        assert_eq!(resampler.available_frames(), 1);
+    }
+
+    #[test]
+    fn output_rates_above_the_input_rate_are_not_decimated() {
+        let mut resampler = AudioResampler::new(APU_SAMPLE_RATE, u32::MAX, ResamplerQuality::Linear, 4);
+        assert_eq!(resampler.decimation, 1);
+        push_level(&mut resampler, 0.5, 8);
+        assert_eq!(resampler.available_frames(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one frame must be buffered")]
+    fn buffering_no_frames_is_rejected() {
+        AudioResampler::new(APU_SAMPLE_RATE, 48000, ResamplerQuality::Linear, 0);
+    }
+}
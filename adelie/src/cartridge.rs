@@ -148,8 +148,16 @@ impl InstantMemory for alloc::boxed::Box<dyn DebugCartridge> {
     }
 }
 
+/// Logo at 0x104-0x133 that boot ROMs check for.
+const LOGO: [u8; 0x30] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E
+];
+
 /// Cartridge info from a ROM.
 #[must_use]
+#[derive(Debug)]
 pub struct CartridgeHeaderInfo {
     /// Mapper type to use.
     pub mapper_type: MapperType,
@@ -169,7 +177,46 @@ pub struct CartridgeHeaderInfo {
     /// The cartridge has rumble.
     pub has_rumble: bool,
 
-    /// Cartridge will boot in a retail console.
+    /// Title, zero-filled past its end.
+    ///
+    /// Older cartridges use all 16 bytes. Cartridges with a CGB flag only have 15, or 11 if they also
+    /// have a manufacturer code.
+    pub title: [u8; 16],
+
+    /// Four-character manufacturer code after the title, found on some later cartridges.
+    pub manufacturer_code: Option<[u8; 4]>,
+
+    /// Whether the cartridge supports CGB mode.
+    pub cgb_support: CGBSupport,
+
+    /// The cartridge supports SGB functions.
+    pub sgb_support: bool,
+
+    /// Company that published the cartridge.
+    pub licensee: Licensee,
+
+    /// Region the cartridge was sold in.
+    pub destination: Destination,
+
+    /// Version number of the game.
+    pub version: u8,
+
+    /// Header checksum at 0x14D.
+    pub header_checksum: u8,
+
+    /// The header checksum matches 0x134-0x14C.
+    pub header_checksum_valid: bool,
+
+    /// Global checksum at 0x14E-0x14F.
+    ///
+    /// This is not checked by any boot ROM. Use [`CartridgeHeaderInfo::global_checksum_matches`] to
+    /// check it against the ROM.
+    pub global_checksum: u16,
+
+    /// The logo at 0x104-0x133 matches the one boot ROMs check for.
+    pub valid_logo: bool,
+
+    /// Cartridge will boot in a retail console (both the logo and the header checksum are valid).
     pub bootable: bool
 }
 impl CartridgeHeaderInfo {
@@ -250,11 +297,8 @@ impl CartridgeHeaderInfo {
             n => return Err(CartridgeHeaderError::UnknownMapper(n))
         };
 
-        let writable_memory_size = if cartridge_type.mapper == MapperType::MBC2 {
-            256 // 512 half bytes
-        }
-        else if cartridge_type.mapper == MapperType::MBC7 {
-            256 // 256 bytes
+        let writable_memory_size = if cartridge_type.mapper == MapperType::MBC2 || cartridge_type.mapper == MapperType::MBC7 {
+            256 // MBC2 has 512 half bytes, MBC7 has 256 bytes
         }
         else if cartridge_type.has_ram {
             match header[0x49] {
@@ -275,18 +319,40 @@ impl CartridgeHeaderInfo {
             n => return Err(CartridgeHeaderError::UnknownROMSize(n))
         };
 
-        let valid_logo = header[0x4..=0x33] == [
-            0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
-            0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
-            0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E
-        ];
+        let valid_logo = header[0x4..=0x33] == LOGO;
 
         let mut checksum = 0u8;
         for i in &header[0x34..=0x4C] {
             checksum = checksum.wrapping_sub(*i).wrapping_sub(1);
         }
 
-        let checksum_matches = valid_logo && checksum == header[0x4D];
+        let header_checksum_valid = checksum == header[0x4D];
+
+        let cgb_support = match header[0x43] {
+            0xC0 => CGBSupport::Only,
+            n if n & 0x80 != 0 => CGBSupport::Supported,
+            _ => CGBSupport::None
+        };
+
+        let licensee = match header[0x4B] {
+            0x33 => Licensee::New([header[0x44], header[0x45]]),
+            n => Licensee::Old(n)
+        };
+
+        // There is no flag for the manufacturer code, so it is assumed to be there if the cartridge
+        // is new enough to use the new licensee code and the bytes look like one.
+        let code = [header[0x3F], header[0x40], header[0x41], header[0x42]];
+        let manufacturer_code = (cgb_support != CGBSupport::None
+            && matches!(licensee, Licensee::New(_))
+            && code.iter().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())).then_some(code);
+
+        let title_length = match (cgb_support, manufacturer_code) {
+            (CGBSupport::None, _) => 16,
+            (_, None) => 15,
+            (_, Some(_)) => 11
+        };
+        let mut title = [0u8; 16];
+        title[..title_length].copy_from_slice(&header[0x34..0x34 + title_length]);
 
         Ok(Self {
             mapper_type: cartridge_type.mapper,
@@ -295,9 +361,74 @@ impl CartridgeHeaderInfo {
             has_rumble: cartridge_type.has_rumble,
             has_save_data: cartridge_type.has_save_data,
             has_rtc: cartridge_type.has_rtc,
-            bootable: valid_logo && checksum_matches,
+            title,
+            manufacturer_code,
+            cgb_support,
+            sgb_support: header[0x46] == 0x03,
+            licensee,
+            destination: match header[0x4A] {
+                0x00 => Destination::Japan,
+                0x01 => Destination::Overseas,
+                n => Destination::Unknown(n)
+            },
+            version: header[0x4C],
+            header_checksum: header[0x4D],
+            header_checksum_valid,
+            global_checksum: u16::from_be_bytes([header[0x4E], header[0x4F]]),
+            valid_logo,
+            bootable: valid_logo && header_checksum_valid,
         })
     }
+
+    /// Get the title up to the first zero byte, or `None` if it is not valid UTF-8.
+    pub fn title_str(&self) -> Option<&str> {
+        let length = self.title.iter().position(|b| *b == 0).unwrap_or(self.title.len());
+        core::str::from_utf8(&self.title[..length]).ok()
+    }
+
+    /// Compute the global checksum of a ROM: the sum of every byte except the checksum itself.
+    pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter()
+            .enumerate()
+            .filter(|(address, _)| !(0x14E..=0x14F).contains(address))
+            .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16))
+    }
+
+    /// Return `true` if the global checksum in the header matches the ROM.
+    pub fn global_checksum_matches(&self, rom: &[u8]) -> bool {
+        Self::compute_global_checksum(rom) == self.global_checksum
+    }
+}
+
+/// CGB flag (0x143).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CGBSupport {
+    /// DMG only; runs in DMG compatibility mode on a CGB.
+    None,
+
+    /// Runs in CGB mode on a CGB, but also works on a DMG.
+    Supported,
+
+    /// Only works on a CGB.
+    Only
+}
+
+/// Company that published the cartridge.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Licensee {
+    /// Old licensee code (0x14B).
+    Old(u8),
+
+    /// New licensee code (0x144-0x145), used when the old licensee code is 0x33.
+    New([u8; 2])
+}
+
+/// Destination code (0x14A).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Destination {
+    Japan,
+    Overseas,
+    Unknown(u8)
 }
 
 #[derive(Default, PartialEq, Debug)]
@@ -408,3 +539,134 @@ impl<C: DebugCartridge> Cartridge for EmulatedCartridge<C> {
     }
     fn set_clk(&mut self, _high: bool) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a header with a valid logo and header checksum.
+    ///
+    /// `title` is written from 0x134 and may run into the CGB flag at 0x143, which is written after
+    /// it if given.
+    fn header(title: &[u8], cgb_flag: Option<u8>, new_licensee: Option<[u8; 2]>) -> [u8; 0x50] {
+        let mut header = [0u8; 0x50];
+        header[0x04..=0x33].copy_from_slice(&LOGO);
+        header[0x34..0x34 + title.len()].copy_from_slice(title);
+        if let Some(cgb_flag) = cgb_flag {
+            header[0x43] = cgb_flag;
+        }
+        match new_licensee {
+            Some(code) => {
+                header[0x44..=0x45].copy_from_slice(&code);
+                header[0x4B] = 0x33;
+            },
+            None => header[0x4B] = 0x01
+        }
+        header[0x4D] = header[0x34..=0x4C].iter().fold(0u8, |sum, b| sum.wrapping_sub(*b).wrapping_sub(1));
+        header
+    }
+
+    #[test]
+    fn title_layouts_are_detected() {
+        struct Case {
+            header: [u8; 0x50],
+            title: &'static str,
+            manufacturer_code: Option<[u8; 4]>,
+            cgb_support: CGBSupport,
+            licensee: Licensee
+        }
+
+        let cases = [
+            // Old cartridges use all 16 bytes for the title.
+            Case {
+                header: header(b"SIXTEEN BYTES AB", None, None),
+                title: "SIXTEEN BYTES AB",
+                manufacturer_code: None,
+                cgb_support: CGBSupport::None,
+                licensee: Licensee::Old(0x01)
+            },
+
+            // The CGB flag takes the last byte, and the old licensee code means no manufacturer code.
+            Case {
+                header: header(b"FIFTEEN BYTES A", Some(0x80), None),
+                title: "FIFTEEN BYTES A",
+                manufacturer_code: None,
+                cgb_support: CGBSupport::Supported,
+                licensee: Licensee::Old(0x01)
+            },
+
+            // A manufacturer code takes another four bytes.
+            Case {
+                header: header(b"ELEVEN BYTEAB3E", Some(0xC0), Some(*b"01")),
+                title: "ELEVEN BYTE",
+                manufacturer_code: Some(*b"AB3E"),
+                cgb_support: CGBSupport::Only,
+                licensee: Licensee::New(*b"01")
+            },
+
+            // Bytes that do not look like a manufacturer code are part of the title.
+            Case {
+                header: header(b"LOWERCASE  tail", Some(0x80), Some(*b"01")),
+                title: "LOWERCASE  tail",
+                manufacturer_code: None,
+                cgb_support: CGBSupport::Supported,
+                licensee: Licensee::New(*b"01")
+            },
+
+            // DMG cartridges never have a manufacturer code.
+            Case {
+                header: header(b"DMG TITLE  AB3EX", None, Some(*b"08")),
+                title: "DMG TITLE  AB3EX",
+                manufacturer_code: None,
+                cgb_support: CGBSupport::None,
+                licensee: Licensee::New(*b"08")
+            }
+        ];
+
+        for case in cases {
+            let info = CartridgeHeaderInfo::read_cartridge_header(&case.header).unwrap();
+            assert_eq!(info.title_str(), Some(case.title));
+            assert_eq!(info.manufacturer_code, case.manufacturer_code, "{}", case.title);
+            assert_eq!(info.cgb_support, case.cgb_support, "{}", case.title);
+            assert_eq!(info.licensee, case.licensee, "{}", case.title);
+            assert!(info.bootable, "{}", case.title);
+        }
+    }
+
+    #[test]
+    fn logo_and_header_checksum_are_checked_separately() {
+        let valid = header(b"CHECKSUMS", None, None);
+
+        let mut bad_logo = valid;
+        bad_logo[0x04] ^= 0xFF;
+        let info = CartridgeHeaderInfo::read_cartridge_header(&bad_logo).unwrap();
+        assert!(!info.valid_logo);
+        assert!(info.header_checksum_valid);
+        assert!(!info.bootable);
+
+        let mut bad_checksum = valid;
+        bad_checksum[0x4C] = 0x01;
+        let info = CartridgeHeaderInfo::read_cartridge_header(&bad_checksum).unwrap();
+        assert!(info.valid_logo);
+        assert!(!info.header_checksum_valid);
+        assert!(!info.bootable);
+    }
+
+    #[test]
+    fn global_checksum_skips_its_own_bytes() {
+        let mut rom = [0u8; 0x8000];
+        rom[0x100..0x150].copy_from_slice(&header(b"GLOBAL", None, None));
+        rom[0x7FFF] = 0x20;
+        let checksum = CartridgeHeaderInfo::compute_global_checksum(&rom);
+        assert_eq!(checksum, rom.iter().fold(0u16, |sum, b| sum.wrapping_add(*b as u16)));
+
+        rom[0x14E..=0x14F].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(CartridgeHeaderInfo::compute_global_checksum(&rom), checksum);
+        let info = CartridgeHeaderInfo::read_cartridge_header(rom[0x100..0x150].try_into().unwrap()).unwrap();
+        assert_eq!(info.global_checksum, checksum);
+        assert!(info.global_checksum_matches(&rom));
+
+        rom[0x150] = 0x01;
+        assert!(!info.global_checksum_matches(&rom));
+    }
+}
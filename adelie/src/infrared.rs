@@ -0,0 +1,22 @@
+//! Infrared port devices.
+
+pub mod link;
+
+/// Denotes a device facing the CGB's infrared port.
+///
+/// Infrared communication has no clock; software measures the time between light pulses, so the
+/// device is polled on every SoC clock (8 MiHz in double speed mode) to keep timing exact.
+pub trait InfraredDevice {
+    /// Exchange light with the device for one SoC clock.
+    ///
+    /// `emitting` is `true` if the Game Boy's LED is on. Return `true` if light is hitting the Game
+    /// Boy's sensor.
+    fn exchange_light(&mut self, emitting: bool) -> bool;
+}
+
+/// Nothing is facing the port, so no light is received.
+impl InfraredDevice for () {
+    fn exchange_light(&mut self, _emitting: bool) -> bool {
+        false
+    }
+}
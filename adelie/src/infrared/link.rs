@@ -0,0 +1,184 @@
+use crate::cartridge::Cartridge;
+use crate::infrared::InfraredDevice;
+use crate::instance::{Emulator, EmulatorCallbacks};
+
+/// One side of an [`InfraredLink`].
+///
+/// Pass this to [`Emulator::new`] as the infrared device for each instance being linked.
+#[derive(Copy, Clone, Default)]
+pub struct InfraredLinkPort {
+    /// Last known state of this Game Boy's LED.
+    emitting: bool,
+
+    /// State of the other Game Boy's LED.
+    remote_emitting: bool
+}
+
+impl InfraredDevice for InfraredLinkPort {
+    fn exchange_light(&mut self, emitting: bool) -> bool {
+        self.emitting = emitting;
+        self.remote_emitting
+    }
+}
+
+impl InfraredLinkPort {
+    /// Pass light between both sides.
+    fn connect(a: &mut InfraredLinkPort, b: &mut InfraredLinkPort) {
+        a.remote_emitting = b.emitting;
+        b.remote_emitting = a.emitting;
+    }
+}
+
+/// An instance using an [`InfraredLinkPort`].
+pub type InfraredEmulator<Cart, Callbacks> = Emulator<Cart, Callbacks, (), InfraredLinkPort>;
+
+/// Points the infrared ports of two instances in the same process at each other, running them in
+/// lockstep.
+///
+/// Both instances are ticked together in real time, so the pulse lengths each one measures are the
+/// same as on hardware.
+pub struct InfraredLink<
+    CartA: Cartridge,
+    CallbacksA: EmulatorCallbacks<CartA, (), InfraredLinkPort>,
+    CartB: Cartridge,
+    CallbacksB: EmulatorCallbacks<CartB, (), InfraredLinkPort>
+> {
+    a: InfraredEmulator<CartA, CallbacksA>,
+    b: InfraredEmulator<CartB, CallbacksB>
+}
+
+impl<
+    CartA: Cartridge,
+    CallbacksA: EmulatorCallbacks<CartA, (), InfraredLinkPort>,
+    CartB: Cartridge,
+    CallbacksB: EmulatorCallbacks<CartB, (), InfraredLinkPort>
+> InfraredLink<CartA, CallbacksA, CartB, CallbacksB> {
+    /// Link two instances.
+    pub fn new(a: InfraredEmulator<CartA, CallbacksA>, b: InfraredEmulator<CartB, CallbacksB>) -> Self {
+        Self { a, b }
+    }
+
+    /// Run one SoC clock cycle at base speed (4 MiHz) on both instances.
+    ///
+    /// An instance in double speed mode runs two SoC clock cycles so both stay in sync in real time.
+    pub fn tick(&mut self) {
+        Self::tick_instance(&mut self.a);
+        Self::tick_instance(&mut self.b);
+        InfraredLinkPort::connect(self.a.get_infrared_device_mut(), self.b.get_infrared_device_mut());
+    }
+
+    fn tick_instance<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart, (), InfraredLinkPort>>(emulator: &mut InfraredEmulator<Cart, Callbacks>) {
+        let cycles = if emulator.in_double_speed_mode() { 2 } else { 1 };
+        for _ in 0..cycles {
+            emulator.tick_soc(true);
+            emulator.tick_soc(false);
+        }
+    }
+
+    /// Access the first instance.
+    pub fn first(&self) -> &InfraredEmulator<CartA, CallbacksA> {
+        &self.a
+    }
+
+    /// Access the first instance.
+    pub fn first_mut(&mut self) -> &mut InfraredEmulator<CartA, CallbacksA> {
+        &mut self.a
+    }
+
+    /// Access the second instance.
+    pub fn second(&self) -> &InfraredEmulator<CartB, CallbacksB> {
+        &self.b
+    }
+
+    /// Access the second instance.
+    pub fn second_mut(&mut self) -> &mut InfraredEmulator<CartB, CallbacksB> {
+        &mut self.b
+    }
+
+    /// Separate both instances.
+    pub fn disconnect(self) -> (InfraredEmulator<CartA, CallbacksA>, InfraredEmulator<CartB, CallbacksB>) {
+        (self.a, self.b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::Model;
+    use crate::instance::tests::new_emulator;
+
+    const RP: u16 = 0xFF56;
+
+    /// SoC clocks the LED is on for each bit.
+    const PULSE: u32 = 64;
+
+    /// SoC clocks the LED is off after a 0 or 1 bit.
+    const GAPS: [u32; 2] = [64, 192];
+
+    #[test]
+    fn exchanges_a_byte_with_pulse_lengths() {
+        let a = new_emulator((), InfraredLinkPort::default(), Model::CGB);
+        let b = new_emulator((), InfraredLinkPort::default(), Model::CGB);
+        let mut link = InfraredLink::new(a, b);
+        link.second_mut().write(RP, 0xC0);
+
+        // The first instance sends 0xA7 most significant bit first, and the second measures how long
+        // the light is off after each pulse as a game would.
+        let sent = 0xA7u8;
+        let mut received = 0u8;
+        let mut bits = 0;
+        let mut dark_clocks = 0;
+        let mut was_lit = false;
+        let mut seen_light = false;
+        let mut measure = |link: &mut InfraredLink<_, _, _, _>| {
+            let lit = link.second_mut().read(RP) & 0b10 == 0;
+            if lit && !was_lit && seen_light {
+                received = (received << 1) | ((dark_clocks > 128) as u8);
+                bits += 1;
+            }
+            dark_clocks = if lit { 0 } else { dark_clocks + 1 };
+            was_lit = lit;
+            seen_light |= lit;
+        };
+
+        for bit in (0..8).rev().map(|i| (sent >> i) & 1) {
+            link.first_mut().write(RP, 0x01);
+            for _ in 0..PULSE {
+                link.tick();
+                measure(&mut link);
+            }
+            link.first_mut().write(RP, 0x00);
+            for _ in 0..GAPS[bit as usize] {
+                link.tick();
+                measure(&mut link);
+            }
+        }
+
+        // A final pulse ends the last gap.
+        link.first_mut().write(RP, 0x01);
+        for _ in 0..4 {
+            link.tick();
+            measure(&mut link);
+        }
+
+        assert_eq!(bits, 8);
+        assert_eq!(received, sent);
+    }
+
+    #[test]
+    fn light_is_not_sensed_unless_reading_is_enabled() {
+        let a = new_emulator((), InfraredLinkPort::default(), Model::CGB);
+        let b = new_emulator((), InfraredLinkPort::default(), Model::CGB);
+        let mut link = InfraredLink::new(a, b);
+        link.first_mut().write(RP, 0x01);
+        link.tick();
+        link.tick();
+        assert_eq!(link.second_mut().read(RP), 0x3E);
+        link.second_mut().write(RP, 0xC0);
+        assert_eq!(link.second_mut().read(RP), 0xFC);
+        link.first_mut().write(RP, 0x00);
+        link.tick();
+        link.tick();
+        assert_eq!(link.second_mut().read(RP), 0xFE);
+    }
+}
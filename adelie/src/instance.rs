@@ -1,35 +1,122 @@
 use crate::cartridge::Cartridge;
-use crate::instance::io::{IO, IORegisters};
+use crate::instance::io::{IO, IORegisters, LCDEvent};
 use crate::memory::{BootROM, BufferedInstantMemory, InstantMemory, Memory, NullMemory};
+use crate::serial::SerialDevice;
+use crate::infrared::InfraredDevice;
+use crate::instance::sgb::SuperGameBoy;
+use crate::util::boxed;
 
+pub(crate) mod apu;
+pub(crate) mod boot;
 pub(crate) mod io;
+pub(crate) mod sgb;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Model {
+    /// Early Game Boy revision with its own boot ROM and post-boot state.
+    DMG0,
     DMG,
-    CGB
+
+    /// Game Boy Pocket and Game Boy Light.
+    MGB,
+
+    /// Super Game Boy, running from the SNES master clock.
+    SGB,
+
+    /// Super Game Boy 2.
+    SGB2,
+
+    /// Early Game Boy Color revision.
+    CGB0,
+    CGB,
+
+    /// Last Game Boy Color revision. It uses the same boot ROM as [`Model::CGB`].
+    CGBE,
+
+    /// Game Boy Advance running in CGB mode.
+    AGB
 }
 impl Model {
+    /// Return `true` for monochrome models (including the Super Game Boy).
     pub const fn is_dmg(self) -> bool {
         match self {
-            Self::DMG => true,
-            Self::CGB => false
+            Self::DMG0 | Self::DMG | Self::MGB | Self::SGB | Self::SGB2 => true,
+            Self::CGB0 | Self::CGB | Self::CGBE | Self::AGB => false
         }
     }
+
+    /// Return `true` for models that support CGB mode.
     pub const fn is_cgb(self) -> bool {
+        !self.is_dmg()
+    }
+
+    /// Return `true` for Super Game Boy models.
+    pub const fn is_sgb(self) -> bool {
+        matches!(self, Self::SGB | Self::SGB2)
+    }
+
+    /// Get the model whose boot ROM this model runs.
+    pub const fn boot_rom_model(self) -> Model {
         match self {
-            Self::DMG => false,
-            Self::CGB => true
+            Self::CGBE => Self::CGB,
+            _ => self
+        }
+    }
+
+    /// Get the SoC clock speed in Hz when not in double speed mode.
+    ///
+    /// The SGB derives its clock from the SNES master clock, so it runs about 2.4% faster. The SGB2
+    /// has its own crystal.
+    pub const fn soc_clock_speed(self) -> u32 {
+        match self {
+            Self::SGB => SGB_SOC_CLOCK_SPEED,
+            _ => SOC_BASE_CLOCK_SPEED
         }
     }
 }
 
-#[derive(Copy, Clone)]
-pub struct Emulator<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart>> {
-    callbacks: Callbacks,
+/// How the console was started.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BootMode {
+    /// A boot ROM is run from power on.
+    BootROM,
+
+    /// The boot ROM is skipped and its post-boot state is set directly.
+    HLE
+}
+
+#[derive(Clone)]
+pub struct Emulator<
+    Cart: Cartridge,
+    Callbacks: EmulatorCallbacks<Cart, Serial, Infrared>,
+    Serial: SerialDevice = (),
+    Infrared: InfraredDevice = ()
+> {
+    /// Only `None` while a callback is being called.
+    callbacks: Option<Callbacks>,
     soc_clock_high: bool,
     soc_clock: u32,
-    io: IO<Cart>,
+    io: IO<Cart, Serial, Infrared>,
+    cpu_registers: CPURegisters,
+    boot_mode: BootMode,
+
+    /// CRC-32 of the cartridge header when the instance was created.
+    cartridge_header_hash: u32,
+
+    /// Palettes chosen by the CGB boot ROM for a DMG cartridge.
+    compatibility_palettes: Option<CompatibilityPalettes>,
+
+    /// Frames completed since power on.
+    frame_count: u64,
+
+    /// Frames completed without reading P1.
+    lag_frame_count: u64,
+
+    /// The last completed frame did not read P1.
+    last_frame_lagged: bool,
+
+    /// P1 read count at the start of the current frame.
+    frame_start_input_polls: u32,
 
     #[cfg(feature = "std")]
     clock: Clock,
@@ -40,37 +127,91 @@ pub struct Emulator<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart>> {
 const SOC_BASE_CLOCK_SPEED: u32 = 1024 * 1024 * 4;
 const SOC_BASE_CLOCK_SPEED_DOUBLE_SPEED: u32 = SOC_BASE_CLOCK_SPEED *2;
 
-impl<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart>> Emulator<Cart, Callbacks> {
+/// Width of the SGB output, with the Game Boy screen inset in the border.
+pub const SGB_SCREEN_WIDTH: usize = 256;
+
+/// Height of the SGB output, with the Game Boy screen inset in the border.
+pub const SGB_SCREEN_HEIGHT: usize = 224;
+
+/// Colors of each shade, from lightest to darkest, on models without color.
+const DMG_SHADES: [Color; 4] = [
+    Color { red: 0xFF, green: 0xFF, blue: 0xFF },
+    Color { red: 0xAA, green: 0xAA, blue: 0xAA },
+    Color { red: 0x55, green: 0x55, blue: 0x55 },
+    Color { red: 0x00, green: 0x00, blue: 0x00 }
+];
+
+/// SNES master clock (21.477 MHz) divided by 5.
+const SGB_SOC_CLOCK_SPEED: u32 = 4295454;
+
+impl<
+    Cart: Cartridge,
+    Callbacks: EmulatorCallbacks<Cart, Serial, Infrared>,
+    Serial: SerialDevice,
+    Infrared: InfraredDevice
+> Emulator<Cart, Callbacks, Serial, Infrared> {
     pub fn new(
         callbacks: Callbacks,
         cartridge: Cart,
+        serial_device: Serial,
+        infrared_device: Infrared,
         boot_rom: BootROM,
         model: Model
     ) -> Self {
-        Self {
-            callbacks,
+        let mut emulator = Self {
+            callbacks: Some(callbacks),
             soc_clock_high: false,
             soc_clock: 0,
             io: IO {
                 double_speed_mode: false,
                 cartridge,
+                serial_device,
+                infrared_device,
                 boot_rom: BufferedInstantMemory::new(boot_rom),
                 video_ram: Default::default(),
                 work_ram: Default::default(),
                 oam: Default::default(),
                 high_ram: Default::default(),
-                no_access: NullMemory::default(),
+                no_access: NullMemory,
                 model,
-                registers: IORegisters::default(),
+                registers: IORegisters::new(model),
+                sgb: model.is_sgb().then(|| boxed(SuperGameBoy::default())),
             },
+            cpu_registers: CPURegisters::default(),
+            boot_mode: BootMode::BootROM,
+            cartridge_header_hash: 0,
+            compatibility_palettes: None,
+            frame_count: 0,
+            lag_frame_count: 0,
+            last_frame_lagged: false,
+            frame_start_input_polls: 0,
+            #[cfg(feature = "std")]
             clock: Clock::new(),
+            #[cfg(feature = "std")]
             last_clock_count: 0
-        }
+        };
+        emulator.cartridge_header_hash = crate::util::crc32(&boot::read_header(&mut emulator.io));
+        emulator
+    }
+
+    /// Create an instance without a boot ROM, starting in the state the boot ROM for `model` leaves
+    /// the console in when handing off to the cartridge.
+    pub fn new_hle(
+        callbacks: Callbacks,
+        cartridge: Cart,
+        serial_device: Serial,
+        infrared_device: Infrared,
+        model: Model
+    ) -> Self {
+        let mut emulator = Self::new(callbacks, cartridge, serial_device, infrared_device, BootROM::default(), model);
+        (emulator.cpu_registers, emulator.compatibility_palettes) = boot::apply_post_boot_state(&mut emulator.io);
+        emulator.boot_mode = BootMode::HLE;
+        emulator
     }
 
     /// Destroy the instance to get the callbacks object back.
     pub fn into_callbacks_object(self) -> Callbacks {
-        self.callbacks
+        self.callbacks.expect("callbacks are only taken while being called")
     }
 
     /// Run half of one SoC clock cycle.
@@ -85,7 +226,71 @@ impl<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart>> Emulator<Cart, Callbac
             return;
         }
         self.soc_clock_high = high;
-        todo!("tick_soc")
+
+        // Everything is clocked on the rising edge.
+        if !high {
+            return;
+        }
+        self.soc_clock = self.soc_clock.wrapping_add(1);
+        self.io.tick_div();
+        self.io.tick_serial();
+        self.io.tick_joypad();
+        self.io.tick_infrared();
+        self.io.tick_sgb();
+
+        // The LCD and APU keep their speed in double speed mode, so they skip every other clock.
+        let base_clock = if self.in_double_speed_mode() {
+            if self.soc_clock % 2 != 0 {
+                return;
+            }
+            self.soc_clock / 2
+        }
+        else {
+            self.soc_clock
+        };
+
+        // The APU runs at 2 MiHz.
+        if base_clock % 2 == 0 {
+            let samples = self.io.registers.audio.memory.tick();
+            self.call_callbacks(|callbacks, emulator| callbacks.on_sample(emulator, &samples));
+        }
+
+        match self.io.tick_lcd() {
+            LCDEvent::VBlank => {
+                self.end_frame();
+                self.call_callbacks(|callbacks, emulator| callbacks.on_vblank(emulator));
+            },
+            LCDEvent::FrameEnd => self.end_frame(),
+            LCDEvent::Dot { x, y } => self.output_dot(x, y),
+            LCDEvent::None => ()
+        }
+    }
+
+    /// Output a dot of the screen, colorizing it on the SGB.
+    fn output_dot(&mut self, x: u8, y: u8) {
+        let shade = self.io.background_shade(x, y);
+        let dot = match self.io.sgb.as_mut() {
+            Some(sgb) => sgb.output_dot(x, y, shade),
+            None => DMG_SHADES[shade as usize]
+        };
+        self.call_callbacks(|callbacks, emulator| callbacks.on_dot(emulator, dot));
+    }
+
+    /// Call a callback, giving it access to the instance.
+    fn call_callbacks(&mut self, callback: impl FnOnce(&mut Callbacks, &Self)) {
+        let mut callbacks = self.callbacks.take().expect("callbacks cannot be called recursively");
+        callback(&mut callbacks, self);
+        self.callbacks = Some(callbacks);
+    }
+
+    /// Read a byte through the bus, as the CPU would.
+    pub(crate) fn read(&mut self, address: u16) -> u8 {
+        self.io.read(address)
+    }
+
+    /// Write a byte through the bus, as the CPU would.
+    pub(crate) fn write(&mut self, address: u16, data: u8) {
+        self.io.write(address, data)
     }
 
     /// Run the SoC timed.
@@ -152,7 +357,7 @@ impl<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart>> Emulator<Cart, Callbac
             SOC_BASE_CLOCK_SPEED_DOUBLE_SPEED
         }
         else {
-            SOC_BASE_CLOCK_SPEED
+            self.io.model.soc_clock_speed()
         }
     }
 
@@ -180,11 +385,229 @@ impl<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart>> Emulator<Cart, Callbac
         &mut self.io.cartridge
     }
 
+    /// Access the device connected to the serial port.
+    pub fn get_serial_device(&self) -> &Serial {
+        &self.io.serial_device
+    }
+
+    /// Access the device connected to the serial port.
+    pub fn get_serial_device_mut(&mut self) -> &mut Serial {
+        &mut self.io.serial_device
+    }
+
+    /// Access the device facing the infrared port.
+    pub fn get_infrared_device(&self) -> &Infrared {
+        &self.io.infrared_device
+    }
+
+    /// Access the device facing the infrared port.
+    pub fn get_infrared_device_mut(&mut self) -> &mut Infrared {
+        &mut self.io.infrared_device
+    }
+
+    /// Set the buttons currently held.
+    ///
+    /// Pressing a button on a selected line requests the joypad interrupt and wakes the CPU from
+    /// HALT or STOP on the next SoC clock.
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.io.registers.joypad_data.memory.set_buttons(buttons);
+    }
+
+    /// Get the buttons currently held.
+    pub fn get_buttons(&self) -> Buttons {
+        self.io.registers.joypad_data.memory.buttons
+    }
+
+    /// Set the buttons held on each of the four SGB joypads.
+    ///
+    /// The first joypad is the one [`Emulator::set_buttons`] sets. The others are only read after the
+    /// game requests them with MLT_REQ.
+    pub fn set_sgb_buttons(&mut self, buttons: [Buttons; 4]) {
+        self.io.registers.joypad_data.memory.set_sgb_buttons(buttons);
+    }
+
+    /// Get the buttons held on each of the four SGB joypads.
+    pub fn get_sgb_buttons(&self) -> [Buttons; 4] {
+        self.io.registers.joypad_data.memory.sgb_buttons()
+    }
+
+    /// Get the number of joypads the game requested with MLT_REQ (1, 2 or 4).
+    pub fn get_sgb_player_count(&self) -> u8 {
+        self.io.registers.joypad_data.memory.sgb_players()
+    }
+
+    /// Compute a CRC-32 of the internal memory (WRAM, VRAM, OAM and HRAM), the CPU registers and
+    /// the SoC clock count.
+    ///
+    /// This is not a full snapshot of the state, but is enough to catch most desyncs when replaying
+    /// input, including inputs applied on a different clock.
+    pub fn state_hash(&self) -> u32 {
+        let memories = [
+            self.io.work_ram.memory.get_memory(),
+            self.io.video_ram.memory.get_memory(),
+            self.io.oam.memory.get_memory(),
+            self.io.high_ram.memory.get_memory()
+        ];
+        let registers = &self.cpu_registers;
+        let [sp_high, sp_low] = registers.sp.to_be_bytes();
+        let [pc_high, pc_low] = registers.pc.to_be_bytes();
+        let registers = [registers.a, registers.f, registers.b, registers.c, registers.d, registers.e, registers.h, registers.l, sp_high, sp_low, pc_high, pc_low];
+        let hash = memories
+            .into_iter()
+            .flatten()
+            .fold(0, crate::util::crc32_update);
+        let hash = crate::util::crc32_update(hash, &registers);
+        crate::util::crc32_update(hash, &self.soc_clock.to_le_bytes())
+    }
+
+    /// Run until the game reads P1, stopping right after the SoC clock that read it.
+    ///
+    /// Returns `false` if `max_frames` frames ended without P1 being read.
+    pub fn advance_to_input_poll(&mut self, max_frames: u32) -> bool {
+        let input_polls = self.io.registers.joypad_data.memory.input_polls();
+        let last_frame = self.frame_count + max_frames as u64;
+        loop {
+            self.tick_soc(!self.soc_clock_high);
+            if self.io.registers.joypad_data.memory.input_polls() != input_polls {
+                return true
+            }
+            if self.frame_count >= last_frame {
+                return false
+            }
+        }
+    }
+
+    /// Get the number of frames completed since power on.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Get the number of frames completed without the game reading P1 (lag frames).
+    pub fn lag_frame_count(&self) -> u64 {
+        self.lag_frame_count
+    }
+
+    /// Reset the lag frame count to 0.
+    pub fn reset_lag_frame_count(&mut self) {
+        self.lag_frame_count = 0
+    }
+
+    /// Return `true` if the game did not read P1 during the last completed frame.
+    pub fn last_frame_lagged(&self) -> bool {
+        self.last_frame_lagged
+    }
+
+    /// Mark the end of a frame upon entering vblank (or when it would have, with the LCD off),
+    /// updating the lag frame count.
+    pub(crate) fn end_frame(&mut self) {
+        let input_polls = self.io.registers.joypad_data.memory.input_polls();
+        self.last_frame_lagged = input_polls == self.frame_start_input_polls;
+        self.frame_start_input_polls = input_polls;
+        self.frame_count += 1;
+        if self.last_frame_lagged {
+            self.lag_frame_count += 1;
+        }
+        self.io.end_sgb_frame();
+    }
+
+    /// Get the CPU registers.
+    pub fn get_cpu_registers(&self) -> &CPURegisters {
+        &self.cpu_registers
+    }
+
+    /// Get the palettes the CGB boot ROM chose for a DMG cartridge, if any.
+    pub fn get_compatibility_palettes(&self) -> Option<&CompatibilityPalettes> {
+        self.compatibility_palettes.as_ref()
+    }
+
+    /// Render the SGB border without the Game Boy screen.
+    ///
+    /// Transparent areas, including where the Game Boy screen goes, are filled with color 0. Returns
+    /// `false` if this is not an SGB.
+    pub fn render_sgb_border(&self, image: &mut [Color; SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT]) -> bool {
+        let Some(sgb) = self.io.sgb.as_ref() else {
+            return false
+        };
+        sgb.render_border(image);
+        true
+    }
+
+    /// Render the last frame as the SNES shows it, with the Game Boy screen inset in the border.
+    ///
+    /// Returns `false` if this is not an SGB.
+    pub fn render_sgb_screen(&self, image: &mut [Color; SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT]) -> bool {
+        let Some(sgb) = self.io.sgb.as_ref() else {
+            return false
+        };
+        sgb.render_screen(image);
+        true
+    }
+
     /// Get the current emulated model of this instance.
     pub fn get_model(&self) -> Model {
         self.io.model
     }
 
+    /// Get whether this instance was started with a boot ROM or with [`Emulator::new_hle`].
+    pub fn get_boot_mode(&self) -> BootMode {
+        self.boot_mode
+    }
+
+    /// Get a CRC-32 of the cartridge header (0x0100-0x014F) read when the instance was created.
+    ///
+    /// The header includes the global checksum, so this identifies the ROM without needing access to
+    /// all of its data.
+    pub fn cartridge_header_hash(&self) -> u32 {
+        self.cartridge_header_hash
+    }
+
+    /// Mute or unmute an audio channel in the mixed output.
+    ///
+    /// Individual channel samples in [`APUSamples`] are unaffected.
+    pub fn set_audio_channel_muted(&mut self, channel: AudioChannel, muted: bool) {
+        self.io.registers.audio.memory.set_channel_muted(channel, muted)
+    }
+
+    /// Get whether or not an audio channel is muted.
+    pub fn is_audio_channel_muted(&self, channel: AudioChannel) -> bool {
+        self.io.registers.audio.memory.is_channel_muted(channel)
+    }
+
+    /// Make only the given audio channel heard in the mixed output, or `None` to hear all
+    /// unmuted channels.
+    ///
+    /// A soloed channel is heard even if it is muted. Individual channel samples in [`APUSamples`]
+    /// are unaffected.
+    pub fn set_audio_channel_solo(&mut self, channel: Option<AudioChannel>) {
+        self.io.registers.audio.memory.set_solo_channel(channel)
+    }
+
+    /// Get the soloed audio channel, if any.
+    pub fn get_audio_channel_solo(&self) -> Option<AudioChannel> {
+        self.io.registers.audio.memory.solo_channel()
+    }
+
+    /// Enable recording each channel's waveform for oscilloscope views, or `None` to disable it.
+    ///
+    /// One point is recorded every `decimation` APU samples (2 MiHz), and the last
+    /// [`OSCILLOSCOPE_LENGTH`] points are kept. Enabling the oscilloscope clears its history.
+    pub fn set_oscilloscope(&mut self, decimation: Option<u32>) {
+        self.io.registers.audio.memory.set_oscilloscope(decimation)
+    }
+
+    /// Copy the waveform history of a channel, oldest first, as DAC output levels from -15 to 15.
+    ///
+    /// Returns `false` if the oscilloscope is not enabled.
+    pub fn read_oscilloscope(&self, channel: AudioChannel, output: &mut [i8; OSCILLOSCOPE_LENGTH]) -> bool {
+        match self.io.registers.audio.memory.oscilloscope() {
+            Some(oscilloscope) => {
+                oscilloscope.copy_history(channel, output);
+                true
+            },
+            None => false
+        }
+    }
+
     #[cfg(feature = "std")]
     fn tick_soc_if_ready(&mut self, clock_speed: u32) -> bool {
         let total_clocks = self.clock.total_clocks(clock_speed);
@@ -205,38 +628,167 @@ pub struct Color {
     pub blue: u8
 }
 
+/// SM83 CPU registers.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct CPURegisters {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16
+}
+
+/// Palettes used to colorize a DMG cartridge on CGB models, as RGB555.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct CompatibilityPalettes {
+    pub background: [u16; 4],
+    pub object0: [u16; 4],
+    pub object1: [u16; 4]
+}
+
+/// Set of buttons held on the joypad.
+///
+/// The lower nibble holds the buttons and the upper nibble holds the d-pad, each in the same order
+/// as the P10-P13 lines they pull low.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Buttons(u8);
+
+impl Buttons {
+    pub const NONE: Buttons = Buttons(0);
+    pub const A: Buttons = Buttons(0b0000_0001);
+    pub const B: Buttons = Buttons(0b0000_0010);
+    pub const SELECT: Buttons = Buttons(0b0000_0100);
+    pub const START: Buttons = Buttons(0b0000_1000);
+    pub const RIGHT: Buttons = Buttons(0b0001_0000);
+    pub const LEFT: Buttons = Buttons(0b0010_0000);
+    pub const UP: Buttons = Buttons(0b0100_0000);
+    pub const DOWN: Buttons = Buttons(0b1000_0000);
+    pub const ALL: Buttons = Buttons(0xFF);
+
+    /// Get the raw bits.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Create a set from raw bits.
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    /// Return `true` if no buttons are held.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Return `true` if all of `other` are held.
+    pub const fn contains(self, other: Buttons) -> bool {
+        (self.0 & other.0) == other.0
+    }
+
+    /// Hold `other`.
+    pub fn insert(&mut self, other: Buttons) {
+        self.0 |= other.0
+    }
+
+    /// Release `other`.
+    pub fn remove(&mut self, other: Buttons) {
+        self.0 &= !other.0
+    }
+
+    /// Hold or release `other`.
+    pub fn set(&mut self, other: Buttons, held: bool) {
+        if held {
+            self.insert(other)
+        }
+        else {
+            self.remove(other)
+        }
+    }
+}
+
+impl core::ops::BitOr for Buttons {
+    type Output = Buttons;
+    fn bitor(self, rhs: Buttons) -> Buttons {
+        Buttons(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for Buttons {
+    fn bitor_assign(&mut self, rhs: Buttons) {
+        self.0 |= rhs.0
+    }
+}
+
+impl core::ops::BitAnd for Buttons {
+    type Output = Buttons;
+    fn bitand(self, rhs: Buttons) -> Buttons {
+        Buttons(self.0 & rhs.0)
+    }
+}
+
+impl core::ops::BitAndAssign for Buttons {
+    fn bitand_assign(&mut self, rhs: Buttons) {
+        self.0 &= rhs.0
+    }
+}
+
+impl core::ops::Not for Buttons {
+    type Output = Buttons;
+    fn not(self) -> Buttons {
+        Buttons(!self.0)
+    }
+}
+
 /// Callbacks that get called when certain events in the emulator occur.
 ///
-/// By default, each callback is a no-op.
-pub trait EmulatorCallbacks<Cart: Cartridge>: Sized {
+/// By default, each callback is a no-op. The object is moved out of the instance for each call, so
+/// large buffers should be kept on the heap rather than in the object itself.
+pub trait EmulatorCallbacks<Cart: Cartridge, Serial: SerialDevice = (), Infrared: InfraredDevice = ()>: Sized {
     /// Called upon generating an audio sample, giving you the combined samples for each audio channel
     /// as well as each individual audio channel.
     ///
     /// This will be called at 2 MiHz.
     fn on_sample(
         &mut self,
-        emulator: &Emulator<Cart, Self>,
+        emulator: &Emulator<Cart, Self, Serial, Infrared>,
         sample: &APUSamples
     ) {}
 
     /// Called upon entering vblank.
     fn on_vblank(
         &mut self,
-        emulator: &Emulator<Cart, Self>
+        emulator: &Emulator<Cart, Self, Serial, Infrared>
     ) {}
 
-    /// Called upon generating a pixel.
+    /// Called upon generating a pixel, from left to right and top to bottom.
+    ///
+    /// On the SGB, the pixel is colorized with the palettes and mask set by the game.
+    ///
+    /// Only the background is drawn so far, in grayscale through BGP. The window and objects are
+    /// not drawn at all, so they are missing from the output (and from SGB colorization) until the
+    /// PPU renders them.
     fn on_dot(
         &mut self,
-        emulator: &Emulator<Cart, Self>,
+        emulator: &Emulator<Cart, Self, Serial, Infrared>,
         dot: Color
     ) {}
 }
 
 /// No-op implementation if no callbacks are desired.
-impl<Cart: Cartridge> EmulatorCallbacks<Cart> for () {}
+impl<Cart: Cartridge, Serial: SerialDevice, Infrared: InfraredDevice> EmulatorCallbacks<Cart, Serial, Infrared> for () {}
 
 /// Defines an audio sample on left/right channels.
+///
+/// Samples are unsigned and centered on `0x8000`, which is silence. Values below `0x8000`
+/// correspond to a negative voltage and values above correspond to a positive voltage.
+///
+/// Samples are generated with integer math only, so the same input always produces the same
+/// samples on any platform or build.
 #[derive(Copy, Clone, PartialEq, Default, Debug)]
 pub struct AudioSample {
     pub left: u16,
@@ -246,13 +798,36 @@ pub struct AudioSample {
 /// Defines an audio sample for all channels.
 #[derive(Copy, Clone, PartialEq, Default, Debug)]
 pub struct APUSamples {
+    /// All channels panned with NR51, scaled with the NR50 master volume, and passed through the
+    /// high-pass filter.
+    ///
+    /// The full range of -480 to 480 (all four DACs at maximum with maximum volume) maps to
+    /// `0x8000 ± 0x7F80`. Since the high-pass filter can overshoot, this is clamped.
     pub mixed: AudioSample,
+
+    /// Individual channel outputs are panned with NR51, but are not affected by the master volume
+    /// or the high-pass filter.
+    ///
+    /// The DAC range of -15 to 15 maps to `0x8000 ± 0x7F80`. A channel not panned to a side, or
+    /// whose DAC is disabled, is `0x8000` on that side.
     pub wave1: AudioSample,
     pub wave2: AudioSample,
     pub sample: AudioSample,
     pub noise: AudioSample,
 }
 
+/// Refers to an individual APU channel, named after the corresponding field in [`APUSamples`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AudioChannel {
+    Wave1,
+    Wave2,
+    Sample,
+    Noise
+}
+
+/// Number of points kept for each channel by the oscilloscope.
+pub const OSCILLOSCOPE_LENGTH: usize = 1024;
+
 pub enum InstantMemoryType {
     WRAM,
     VRAM,
@@ -291,3 +866,235 @@ impl Clock {
         (time_since_start * speed / 1000000000) as u64
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    extern crate std;
+    use super::*;
+    use crate::cartridge::{EmulatedCartridge, NullCartridge};
+
+    pub(crate) type TestEmulator<Serial = (), Infrared = ()> = Emulator<EmulatedCartridge<NullCartridge>, (), Serial, Infrared>;
+
+    /// Create an instance with no cartridge and no boot ROM.
+    pub(crate) fn new_emulator<Serial: SerialDevice, Infrared: InfraredDevice>(serial: Serial, infrared: Infrared, model: Model) -> TestEmulator<Serial, Infrared> {
+        Emulator::new((), EmulatedCartridge::new(NullCartridge), serial, infrared, BootROM::default(), model)
+    }
+
+    pub(crate) const SCREEN_WIDTH: usize = 160;
+    pub(crate) const SCREEN_HEIGHT: usize = 144;
+
+    /// Keeps the last dot output at each position of the screen.
+    pub(crate) struct ScreenRecorder {
+        pub(crate) screen: std::vec::Vec<Color>,
+        next_dot: usize,
+        pub(crate) vblanks: u32
+    }
+
+    impl<Cart: Cartridge, Serial: SerialDevice, Infrared: InfraredDevice> EmulatorCallbacks<Cart, Serial, Infrared> for ScreenRecorder {
+        fn on_vblank(&mut self, _emulator: &Emulator<Cart, Self, Serial, Infrared>) {
+            self.vblanks += 1;
+        }
+
+        fn on_dot(&mut self, _emulator: &Emulator<Cart, Self, Serial, Infrared>, dot: Color) {
+            self.screen[self.next_dot] = dot;
+            self.next_dot = (self.next_dot + 1) % self.screen.len();
+        }
+    }
+
+    /// Create an instance with no cartridge and no boot ROM that records its screen.
+    pub(crate) fn new_recording_emulator(model: Model) -> Emulator<EmulatedCartridge<NullCartridge>, ScreenRecorder> {
+        let recorder = ScreenRecorder { screen: std::vec![Color::default(); SCREEN_WIDTH * SCREEN_HEIGHT], next_dot: 0, vblanks: 0 };
+        Emulator::new(recorder, EmulatedCartridge::new(NullCartridge), (), (), BootROM::default(), model)
+    }
+
+    /// Fill the background with tile 0, drawn with a single color.
+    pub(crate) fn fill_background<Callbacks: EmulatorCallbacks<EmulatedCartridge<NullCartridge>>>(
+        emulator: &mut Emulator<EmulatedCartridge<NullCartridge>, Callbacks>,
+        color: u8
+    ) {
+        let vram = emulator.get_internal_memory_mut(InstantMemoryType::VRAM).get_memory_mut().unwrap();
+        let planes = [if color & 1 != 0 { 0xFF } else { 0x00 }, if color & 2 != 0 { 0xFF } else { 0x00 }];
+        for row in vram[..16].chunks_exact_mut(2) {
+            row.copy_from_slice(&planes);
+        }
+        vram[0x1800..0x1C00].fill(0);
+    }
+
+    /// Run a test on a thread with enough stack for several instances, since their large state is
+    /// only boxed with the `alloc` feature.
+    pub(crate) fn with_large_stack(test: impl FnOnce() + Send + 'static) {
+        std::thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(test)
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    /// Run whole SoC clock cycles.
+    pub(crate) fn run<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart, Serial, Infrared>, Serial: SerialDevice, Infrared: InfraredDevice>(
+        emulator: &mut Emulator<Cart, Callbacks, Serial, Infrared>,
+        clocks: u32
+    ) {
+        for _ in 0..clocks {
+            emulator.tick_soc(true);
+            emulator.tick_soc(false);
+        }
+    }
+
+    const P1: u16 = 0xFF00;
+    const IF: u16 = 0xFF0F;
+    const JOYPAD_INTERRUPT: u8 = 0b10000;
+
+    fn take_joypad_interrupt(emulator: &mut TestEmulator) -> bool {
+        let requested = emulator.read(IF);
+        emulator.write(IF, 0);
+        requested & JOYPAD_INTERRUPT != 0
+    }
+
+    #[test]
+    fn pressing_a_selected_button_requests_the_joypad_interrupt() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        emulator.write(P1, 0x10); // buttons
+
+        emulator.set_buttons(Buttons::A);
+        assert!(!take_joypad_interrupt(&mut emulator));
+        run(&mut emulator, 1);
+        assert!(take_joypad_interrupt(&mut emulator));
+        assert_eq!(emulator.read(P1) & 0xF, 0xE);
+
+        // Holding it or pressing a d-pad button on the unselected line does nothing.
+        emulator.set_buttons(Buttons::A | Buttons::UP);
+        run(&mut emulator, 1);
+        assert!(!take_joypad_interrupt(&mut emulator));
+
+        // Releasing does nothing either, but pressing another one does.
+        emulator.set_buttons(Buttons::UP);
+        run(&mut emulator, 1);
+        assert!(!take_joypad_interrupt(&mut emulator));
+        emulator.set_buttons(Buttons::UP | Buttons::START);
+        run(&mut emulator, 1);
+        assert!(take_joypad_interrupt(&mut emulator));
+    }
+
+    #[test]
+    fn selecting_a_line_with_a_held_button_requests_the_joypad_interrupt() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        emulator.write(P1, 0x30);
+        emulator.set_buttons(Buttons::DOWN);
+        run(&mut emulator, 1);
+        assert!(!take_joypad_interrupt(&mut emulator));
+
+        emulator.write(P1, 0x20); // d-pad
+        run(&mut emulator, 1);
+        assert!(take_joypad_interrupt(&mut emulator));
+        assert_eq!(emulator.read(P1), 0xE7);
+    }
+
+    const LCDC: u16 = 0xFF40;
+    const LY: u16 = 0xFF44;
+    const VBLANK_INTERRUPT: u8 = 0b00001;
+
+    /// SoC clocks from turning the LCD on until vblank.
+    const CLOCKS_TO_VBLANK: u32 = 144 * 456;
+    pub(crate) const CLOCKS_PER_FRAME: u32 = 154 * 456;
+
+    #[test]
+    fn vblank_ends_the_frame() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        emulator.write(LCDC, 0x91);
+
+        run(&mut emulator, CLOCKS_TO_VBLANK);
+        assert_eq!(emulator.frame_count(), 0);
+        assert_eq!(emulator.read(IF) & VBLANK_INTERRUPT, 0);
+        run(&mut emulator, 1);
+        assert_eq!(emulator.frame_count(), 1);
+        assert_eq!(emulator.read(LY), 144);
+        assert_eq!(emulator.read(IF) & VBLANK_INTERRUPT, VBLANK_INTERRUPT);
+
+        run(&mut emulator, CLOCKS_PER_FRAME);
+        assert_eq!(emulator.frame_count(), 2);
+    }
+
+    #[test]
+    fn frames_are_still_counted_with_the_lcd_off() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        run(&mut emulator, CLOCKS_TO_VBLANK + 1);
+        assert_eq!(emulator.frame_count(), 1);
+        assert_eq!(emulator.read(IF) & VBLANK_INTERRUPT, 0);
+    }
+
+    #[test]
+    fn frame_without_p1_read_counts_as_lag() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        emulator.write(LCDC, 0x91);
+
+        run(&mut emulator, CLOCKS_TO_VBLANK + 1);
+        assert!(emulator.last_frame_lagged());
+        assert_eq!(emulator.lag_frame_count(), 1);
+
+        emulator.write(P1, 0x20);
+        emulator.read(P1);
+        run(&mut emulator, CLOCKS_PER_FRAME);
+        assert!(!emulator.last_frame_lagged());
+        assert_eq!(emulator.lag_frame_count(), 1);
+
+        run(&mut emulator, CLOCKS_PER_FRAME);
+        assert!(emulator.last_frame_lagged());
+        assert_eq!(emulator.lag_frame_count(), 2);
+        assert_eq!(emulator.frame_count(), 3);
+
+        emulator.reset_lag_frame_count();
+        assert_eq!(emulator.lag_frame_count(), 0);
+    }
+
+    #[test]
+    fn advance_to_input_poll_gives_up_after_max_frames() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        emulator.write(LCDC, 0x91);
+        assert!(!emulator.advance_to_input_poll(2));
+        assert_eq!(emulator.frame_count(), 2);
+        assert_eq!(emulator.lag_frame_count(), 2);
+    }
+
+    const BGP: u16 = 0xFF47;
+
+    #[test]
+    fn dots_show_the_background_through_bgp() {
+        let mut emulator = new_recording_emulator(Model::DMG);
+        fill_background(&mut emulator, 3);
+
+        // Put color 1 in the top left corner of tile 1, shown at (8, 0).
+        let vram = emulator.get_internal_memory_mut(InstantMemoryType::VRAM).get_memory_mut().unwrap();
+        vram[0x10] = 0x80;
+        vram[0x1801] = 1;
+
+        emulator.write(BGP, 0b00_01_10_11);
+        emulator.write(LCDC, 0x91);
+        run(&mut emulator, CLOCKS_PER_FRAME);
+
+        let recorder = emulator.callbacks.as_ref().unwrap();
+        assert_eq!(recorder.vblanks, 1);
+        assert_eq!(recorder.screen[0], DMG_SHADES[0]);
+        assert_eq!(recorder.screen[8], DMG_SHADES[2]);
+        assert_eq!(recorder.screen[9], DMG_SHADES[3]);
+        assert_eq!(recorder.screen[SCREEN_WIDTH * SCREEN_HEIGHT - 1], DMG_SHADES[0]);
+
+        // Disabling the background (LCDC bit 0) draws color 0.
+        emulator.write(LCDC, 0x90);
+        run(&mut emulator, CLOCKS_PER_FRAME);
+        let recorder = emulator.callbacks.as_ref().unwrap();
+        assert_eq!(recorder.screen[8], DMG_SHADES[3]);
+    }
+
+    #[test]
+    fn cgbe_boots_like_the_cgb() {
+        let mut cgb = TestEmulator::new_hle((), EmulatedCartridge::new(NullCartridge), (), (), Model::CGB);
+        let mut cgbe = TestEmulator::new_hle((), EmulatedCartridge::new(NullCartridge), (), (), Model::CGBE);
+        assert!(Model::CGBE.is_cgb());
+        assert_eq!(cgbe.get_cpu_registers(), cgb.get_cpu_registers());
+        for address in 0xFF00..=0xFF7F {
+            assert_eq!(cgbe.read(address), cgb.read(address), "{address:04X}");
+        }
+    }
+}
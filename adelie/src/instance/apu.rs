@@ -0,0 +1,1357 @@
+//! Audio processing unit.
+
+use crate::instance::{APUSamples, AudioChannel, AudioSample, Model, OSCILLOSCOPE_LENGTH};
+use crate::memory::InstantMemory;
+use crate::util::{boxed, Boxed};
+
+pub(crate) const AUDIO_REGISTERS_START: u16 = 0xFF10;
+pub(crate) const AUDIO_REGISTERS_END: u16 = 0xFF26;
+pub(crate) const WAVE_RAM_START: u16 = 0xFF30;
+pub(crate) const WAVE_RAM_END: u16 = 0xFF3F;
+pub(crate) const PCM12: u16 = 0xFF76;
+pub(crate) const PCM34: u16 = 0xFF77;
+
+// Register offsets from 0xFF10
+const NR10: usize = 0x00;
+const NR11: usize = 0x01;
+const NR12: usize = 0x02;
+const NR13: usize = 0x03;
+const NR14: usize = 0x04;
+const NR21: usize = 0x06;
+const NR22: usize = 0x07;
+const NR23: usize = 0x08;
+const NR24: usize = 0x09;
+const NR30: usize = 0x0A;
+const NR31: usize = 0x0B;
+const NR32: usize = 0x0C;
+const NR33: usize = 0x0D;
+const NR34: usize = 0x0E;
+const NR41: usize = 0x10;
+const NR42: usize = 0x11;
+const NR43: usize = 0x12;
+const NR44: usize = 0x13;
+const NR50: usize = 0x14;
+const NR51: usize = 0x15;
+const NR52: usize = 0x16;
+
+const REGISTER_COUNT: usize = NR52 + 1;
+
+/// Bits that always read back as 1 (write-only or unused bits).
+const READ_MASK: [u8; REGISTER_COUNT] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // unused, NR21-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // unused, NR41-NR44
+    0x00, 0x00, 0x70              // NR50-NR52
+];
+
+const PULSE_LENGTH: u16 = 64;
+const WAVE_LENGTH: u16 = 256;
+const NOISE_LENGTH: u16 = 64;
+
+const DUTY_CYCLES: [u8; 4] = [
+    0b00000001, // 12.5%
+    0b10000001, // 25%
+    0b10000111, // 50%
+    0b01111110  // 75%
+];
+
+/// Scale applied to a single channel's DAC output (-15 to 15) to produce an [`AudioSample`].
+const CHANNEL_SAMPLE_SCALE: i64 = 2176;
+
+/// Scale applied to the mixed output (-480 to 480) to produce an [`AudioSample`].
+const MIXED_SAMPLE_SCALE: i64 = 68;
+
+/// High-pass filter capacitor charge factors per 2 MiHz APU cycle, as 0.32 fixed point.
+///
+/// These are 0.999958 (DMG and SGB) and 0.998943 (MGB and CGB) per SoC clock, squared.
+const DMG_CHARGE_FACTOR: i64 = 4294606526;
+const CGB_CHARGE_FACTOR: i64 = 4285892534;
+
+/// APU hardware revision, which determines several behaviors.
+#[derive(Copy, Clone, PartialEq)]
+enum APURevision {
+    DMG,
+    CGB,
+    AGB
+}
+
+impl APURevision {
+    const fn from_model(model: Model) -> Self {
+        match model {
+            Model::DMG0 | Model::DMG | Model::MGB | Model::SGB | Model::SGB2 => Self::DMG,
+            Model::CGB0 | Model::CGB | Model::CGBE => Self::CGB,
+            Model::AGB => Self::AGB
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct APU {
+    revision: APURevision,
+    charge_factor: i64,
+    registers: [u8; REGISTER_COUNT],
+    wave_ram: [u8; 0x10],
+    powered_on: bool,
+
+    /// Next frame sequencer step to run (0-7).
+    frame_sequencer_step: u8,
+    last_div_apu_bit: bool,
+
+    pulse1: PulseChannel,
+    sweep: Sweep,
+    pulse2: PulseChannel,
+    wave: WaveChannel,
+    noise: NoiseChannel,
+
+    high_pass_left: HighPassFilter,
+    high_pass_right: HighPassFilter,
+
+    /// Bit n set = channel n is excluded from the mixed output.
+    muted_channels: u8,
+    solo_channel: Option<AudioChannel>,
+    oscilloscope: Option<Boxed<Oscilloscope>>
+}
+
+impl APU {
+    pub fn new(model: Model) -> Self {
+        Self {
+            revision: APURevision::from_model(model),
+            charge_factor: match model {
+                Model::DMG0 | Model::DMG | Model::SGB | Model::SGB2 => DMG_CHARGE_FACTOR,
+                Model::MGB | Model::CGB0 | Model::CGB | Model::CGBE | Model::AGB => CGB_CHARGE_FACTOR
+            },
+            registers: [0u8; REGISTER_COUNT],
+            wave_ram: [0u8; 0x10],
+            powered_on: false,
+            frame_sequencer_step: 0,
+            last_div_apu_bit: false,
+            pulse1: PulseChannel::default(),
+            sweep: Sweep::default(),
+            pulse2: PulseChannel::default(),
+            wave: WaveChannel::default(),
+            noise: NoiseChannel::default(),
+            high_pass_left: HighPassFilter::default(),
+            high_pass_right: HighPassFilter::default(),
+            muted_channels: 0,
+            solo_channel: None,
+            oscilloscope: None
+        }
+    }
+
+    pub(crate) fn set_channel_muted(&mut self, channel: AudioChannel, muted: bool) {
+        let bit = 1 << (channel as u8);
+        if muted {
+            self.muted_channels |= bit;
+        }
+        else {
+            self.muted_channels &= !bit;
+        }
+    }
+
+    pub(crate) fn is_channel_muted(&self, channel: AudioChannel) -> bool {
+        (self.muted_channels >> (channel as u8)) & 1 != 0
+    }
+
+    pub(crate) fn set_solo_channel(&mut self, channel: Option<AudioChannel>) {
+        self.solo_channel = channel;
+    }
+
+    pub(crate) fn solo_channel(&self) -> Option<AudioChannel> {
+        self.solo_channel
+    }
+
+    pub(crate) fn set_oscilloscope(&mut self, decimation: Option<u32>) {
+        self.oscilloscope = decimation.map(|decimation| boxed(Oscilloscope::new(decimation)));
+    }
+
+    pub(crate) fn oscilloscope(&self) -> Option<&Oscilloscope> {
+        self.oscilloscope.as_ref().map(|oscilloscope| -> &Oscilloscope { oscilloscope })
+    }
+
+    /// Return true if the channel is included in the mixed output.
+    fn channel_audible(&self, channel: usize) -> bool {
+        match self.solo_channel {
+            Some(solo) => solo as usize == channel,
+            None => (self.muted_channels >> channel) & 1 == 0
+        }
+    }
+
+    /// Clock the frame sequencer from the DIV-APU bit of the system counter.
+    ///
+    /// The frame sequencer steps on the falling edge of this bit. Because of this, resetting DIV
+    /// while the bit is set produces an early falling edge (skipping the rest of the current step),
+    /// and resetting it while the bit is clear delays the next falling edge (extending the current
+    /// step). This is called after every system counter change, including DIV writes.
+    pub(crate) fn tick_div_apu(&mut self, div_apu_bit: bool) {
+        let falling_edge = self.last_div_apu_bit && !div_apu_bit;
+        self.last_div_apu_bit = div_apu_bit;
+        if falling_edge && self.powered_on {
+            self.step_frame_sequencer();
+        }
+    }
+
+    /// Advance each channel by one 2 MiHz APU cycle and generate a sample.
+    pub(crate) fn tick(&mut self) -> APUSamples {
+        if self.powered_on {
+            self.pulse1.tick();
+            self.pulse2.tick();
+            self.wave.tick(&self.wave_ram);
+            self.noise.tick();
+        }
+        self.mix()
+    }
+
+    /// Get the current 4-bit digital output of each channel, in order.
+    pub(crate) fn digital_outputs(&self) -> [u8; 4] {
+        [
+            self.pulse1.digital_output(),
+            self.pulse2.digital_output(),
+            self.wave.digital_output(),
+            self.noise.digital_output()
+        ]
+    }
+
+    /// Get the analog output of each channel's DAC, from -15 to 15, or 0 if the DAC is disabled.
+    ///
+    /// On the DMG and CGB, a digital value of 0 outputs the highest voltage, and 15 outputs the
+    /// lowest, so an enabled DAC on a silent channel still has a DC offset.
+    ///
+    /// On the AGB, the output is not inverted, and a disabled channel outputs nothing even if its
+    /// DAC is enabled.
+    fn dac_outputs(&self) -> [i64; 4] {
+        let dac_enabled = [
+            self.pulse1.dac_enabled,
+            self.pulse2.dac_enabled,
+            self.wave.dac_enabled,
+            self.noise.dac_enabled
+        ];
+        let channel_enabled = [
+            self.pulse1.enabled,
+            self.pulse2.enabled,
+            self.wave.enabled,
+            self.noise.enabled
+        ];
+        let digital = self.digital_outputs();
+        core::array::from_fn(|i| {
+            let digital = digital[i] as i64;
+            match self.revision {
+                _ if !dac_enabled[i] => 0,
+                APURevision::DMG | APURevision::CGB => 15 - 2 * digital,
+                APURevision::AGB if channel_enabled[i] => 2 * digital - 15,
+                APURevision::AGB => 0
+            }
+        })
+    }
+
+    fn mix(&mut self) -> APUSamples {
+        let dac = self.dac_outputs();
+        let panning = self.registers[NR51];
+        let master_volume = self.registers[NR50];
+
+        if let Some(oscilloscope) = self.oscilloscope.as_mut() {
+            oscilloscope.record(dac);
+        }
+
+        let mut channels = [AudioSample::default(); 4];
+        let mut left = 0;
+        let mut right = 0;
+        for (i, channel) in channels.iter_mut().enumerate() {
+            let to_left = (panning >> (4 + i)) & 1 != 0;
+            let to_right = (panning >> i) & 1 != 0;
+            let channel_left = if to_left { dac[i] } else { 0 };
+            let channel_right = if to_right { dac[i] } else { 0 };
+
+            // Individual channels are always reported, even if they are muted.
+            *channel = AudioSample::from_levels(channel_left, channel_right, CHANNEL_SAMPLE_SCALE);
+            if self.channel_audible(i) {
+                left += channel_left;
+                right += channel_right;
+            }
+        }
+
+        // NR50 volume is 1-8 (the VIN bits are ignored since no cartridge drives VIN)
+        left *= (((master_volume >> 4) & 7) + 1) as i64;
+        right *= ((master_volume & 7) + 1) as i64;
+
+        let left = self.high_pass_left.apply(left, self.charge_factor);
+        let right = self.high_pass_right.apply(right, self.charge_factor);
+
+        let [wave1, wave2, sample, noise] = channels;
+        APUSamples {
+            mixed: AudioSample::from_levels_fixed(left, right, MIXED_SAMPLE_SCALE),
+            wave1,
+            wave2,
+            sample,
+            noise
+        }
+    }
+
+    fn step_frame_sequencer(&mut self) {
+        let step = self.frame_sequencer_step;
+        self.frame_sequencer_step = (step + 1) & 7;
+
+        // Length counters: 256 Hz
+        if step & 1 == 0 {
+            self.pulse1.enabled &= !self.pulse1.length.clock();
+            self.pulse2.enabled &= !self.pulse2.length.clock();
+            self.wave.enabled &= !self.wave.length.clock();
+            self.noise.enabled &= !self.noise.length.clock();
+        }
+
+        // Sweep: 128 Hz
+        if step == 2 || step == 6 {
+            self.clock_sweep();
+        }
+
+        // Envelopes: 64 Hz
+        if step == 7 {
+            self.pulse1.envelope.clock();
+            self.pulse2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+    }
+
+    /// Return true if the next frame sequencer step will not clock the length counters.
+    ///
+    /// Enabling a length counter during this half of the period clocks it an extra time.
+    fn in_first_half_of_length_period(&self) -> bool {
+        self.frame_sequencer_step & 1 == 1
+    }
+
+    fn clock_sweep(&mut self) {
+        if !self.sweep.clock() {
+            return
+        }
+
+        let new_frequency = self.sweep.calculate();
+        if new_frequency > 2047 {
+            self.pulse1.enabled = false;
+        }
+        else if self.sweep.shift != 0 {
+            self.sweep.shadow_frequency = new_frequency;
+            self.pulse1.frequency = new_frequency;
+            self.registers[NR13] = new_frequency as u8;
+            self.registers[NR14] = (self.registers[NR14] & !0x7) | ((new_frequency >> 8) as u8);
+
+            // Overflow check is done again with the new frequency, but the result is discarded.
+            if self.sweep.calculate() > 2047 {
+                self.pulse1.enabled = false;
+            }
+        }
+    }
+
+    /// On the DMG, retriggering the wave channel just as it reads wave RAM corrupts the first
+    /// four bytes of wave RAM with the bytes being read.
+    fn corrupt_wave_ram_on_retrigger(&mut self) {
+        if !self.wave.enabled || self.wave.timer != 1 {
+            return
+        }
+        let next_byte = (((self.wave.position + 1) & 31) >> 1) as usize;
+        if next_byte < 4 {
+            self.wave_ram[0] = self.wave_ram[next_byte];
+        }
+        else {
+            let block = next_byte & !3;
+            self.wave_ram.copy_within(block..block + 4, 0);
+        }
+    }
+
+    /// Resolve a wave RAM access to an offset in wave RAM, or `None` if inaccessible.
+    ///
+    /// While the wave channel is playing, accesses go to the byte it is currently reading instead.
+    /// On the DMG, this only works at the moment the channel reads wave RAM.
+    fn resolve_wave_ram_offset(&self, address: u16) -> Option<usize> {
+        if !self.wave.enabled {
+            return Some((address - WAVE_RAM_START) as usize)
+        }
+        if self.revision == APURevision::DMG && !self.wave.just_read {
+            return None
+        }
+        Some((self.wave.position >> 1) as usize)
+    }
+
+    fn set_power(&mut self, on: bool) {
+        if on == self.powered_on {
+            return
+        }
+
+        if on {
+            self.powered_on = true;
+            self.frame_sequencer_step = 0;
+            return
+        }
+
+        let lengths = [
+            self.pulse1.length.counter,
+            self.pulse2.length.counter,
+            self.wave.length.counter,
+            self.noise.length.counter
+        ];
+
+        self.registers = [0u8; REGISTER_COUNT];
+        self.pulse1 = PulseChannel::default();
+        self.sweep = Sweep::default();
+        self.pulse2 = PulseChannel::default();
+        self.wave = WaveChannel::default();
+        self.noise = NoiseChannel::default();
+        self.powered_on = false;
+
+        // Length counters are not affected by power on the DMG, but they are on the CGB.
+        if self.revision == APURevision::DMG {
+            self.pulse1.length.counter = lengths[0];
+            self.pulse2.length.counter = lengths[1];
+            self.wave.length.counter = lengths[2];
+            self.noise.length.counter = lengths[3];
+        }
+    }
+
+    fn read_register(&self, index: usize) -> u8 {
+        if index == NR52 {
+            return READ_MASK[NR52]
+                | ((self.powered_on as u8) << 7)
+                | ((self.noise.enabled as u8) << 3)
+                | ((self.wave.enabled as u8) << 2)
+                | ((self.pulse2.enabled as u8) << 1)
+                | (self.pulse1.enabled as u8)
+        }
+        self.registers[index] | READ_MASK[index]
+    }
+
+    fn write_register(&mut self, index: usize, data: u8) {
+        if index == NR52 {
+            self.set_power((data & 0x80) != 0);
+            return
+        }
+
+        if !self.powered_on {
+            // On the DMG, length counters can still be loaded while powered off.
+            if self.revision == APURevision::DMG {
+                match index {
+                    NR11 => self.pulse1.length.load(PULSE_LENGTH, data & 0x3F),
+                    NR21 => self.pulse2.length.load(PULSE_LENGTH, data & 0x3F),
+                    NR31 => self.wave.length.load(WAVE_LENGTH, data),
+                    NR41 => self.noise.length.load(NOISE_LENGTH, data & 0x3F),
+                    _ => ()
+                }
+            }
+            return
+        }
+
+        self.registers[index] = data;
+        let first_half = self.in_first_half_of_length_period();
+
+        match index {
+            NR10 => self.pulse1.enabled &= !self.sweep.write(data),
+            NR11 => self.pulse1.write_length_duty(data),
+            NR12 => self.pulse1.write_envelope(data),
+            NR13 => self.pulse1.frequency = (self.pulse1.frequency & 0x700) | (data as u16),
+            NR14 => {
+                self.pulse1.frequency = (self.pulse1.frequency & 0xFF) | (((data & 7) as u16) << 8);
+                if self.pulse1.write_control(data, first_half) {
+                    self.pulse1.trigger();
+                    if self.sweep.trigger(self.pulse1.frequency) {
+                        self.pulse1.enabled = false;
+                    }
+                }
+            },
+
+            NR21 => self.pulse2.write_length_duty(data),
+            NR22 => self.pulse2.write_envelope(data),
+            NR23 => self.pulse2.frequency = (self.pulse2.frequency & 0x700) | (data as u16),
+            NR24 => {
+                self.pulse2.frequency = (self.pulse2.frequency & 0xFF) | (((data & 7) as u16) << 8);
+                if self.pulse2.write_control(data, first_half) {
+                    self.pulse2.trigger();
+                }
+            },
+
+            NR30 => {
+                self.wave.dac_enabled = (data & 0x80) != 0;
+                self.wave.enabled &= self.wave.dac_enabled;
+            },
+            NR31 => self.wave.length.load(WAVE_LENGTH, data),
+            NR32 => self.wave.volume_code = (data >> 5) & 3,
+            NR33 => self.wave.frequency = (self.wave.frequency & 0x700) | (data as u16),
+            NR34 => {
+                self.wave.frequency = (self.wave.frequency & 0xFF) | (((data & 7) as u16) << 8);
+                let trigger = (data & 0x80) != 0;
+                if self.wave.length.write_control(data, WAVE_LENGTH, first_half) {
+                    self.wave.enabled = false;
+                }
+                if trigger {
+                    if self.revision == APURevision::DMG {
+                        self.corrupt_wave_ram_on_retrigger();
+                    }
+                    self.wave.trigger();
+                }
+            },
+
+            NR41 => self.noise.length.load(NOISE_LENGTH, data & 0x3F),
+            NR42 => {
+                self.noise.envelope.write(data);
+                self.noise.dac_enabled = (data & 0xF8) != 0;
+                self.noise.enabled &= self.noise.dac_enabled;
+            },
+            NR43 => {
+                self.noise.clock_shift = data >> 4;
+                self.noise.width_7 = (data & 0x8) != 0;
+                self.noise.divisor_code = data & 0x7;
+            },
+            NR44 => {
+                let trigger = (data & 0x80) != 0;
+                if self.noise.length.write_control(data, NOISE_LENGTH, first_half) {
+                    self.noise.enabled = false;
+                }
+                if trigger {
+                    self.noise.trigger();
+                }
+            },
+
+            _ => ()
+        }
+    }
+}
+
+impl InstantMemory for APU {
+    fn read(&mut self, address: u16) -> u8 {
+        match address {
+            AUDIO_REGISTERS_START..=AUDIO_REGISTERS_END => self.read_register((address - AUDIO_REGISTERS_START) as usize),
+            WAVE_RAM_START..=WAVE_RAM_END => match self.resolve_wave_ram_offset(address) {
+                Some(offset) => self.wave_ram[offset],
+                None => 0xFF
+            },
+
+            // CGB only; read-only digital outputs of each channel
+            PCM12 | PCM34 => {
+                let outputs = self.digital_outputs();
+                let first = if address == PCM12 { 0 } else { 2 };
+                outputs[first] | (outputs[first + 1] << 4)
+            },
+
+            _ => {
+                debug_assert!(false, "{address:#04X} is not a valid address in APU");
+                0xFF
+            }
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        match address {
+            AUDIO_REGISTERS_START..=AUDIO_REGISTERS_END => self.write_register((address - AUDIO_REGISTERS_START) as usize, data),
+            WAVE_RAM_START..=WAVE_RAM_END => if let Some(offset) = self.resolve_wave_ram_offset(address) {
+                self.wave_ram[offset] = data
+            },
+            PCM12 | PCM34 => (),
+            _ => debug_assert!(false, "{address:#04X} is not a valid address in APU")
+        }
+    }
+
+    fn get_memory(&self) -> Option<&[u8]> {
+        Some(self.wave_ram.as_slice())
+    }
+
+    fn get_memory_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.wave_ram.as_mut_slice())
+    }
+}
+
+/// Fractional bits used for the high-pass filter's fixed point math.
+const HIGH_PASS_FRACTION_BITS: u32 = 16;
+
+/// Emulates the capacitor that removes DC offset from the analog output.
+///
+/// Fixed point math is used so output is identical regardless of platform or build.
+#[derive(Copy, Clone, Default)]
+struct HighPassFilter {
+    capacitor: i64
+}
+
+impl HighPassFilter {
+    /// Filter the given level, returning the result with [`HIGH_PASS_FRACTION_BITS`] fractional bits.
+    fn apply(&mut self, level: i64, charge_factor: i64) -> i64 {
+        let input = level << HIGH_PASS_FRACTION_BITS;
+        let output = input - self.capacitor;
+        self.capacitor = input - ((output * charge_factor) >> 32);
+        output
+    }
+}
+
+impl AudioSample {
+    fn from_levels(left: i64, right: i64, scale: i64) -> Self {
+        Self::from_levels_fixed(left << HIGH_PASS_FRACTION_BITS, right << HIGH_PASS_FRACTION_BITS, scale)
+    }
+
+    fn from_levels_fixed(left: i64, right: i64, scale: i64) -> Self {
+        let convert = |level: i64| (0x8000 + ((level * scale) >> HIGH_PASS_FRACTION_BITS)).clamp(0, 0xFFFF) as u16;
+        Self { left: convert(left), right: convert(right) }
+    }
+}
+
+/// Waveform history of each channel's DAC output, for oscilloscope views.
+#[derive(Copy, Clone)]
+pub(crate) struct Oscilloscope {
+    history: [[i8; OSCILLOSCOPE_LENGTH]; 4],
+    position: usize,
+    decimation: u32,
+    counter: u32
+}
+
+impl Oscilloscope {
+    fn new(decimation: u32) -> Self {
+        Self {
+            history: [[0; OSCILLOSCOPE_LENGTH]; 4],
+            position: 0,
+            decimation: decimation.max(1),
+            counter: 0
+        }
+    }
+
+    fn record(&mut self, dac: [i64; 4]) {
+        self.counter += 1;
+        if self.counter < self.decimation {
+            return
+        }
+        self.counter = 0;
+        for (history, level) in self.history.iter_mut().zip(dac) {
+            history[self.position] = level as i8;
+        }
+        self.position = (self.position + 1) % OSCILLOSCOPE_LENGTH;
+    }
+
+    /// Copy the history of a channel, oldest first.
+    pub(crate) fn copy_history(&self, channel: AudioChannel, output: &mut [i8; OSCILLOSCOPE_LENGTH]) {
+        let history = &self.history[channel as usize];
+        let (older, newer) = history.split_at(self.position);
+        output[..newer.len()].copy_from_slice(newer);
+        output[newer.len()..].copy_from_slice(older);
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct LengthCounter {
+    counter: u16,
+    enabled: bool
+}
+
+impl LengthCounter {
+    fn load(&mut self, max: u16, value: u8) {
+        self.counter = max - (value as u16);
+    }
+
+    /// Clock the length counter, returning true if the channel should be disabled.
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            self.counter == 0
+        }
+        else {
+            false
+        }
+    }
+
+    /// Handle the length enable and trigger bits of an NRx4 write, returning true if the channel
+    /// should be disabled.
+    fn write_control(&mut self, data: u8, max: u16, first_half: bool) -> bool {
+        let was_enabled = self.enabled;
+        let trigger = (data & 0x80) != 0;
+        self.enabled = (data & 0x40) != 0;
+
+        let mut disable = false;
+        if first_half && !was_enabled && self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            disable = self.counter == 0 && !trigger;
+        }
+
+        if trigger && self.counter == 0 {
+            self.counter = if self.enabled && first_half { max - 1 } else { max };
+        }
+
+        disable
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8,
+    volume: u8,
+    timer: u8
+}
+
+impl Envelope {
+    fn write(&mut self, data: u8) {
+        self.initial_volume = data >> 4;
+        self.increase = (data & 0x8) != 0;
+        self.period = data & 0x7;
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            }
+            else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct Sweep {
+    enabled: bool,
+    shadow_frequency: u16,
+    timer: u8,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    negate_used: bool
+}
+
+impl Sweep {
+    /// Write NR10, returning true if the channel should be disabled.
+    ///
+    /// Clearing negate mode after a negated calculation was made since the last trigger disables
+    /// the channel.
+    fn write(&mut self, data: u8) -> bool {
+        let negate = (data & 0x8) != 0;
+        let disable = self.negate && !negate && self.negate_used;
+        self.period = (data >> 4) & 0x7;
+        self.negate = negate;
+        self.shift = data & 0x7;
+        disable
+    }
+
+    fn reload_timer(&mut self) {
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    fn calculate(&mut self) -> u16 {
+        let delta = self.shadow_frequency >> self.shift;
+        if self.negate {
+            self.negate_used = true;
+            self.shadow_frequency - delta
+        }
+        else {
+            self.shadow_frequency + delta
+        }
+    }
+
+    /// Trigger the sweep, returning true if the channel should be disabled due to overflow.
+    fn trigger(&mut self, frequency: u16) -> bool {
+        self.shadow_frequency = frequency;
+        self.negate_used = false;
+        self.reload_timer();
+        self.enabled = self.period != 0 || self.shift != 0;
+        self.shift != 0 && self.calculate() > 2047
+    }
+
+    /// Clock the sweep timer, returning true if a frequency calculation should happen.
+    fn clock(&mut self) -> bool {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer != 0 {
+            return false
+        }
+        self.reload_timer();
+        self.enabled && self.period != 0
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct PulseChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    timer: u16,
+    length: LengthCounter,
+    envelope: Envelope
+}
+
+impl PulseChannel {
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 2
+    }
+
+    fn tick(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+        }
+        else {
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) & 7;
+        }
+    }
+
+    fn write_length_duty(&mut self, data: u8) {
+        self.duty = data >> 6;
+        self.length.load(PULSE_LENGTH, data & 0x3F);
+    }
+
+    fn write_envelope(&mut self, data: u8) {
+        self.envelope.write(data);
+        self.dac_enabled = (data & 0xF8) != 0;
+        self.enabled &= self.dac_enabled;
+    }
+
+    /// Handle an NRx4 write, returning true if the channel should be triggered.
+    fn write_control(&mut self, data: u8, first_half: bool) -> bool {
+        if self.length.write_control(data, PULSE_LENGTH, first_half) {
+            self.enabled = false;
+        }
+        (data & 0x80) != 0
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.timer = self.period();
+        self.envelope.trigger();
+    }
+
+    fn digital_output(&self) -> u8 {
+        let high = (DUTY_CYCLES[self.duty as usize] >> (7 - self.duty_step)) & 1 != 0;
+        if self.enabled && high {
+            self.envelope.volume
+        }
+        else {
+            0
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    volume_code: u8,
+    frequency: u16,
+    timer: u16,
+    position: u8,
+    sample_buffer: u8,
+
+    /// Wave RAM was read on the last tick.
+    just_read: bool,
+    length: LengthCounter
+}
+
+impl WaveChannel {
+    fn period(&self) -> u16 {
+        2048 - self.frequency
+    }
+
+    fn tick(&mut self, wave_ram: &[u8; 0x10]) {
+        self.just_read = self.timer <= 1;
+        if self.just_read {
+            self.timer = self.period();
+            self.position = (self.position + 1) & 31;
+            self.sample_buffer = wave_ram[(self.position >> 1) as usize];
+        }
+        else {
+            self.timer -= 1;
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+
+        // There is a short delay before the first sample is read after triggering.
+        self.timer = self.period() + 3;
+        self.position = 0;
+    }
+
+    fn digital_output(&self) -> u8 {
+        if !self.enabled {
+            return 0
+        }
+        let sample = if self.position & 1 == 0 { self.sample_buffer >> 4 } else { self.sample_buffer & 0xF };
+        match self.volume_code {
+            0 => 0,
+            n => sample >> (n - 1)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct NoiseChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    clock_shift: u8,
+    width_7: bool,
+    divisor_code: u8,
+    lfsr: u16,
+    timer: u32,
+    length: LengthCounter,
+    envelope: Envelope
+}
+
+impl NoiseChannel {
+    fn period(&self) -> u32 {
+        let divisor = if self.divisor_code == 0 { 8 } else { (self.divisor_code as u32) * 16 };
+
+        // Divisor is in SoC clocks, but the APU ticks at half that.
+        (divisor << self.clock_shift) / 2
+    }
+
+    fn tick(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return
+        }
+        self.timer = self.period();
+
+        // Clock shifts 14 and 15 do not clock the LFSR.
+        if self.clock_shift >= 14 {
+            return
+        }
+        let xor = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+        self.lfsr = (self.lfsr >> 1) | (xor << 14);
+        if self.width_7 {
+            self.lfsr = (self.lfsr & !0x40) | (xor << 6);
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.lfsr = 0x7FFF;
+        self.timer = self.period();
+        self.envelope.trigger();
+    }
+
+    fn digital_output(&self) -> u8 {
+        if self.enabled && (self.lfsr & 1) == 0 {
+            self.envelope.volume
+        }
+        else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::tests::{new_emulator, run, TestEmulator};
+
+    const NR11: u16 = 0xFF11;
+    const NR12: u16 = 0xFF12;
+    const NR14: u16 = 0xFF14;
+    const NR50: u16 = 0xFF24;
+    const NR51: u16 = 0xFF25;
+    const NR52: u16 = 0xFF26;
+    const DIV: u16 = 0xFF04;
+
+    /// Power on an APU with pulse 1's DAC enabled but the channel not triggered, so it outputs a
+    /// constant DC offset.
+    fn apu_with_dc_offset(model: Model) -> APU {
+        let mut apu = APU::new(model);
+        apu.write(NR52, 0x80);
+        apu.write(NR12, 0xF0);
+        apu
+    }
+
+    /// Get a sample's distance from silence.
+    fn level(sample: u16) -> i64 {
+        sample as i64 - 0x8000
+    }
+
+    /// Power on and trigger pulse 1 with a length of 1, so the next length clock disables it.
+    fn trigger_short_pulse(emulator: &mut TestEmulator) {
+        emulator.write(NR52, 0x80);
+        emulator.write(NR12, 0xF0);
+        emulator.write(NR11, 0x3F);
+        emulator.write(NR14, 0xC0);
+        assert_eq!(emulator.read(NR52) & 1, 1);
+    }
+
+    #[test]
+    fn frame_sequencer_steps_on_div_apu_falling_edge() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        trigger_short_pulse(&mut emulator);
+
+        // Bit 12 of the system counter first falls after 0x2000 SoC clocks.
+        run(&mut emulator, 0x1FFF);
+        assert_eq!(emulator.read(NR52) & 1, 1);
+        run(&mut emulator, 1);
+        assert_eq!(emulator.read(NR52) & 1, 0);
+    }
+
+    #[test]
+    fn frame_sequencer_uses_bit_13_in_double_speed_mode() {
+        let mut emulator = new_emulator((), (), Model::CGB);
+        emulator.io.double_speed_mode = true;
+        trigger_short_pulse(&mut emulator);
+
+        run(&mut emulator, 0x3FFF);
+        assert_eq!(emulator.read(NR52) & 1, 1);
+        run(&mut emulator, 1);
+        assert_eq!(emulator.read(NR52) & 1, 0);
+    }
+
+    #[test]
+    fn div_reset_while_div_apu_bit_is_set_steps_early() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        trigger_short_pulse(&mut emulator);
+
+        run(&mut emulator, 0x1000);
+        emulator.write(DIV, 0);
+        assert_eq!(emulator.read(NR52) & 1, 0);
+    }
+
+    #[test]
+    fn div_reset_while_div_apu_bit_is_clear_delays_the_step() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        trigger_short_pulse(&mut emulator);
+
+        run(&mut emulator, 0xFFF);
+        emulator.write(DIV, 0);
+        run(&mut emulator, 0x1FFF);
+        assert_eq!(emulator.read(NR52) & 1, 1);
+        run(&mut emulator, 1);
+        assert_eq!(emulator.read(NR52) & 1, 0);
+    }
+
+    #[test]
+    fn dmg_keeps_length_counters_through_power_off() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        trigger_short_pulse(&mut emulator);
+        emulator.write(NR52, 0x00);
+        run(&mut emulator, 0x2000);
+
+        // The length counter is not clocked while powered off, so it still has one step left.
+        emulator.write(NR52, 0x80);
+        emulator.write(NR12, 0xF0);
+        emulator.write(NR14, 0xC0);
+        assert_eq!(emulator.read(NR52) & 1, 1);
+        run(&mut emulator, 0x2000);
+        assert_eq!(emulator.read(NR52) & 1, 0);
+    }
+
+    #[test]
+    fn nr52_power_off_clears_registers_and_ignores_writes() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        assert_eq!(emulator.read(NR52), 0x70);
+
+        emulator.write(NR52, 0x80);
+        emulator.write(NR50, 0x77);
+        assert_eq!(emulator.read(NR50), 0x77);
+        assert_eq!(emulator.read(NR52), 0xF0);
+
+        emulator.write(NR52, 0x00);
+        assert_eq!(emulator.read(NR50), 0x00);
+        assert_eq!(emulator.read(NR52), 0x70);
+
+        emulator.write(NR50, 0x77);
+        emulator.write(NR52, 0x80);
+        assert_eq!(emulator.read(NR50), 0x00);
+    }
+
+    #[test]
+    fn nr52_reports_triggered_channels() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        trigger_short_pulse(&mut emulator);
+        assert_eq!(emulator.read(NR52), 0xF1);
+    }
+
+    #[test]
+    fn nr51_pans_each_side_separately() {
+        let mut apu = apu_with_dc_offset(Model::DMG);
+        apu.write(NR50, 0x77);
+        apu.write(NR51, 0x01);
+        let samples = apu.tick();
+        assert_eq!(level(samples.wave1.left), 0);
+        assert_eq!(level(samples.wave1.right), 15 * CHANNEL_SAMPLE_SCALE);
+        assert_eq!(level(samples.mixed.left), 0);
+        assert_eq!(level(samples.mixed.right), 15 * 8 * MIXED_SAMPLE_SCALE);
+
+        let mut apu = apu_with_dc_offset(Model::DMG);
+        apu.write(NR50, 0x77);
+        apu.write(NR51, 0x10);
+        let samples = apu.tick();
+        assert_eq!(level(samples.wave1.left), 15 * CHANNEL_SAMPLE_SCALE);
+        assert_eq!(level(samples.wave1.right), 0);
+        assert_eq!(level(samples.mixed.left), 15 * 8 * MIXED_SAMPLE_SCALE);
+        assert_eq!(level(samples.mixed.right), 0);
+    }
+
+    #[test]
+    fn nr50_scales_the_mixed_output() {
+        let mut apu = apu_with_dc_offset(Model::DMG);
+        apu.write(NR50, 0x30);
+        apu.write(NR51, 0x11);
+        let samples = apu.tick();
+        assert_eq!(level(samples.mixed.left), 15 * 4 * MIXED_SAMPLE_SCALE);
+        assert_eq!(level(samples.mixed.right), 15 * MIXED_SAMPLE_SCALE);
+
+        // Individual channels are not affected by the master volume.
+        assert_eq!(samples.wave1.left, samples.wave1.right);
+    }
+
+    #[test]
+    fn disabled_dacs_are_silent() {
+        let mut apu = apu_with_dc_offset(Model::DMG);
+        apu.write(NR50, 0x77);
+        apu.write(NR51, 0xFF);
+
+        // An enabled DAC outputs a DC offset even at volume 0.
+        apu.write(NR12, 0x08);
+        assert_eq!(level(apu.tick().wave1.left), 15 * CHANNEL_SAMPLE_SCALE);
+
+        let mut apu = apu_with_dc_offset(Model::DMG);
+        apu.write(NR50, 0x77);
+        apu.write(NR51, 0xFF);
+        apu.write(NR12, 0x00);
+        let samples = apu.tick();
+        assert_eq!(level(samples.wave1.left), 0);
+        assert_eq!(level(samples.wave1.right), 0);
+        assert_eq!(level(samples.mixed.left), 0);
+        assert_eq!(level(samples.mixed.right), 0);
+    }
+
+    #[test]
+    fn high_pass_filter_removes_dc_faster_on_cgb() {
+        let decayed = |model| {
+            let mut apu = apu_with_dc_offset(model);
+            apu.write(NR50, 0x77);
+            apu.write(NR51, 0x11);
+            let initial = level(apu.tick().mixed.left);
+            let mut last = initial;
+            for _ in 0..4096 {
+                last = level(apu.tick().mixed.left);
+            }
+            (initial, last)
+        };
+
+        let (dmg_initial, dmg) = decayed(Model::DMG);
+        let (cgb_initial, cgb) = decayed(Model::CGB);
+        assert_eq!(dmg_initial, cgb_initial);
+        assert!(dmg < dmg_initial && dmg > dmg_initial / 2, "{dmg}");
+        assert!(cgb < cgb_initial / 100, "{cgb}");
+    }
+
+    #[test]
+    fn pcm_registers_read_the_digital_outputs() {
+        let mut apu = APU::new(Model::CGB);
+        apu.write(NR52, 0x80);
+
+        // Both pulse channels start on the high step of the 50% duty cycle.
+        apu.write(NR11, 0x80);
+        apu.write(NR12, 0xA0);
+        apu.write(NR14, 0x80);
+        apu.write(0xFF16, 0x80);
+        apu.write(0xFF17, 0x50);
+        apu.write(0xFF19, 0x80);
+
+        // The wave channel reads the low nibble of the first byte a few cycles after triggering.
+        apu.write(WAVE_RAM_START, 0x7C);
+        apu.write(0xFF1A, 0x80);
+        apu.write(0xFF1C, 0x20);
+        apu.write(0xFF1D, 0xFF);
+        apu.write(0xFF1E, 0x87);
+
+        // The noise channel's LFSR starts with bit 0 set, so it outputs 0.
+        apu.write(0xFF21, 0xF0);
+        apu.write(0xFF23, 0x80);
+
+        assert_eq!(apu.read(PCM12), 0x5A);
+        assert_eq!(apu.read(PCM34), 0x00);
+        for _ in 0..4 {
+            apu.tick();
+        }
+        assert_eq!(apu.read(PCM12), 0x5A);
+        assert_eq!(apu.read(PCM34), 0x0C);
+
+        // They are read-only.
+        apu.write(PCM12, 0x00);
+        assert_eq!(apu.read(PCM12), 0x5A);
+    }
+
+    /// Power on an APU with both pulse channels outputting a DC offset to both sides.
+    fn apu_with_two_channels() -> APU {
+        let mut apu = apu_with_dc_offset(Model::DMG);
+        apu.write(0xFF17, 0xF0);
+        apu.write(NR50, 0x77);
+        apu.write(NR51, 0x33);
+        apu
+    }
+
+    #[test]
+    fn muted_channels_are_only_removed_from_the_mixed_output() {
+        let mut apu = apu_with_two_channels();
+        apu.set_channel_muted(AudioChannel::Wave1, true);
+        let samples = apu.tick();
+        assert_eq!(level(samples.mixed.left), 15 * 8 * MIXED_SAMPLE_SCALE);
+        assert_eq!(level(samples.wave1.left), 15 * CHANNEL_SAMPLE_SCALE);
+        assert_eq!(level(samples.wave2.left), 15 * CHANNEL_SAMPLE_SCALE);
+
+        let mut apu = apu_with_two_channels();
+        assert_eq!(level(apu.tick().mixed.left), 30 * 8 * MIXED_SAMPLE_SCALE);
+    }
+
+    #[test]
+    fn solo_channel_is_heard_alone_even_if_muted() {
+        let mut apu = apu_with_two_channels();
+        apu.set_channel_muted(AudioChannel::Wave2, true);
+        apu.set_solo_channel(Some(AudioChannel::Wave2));
+        let samples = apu.tick();
+        assert_eq!(level(samples.mixed.right), 15 * 8 * MIXED_SAMPLE_SCALE);
+        assert_eq!(level(samples.wave1.right), 15 * CHANNEL_SAMPLE_SCALE);
+
+        // Soloing a silent channel silences the mix.
+        let mut apu = apu_with_two_channels();
+        apu.set_solo_channel(Some(AudioChannel::Noise));
+        let samples = apu.tick();
+        assert_eq!(level(samples.mixed.right), 0);
+        assert_eq!(level(samples.wave1.right), 15 * CHANNEL_SAMPLE_SCALE);
+    }
+
+    #[test]
+    fn oscilloscope_keeps_the_last_points_of_each_channel() {
+        let mut oscilloscope = Oscilloscope::new(2);
+        let points = OSCILLOSCOPE_LENGTH as i64 + 100;
+        for point in 0..points {
+            // Each point is recorded twice, and only one of them is kept.
+            let dac = [point % 31 - 15, 15 - point % 31, 0, 7];
+            oscilloscope.record(dac);
+            oscilloscope.record(dac);
+        }
+
+        let mut history = [0; OSCILLOSCOPE_LENGTH];
+        oscilloscope.copy_history(AudioChannel::Wave1, &mut history);
+        for (i, &level) in history.iter().enumerate() {
+            assert_eq!(level as i64, (100 + i as i64) % 31 - 15);
+        }
+        oscilloscope.copy_history(AudioChannel::Wave2, &mut history);
+        assert_eq!(history[OSCILLOSCOPE_LENGTH - 1] as i64, 15 - (points - 1) % 31);
+        oscilloscope.copy_history(AudioChannel::Noise, &mut history);
+        assert!(history.iter().all(|&level| level == 7));
+    }
+
+    #[test]
+    fn oscilloscope_records_the_dac_outputs() {
+        let mut apu = apu_with_two_channels();
+        assert!(apu.oscilloscope().is_none());
+        apu.set_oscilloscope(Some(1));
+        apu.write(0xFF17, 0x00);
+        apu.tick();
+
+        let mut history = [0; OSCILLOSCOPE_LENGTH];
+        apu.oscilloscope().unwrap().copy_history(AudioChannel::Wave1, &mut history);
+        assert_eq!(history[OSCILLOSCOPE_LENGTH - 1], 15);
+        apu.oscilloscope().unwrap().copy_history(AudioChannel::Wave2, &mut history);
+        assert_eq!(history[OSCILLOSCOPE_LENGTH - 1], 0);
+    }
+
+    #[test]
+    fn cgb_clears_length_counters_on_power_off() {
+        let mut emulator = new_emulator((), (), Model::CGB);
+        trigger_short_pulse(&mut emulator);
+        emulator.write(NR52, 0x00);
+
+        // Lengths also cannot be loaded while powered off.
+        emulator.write(NR11, 0x3F);
+
+        // Triggering with a cleared length counter reloads it with the full length.
+        emulator.write(NR52, 0x80);
+        emulator.write(NR12, 0xF0);
+        emulator.write(NR14, 0xC0);
+        run(&mut emulator, 0x2000);
+        assert_eq!(emulator.read(NR52) & 1, 1);
+    }
+
+    #[test]
+    fn dmg_loads_length_counters_while_powered_off() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        emulator.write(NR11, 0x3F);
+        emulator.write(NR52, 0x80);
+        emulator.write(NR12, 0xF0);
+        emulator.write(NR14, 0xC0);
+        run(&mut emulator, 0x2000);
+        assert_eq!(emulator.read(NR52) & 1, 0);
+    }
+
+    /// Power on an APU and start the wave channel, reading wave RAM every other cycle with each
+    /// byte of wave RAM set to its index times 0x11.
+    fn playing_wave_channel(model: Model) -> APU {
+        let mut apu = APU::new(model);
+        apu.write(NR52, 0x80);
+        for i in 0..0x10 {
+            apu.write(WAVE_RAM_START + i, i as u8 * 0x11);
+        }
+        apu.write(0xFF1A, 0x80);
+        apu.write(0xFF1D, 0xFE);
+        apu.write(0xFF1E, 0x87);
+        apu
+    }
+
+    #[test]
+    fn dmg_only_accesses_wave_ram_as_it_is_read() {
+        for (model, between_reads, written) in [(Model::DMG, 0xFF, 0xFF), (Model::CGB, 0x11, 0x99)] {
+            let mut apu = playing_wave_channel(model);
+
+            // The first sample is read 5 cycles after triggering, then every 2 cycles.
+            for _ in 0..7 {
+                apu.tick();
+            }
+            assert_eq!(apu.read(WAVE_RAM_END), 0x11);
+            apu.tick();
+            assert_eq!(apu.read(WAVE_RAM_END), between_reads);
+            apu.write(WAVE_RAM_START, 0x99);
+            assert_eq!(apu.read(WAVE_RAM_END), written);
+        }
+    }
+
+    #[test]
+    fn dmg_corrupts_wave_ram_when_retriggered_as_it_is_read() {
+        for (model, first_byte) in [(Model::DMG, 0x11), (Model::CGB, 0x00)] {
+            let mut apu = playing_wave_channel(model);
+
+            // Retrigger one cycle before the second sample is read.
+            for _ in 0..6 {
+                apu.tick();
+            }
+            apu.write(0xFF1E, 0x87);
+            apu.write(0xFF1A, 0x00);
+            assert_eq!(apu.read(WAVE_RAM_START), first_byte);
+            assert_eq!(apu.read(WAVE_RAM_START + 1), 0x11);
+        }
+    }
+
+    #[test]
+    fn charge_factor_depends_on_the_model() {
+        for model in [Model::DMG0, Model::DMG, Model::SGB, Model::SGB2] {
+            assert_eq!(APU::new(model).charge_factor, DMG_CHARGE_FACTOR);
+        }
+        for model in [Model::MGB, Model::CGB0, Model::CGB, Model::CGBE, Model::AGB] {
+            assert_eq!(APU::new(model).charge_factor, CGB_CHARGE_FACTOR);
+        }
+    }
+
+    #[test]
+    fn agb_dac_is_not_inverted_and_silent_while_the_channel_is_off() {
+        for (model, untriggered, triggered) in [(Model::CGB, 15, -5), (Model::AGB, 0, 5)] {
+            let mut apu = APU::new(model);
+            apu.write(NR52, 0x80);
+            apu.write(NR51, 0x11);
+            apu.write(NR11, 0xC0);
+            apu.write(NR12, 0xA0);
+            assert_eq!(level(apu.tick().wave1.left), untriggered * CHANNEL_SAMPLE_SCALE);
+
+            // The first cycle moved to a high step of the 75% duty cycle.
+            apu.write(NR14, 0x80);
+            assert_eq!(level(apu.tick().wave1.left), triggered * CHANNEL_SAMPLE_SCALE);
+        }
+    }
+}
@@ -0,0 +1,423 @@
+//! High-level emulation of the boot ROM.
+//!
+//! Instead of running a boot ROM, the state it leaves the console in when handing off to the
+//! cartridge at 0x0100 is set directly.
+
+use crate::cartridge::Cartridge;
+use crate::infrared::InfraredDevice;
+use crate::instance::io::IO;
+use crate::instance::{CompatibilityPalettes, CPURegisters, Model};
+use crate::memory::InstantMemory;
+use crate::serial::SerialDevice;
+
+const HEADER_START: u16 = 0x0100;
+const HEADER_SIZE: usize = 0x50;
+
+const FLAG_Z: u8 = 0b1000_0000;
+const FLAG_H: u8 = 0b0010_0000;
+const FLAG_C: u8 = 0b0001_0000;
+
+/// Registered trademark symbol copied into VRAM after the logo.
+const REGISTERED_TILE: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+
+/// First tile the logo is decompressed into.
+const LOGO_TILE_DATA: u16 = 0x8010;
+
+/// Tile map rows the logo and trademark symbol are placed in.
+const LOGO_TILE_MAP_TOP: u16 = 0x9904;
+const LOGO_TILE_MAP_BOTTOM: u16 = 0x9924;
+const REGISTERED_TILE_MAP: u16 = 0x9910;
+
+/// Default palettes used for DMG cartridges that are not recognized.
+const DEFAULT_COMPATIBILITY_PALETTES: CompatibilityPalettes = CompatibilityPalettes {
+    background: [0x7FFF, 0x1BEF, 0x6180, 0x0000],
+    object0: [0x7FFF, 0x421F, 0x1CF2, 0x0000],
+    object1: [0x7FFF, 0x421F, 0x1CF2, 0x0000]
+};
+
+// The palette tables below follow SameBoy's disassembly of the CGB boot ROM.
+
+/// Title hashes of Nintendo-licensed DMG games the CGB boot ROM recognizes. Each hash from
+/// [`SHARED_TITLE_HASHES_START`] on belongs to several games, and also needs the fourth letter of
+/// the title to match the entry in [`TITLE_FOURTH_LETTERS`].
+const TITLE_HASHES: [u8; 94] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+    0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3
+];
+
+const SHARED_TITLE_HASHES_START: usize = 65;
+
+/// Fourth title letter for each hash from [`SHARED_TITLE_HASHES_START`] on.
+const TITLE_FOURTH_LETTERS: [u8; 29] = *b"BEFAARBEKEK R-URAR INAILICE R";
+
+/// Index into [`PALETTE_COMBINATIONS`] for each entry of [`TITLE_HASHES`].
+const TITLE_PALETTE_COMBINATIONS: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44,
+    21, 32, 31, 20, 5, 33, 13, 14, 5, 29, 5, 18, 9, 3, 2, 26,
+    25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34,
+    5, 42, 6, 5, 33, 25, 42, 42, 40, 14, 16, 25, 42, 42, 5, 0,
+    39, 36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39, 24, 31, 50, 17,
+    46, 6, 27, 0, 47, 41, 41, 0, 0, 19, 34, 23, 18, 29
+];
+
+/// Object 0, object 1 and background palettes, as the index of their first color in
+/// [`PALETTE_COLORS`]. The first combination is used for unrecognized games.
+const PALETTE_COMBINATIONS: [[u8; 3]; 51] = [
+    [16, 16, 116], [72, 72, 72], [80, 80, 80], [96, 96, 96], [36, 36, 36], [0, 0, 0],
+    [108, 108, 108], [20, 20, 20], [48, 48, 48], [104, 104, 104], [64, 32, 32], [16, 112, 112],
+    [16, 8, 8], [12, 16, 16], [16, 116, 116], [112, 16, 112], [8, 68, 8], [64, 64, 32],
+    [16, 16, 28], [16, 16, 72], [16, 16, 80], [76, 76, 36], [15, 15, 44], [68, 68, 8],
+    [16, 16, 8], [16, 16, 12], [112, 112, 0], [12, 12, 0], [0, 0, 4], [72, 88, 72],
+    [80, 88, 80], [96, 88, 96], [64, 88, 32], [68, 16, 52], [111, 0, 56], [111, 16, 60],
+    [76, 88, 36], [64, 112, 40], [16, 92, 112], [68, 88, 8], [16, 0, 8], [16, 112, 12],
+    [112, 12, 0], [12, 112, 16], [84, 112, 16], [12, 112, 0], [100, 12, 112], [0, 112, 32],
+    [16, 12, 112], [112, 12, 24], [16, 112, 116]
+];
+
+/// Colors the combinations are made from, in groups of four per palette.
+const PALETTE_COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000,
+    0x639F, 0x4279, 0x15B0, 0x04CB,
+    0x7FFF, 0x6E31, 0x454A, 0x0000,
+    0x7FFF, 0x1BEF, 0x0200, 0x0000,
+    0x7FFF, 0x421F, 0x1CF2, 0x0000,
+    0x7FFF, 0x5294, 0x294A, 0x0000,
+    0x7FFF, 0x03FF, 0x012F, 0x0000,
+    0x7FFF, 0x03EF, 0x01D6, 0x0000,
+    0x7FFF, 0x42B5, 0x3DC8, 0x0000,
+    0x7E74, 0x03FF, 0x0180, 0x0000,
+    0x67FF, 0x77AC, 0x1A13, 0x2D6B,
+    0x7ED6, 0x4BFF, 0x2175, 0x0000,
+    0x53FF, 0x4A5F, 0x7E52, 0x0000,
+    0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0,
+    0x03ED, 0x7FFF, 0x255F, 0x0000,
+    0x036A, 0x021F, 0x03FF, 0x7FFF,
+    0x7FFF, 0x01DF, 0x0112, 0x0000,
+    0x231F, 0x035F, 0x00F2, 0x0009,
+    0x7FFF, 0x03EA, 0x011F, 0x0000,
+    0x299F, 0x001A, 0x000C, 0x0000,
+    0x7FFF, 0x027F, 0x001F, 0x0000,
+    0x7FFF, 0x03E0, 0x0206, 0x0120,
+    0x7FFF, 0x7EEB, 0x001F, 0x7C00,
+    0x7FFF, 0x3FFF, 0x7E00, 0x001F,
+    0x7FFF, 0x03FF, 0x001F, 0x0000,
+    0x03FF, 0x001F, 0x000C, 0x0000,
+    0x7FFF, 0x033F, 0x0193, 0x0000,
+    0x0000, 0x4200, 0x037F, 0x7FFF,
+    0x7FFF, 0x7E8C, 0x7C00, 0x0000,
+    0x7FFF, 0x1BEF, 0x6180, 0x0000
+];
+
+/// Post-boot register values written through the bus, in order.
+///
+/// APU registers leave channel 1 in the state the boot sound ends in.
+const IO_REGISTERS: &[(u16, u8)] = &[
+    (0xFF00, 0xCF), // P1
+    (0xFF07, 0xF8), // TAC
+    (0xFF26, 0x80), // NR52 (powered on first so the other APU registers can be written)
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0x80), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xC1), // NR13
+    (0xFF14, 0x87), // NR14
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF40, 0x91), // LCDC
+    (0xFF47, 0xFC), // BGP (OBP0 and OBP1 are not written by any boot ROM)
+    (0xFF0F, 0x01), // IF (vblank)
+];
+
+/// Set the state the boot ROM leaves the console in, returning the CPU registers and the palettes
+/// chosen for DMG cartridges on CGB models.
+pub(crate) fn apply_post_boot_state<Cart: Cartridge, Serial: SerialDevice, Infrared: InfraredDevice>(
+    io: &mut IO<Cart, Serial, Infrared>
+) -> (CPURegisters, Option<CompatibilityPalettes>) {
+    io.registers.disable_bootrom.memory.unmap();
+
+    let header = read_header(io);
+
+    for &(address, data) in IO_REGISTERS {
+        io.write(address, data);
+    }
+
+    let model = io.model;
+    let cgb_mode = (header[0x43] & 0x80) != 0;
+    let header_checksum_zero = header[0x4D] == 0;
+    let title_hash = nintendo_title_hash(&header);
+
+    // DIV is documented as 0x18 on DMG0 and 0xAB on DMG/MGB at handoff. The lower byte of the
+    // system counter and the values for other models are approximate.
+    let system_counter = match model {
+        Model::DMG0 => 0x1830,
+        Model::DMG | Model::MGB => 0xABCC,
+        Model::SGB | Model::SGB2 => 0xD85C,
+        Model::CGB0 | Model::CGB | Model::CGBE | Model::AGB => 0x1EA0
+    };
+    io.registers.timer_div.memory.set_system_counter(system_counter);
+
+    // The SGB shows the logo on the SNES instead.
+    if model.is_dmg() && !model.is_sgb() {
+        write_logo(io, &header);
+    }
+
+    let dmg_flags = if header_checksum_zero { FLAG_Z } else { FLAG_Z | FLAG_H | FLAG_C };
+
+    let mut cpu = match model {
+        Model::DMG0 => CPURegisters { a: 0x01, f: 0x00, b: 0xFF, c: 0x13, d: 0x00, e: 0xC1, h: 0x84, l: 0x03, ..CPURegisters::default() },
+        Model::DMG => CPURegisters { a: 0x01, f: dmg_flags, b: 0x00, c: 0x13, d: 0x00, e: 0xD8, h: 0x01, l: 0x4D, ..CPURegisters::default() },
+        Model::MGB => CPURegisters { a: 0xFF, f: dmg_flags, b: 0x00, c: 0x13, d: 0x00, e: 0xD8, h: 0x01, l: 0x4D, ..CPURegisters::default() },
+        Model::SGB => CPURegisters { a: 0x01, f: 0x00, b: 0x00, c: 0x14, d: 0x00, e: 0x00, h: 0xC0, l: 0x60, ..CPURegisters::default() },
+        Model::SGB2 => CPURegisters { a: 0xFF, f: 0x00, b: 0x00, c: 0x14, d: 0x00, e: 0x00, h: 0xC0, l: 0x60, ..CPURegisters::default() },
+        Model::CGB0 | Model::CGB | Model::CGBE | Model::AGB => {
+            let mut registers = if cgb_mode {
+                CPURegisters { a: 0x11, f: FLAG_Z, b: 0x00, c: 0x00, d: 0xFF, e: 0x56, h: 0x00, l: 0x0D, ..CPURegisters::default() }
+            }
+            else {
+                CPURegisters { a: 0x11, f: FLAG_Z, b: title_hash.unwrap_or(0), c: 0x00, d: 0x00, e: 0x08, h: 0x00, l: 0x7C, ..CPURegisters::default() }
+            };
+
+            // The AGB boot ROM ends with INC B.
+            if model == Model::AGB {
+                registers.b = registers.b.wrapping_add(1);
+                registers.f = (if registers.b == 0 { FLAG_Z } else { 0 }) | (if registers.b & 0xF == 0 { FLAG_H } else { 0 });
+            }
+            registers
+        }
+    };
+
+    cpu.sp = 0xFFFE;
+    cpu.pc = 0x0100;
+
+    // KEY0 is locked once the boot ROM is unmapped, so it is set directly. CGB cartridges get their
+    // CGB flag written to it, and DMG cartridges get DMG compatibility mode and object priority by X
+    // coordinate.
+    if model.is_cgb() {
+        io.registers.key0.byte = if cgb_mode { header[0x43] } else { 0x04 };
+        io.registers.object_priority.byte = if cgb_mode { 0x00 } else { 0x01 };
+    }
+
+    let palettes = if model.is_cgb() && !cgb_mode {
+        Some(compatibility_palettes(title_hash, header[0x37]))
+    }
+    else {
+        None
+    };
+
+    (cpu, palettes)
+}
+
+/// Read the cartridge header (0x0100-0x014F).
+pub(crate) fn read_header<Cart: Cartridge, Serial: SerialDevice, Infrared: InfraredDevice>(io: &mut IO<Cart, Serial, Infrared>) -> [u8; HEADER_SIZE] {
+    let mut header = [0u8; HEADER_SIZE];
+    for (address, byte) in (HEADER_START..).zip(header.iter_mut()) {
+        *byte = io.read(address);
+    }
+    header
+}
+
+/// Decompress the cartridge's logo into VRAM and place it in the tile map, as the DMG boot ROM does.
+fn write_logo<Cart: Cartridge, Serial: SerialDevice, Infrared: InfraredDevice>(io: &mut IO<Cart, Serial, Infrared>, header: &[u8; HEADER_SIZE]) {
+    let vram = &mut io.video_ram.memory;
+
+    // Each nibble becomes a row of 8 pixels (each bit doubled), written twice. Only the low bitplane
+    // is written.
+    let mut address = LOGO_TILE_DATA;
+    for &byte in &header[0x04..=0x33] {
+        for nibble in [byte >> 4, byte & 0xF] {
+            let row = (0..4).fold(0u8, |row, bit| row | ((((nibble >> bit) & 1) * 0b11) << (bit * 2)));
+            for _ in 0..2 {
+                vram.write(address, row);
+                address += 2;
+            }
+        }
+    }
+    for row in REGISTERED_TILE {
+        vram.write(address, row);
+        address += 2;
+    }
+
+    for tile in 0..12 {
+        vram.write(LOGO_TILE_MAP_TOP + tile, 0x01 + tile as u8);
+        vram.write(LOGO_TILE_MAP_BOTTOM + tile, 0x0D + tile as u8);
+    }
+    vram.write(REGISTERED_TILE_MAP, 0x19);
+}
+
+/// Sum of the title bytes (0x134-0x143) if the cartridge is licensed by Nintendo, which the CGB
+/// boot ROM uses to identify DMG cartridges.
+fn nintendo_title_hash(header: &[u8; HEADER_SIZE]) -> Option<u8> {
+    let old_licensee = header[0x4B];
+    let nintendo = old_licensee == 0x01 || (old_licensee == 0x33 && &header[0x44..=0x45] == b"01");
+    if !nintendo {
+        return None
+    }
+    Some(header[0x34..=0x43].iter().fold(0u8, |sum, &b| sum.wrapping_add(b)))
+}
+
+/// Choose palettes for a DMG cartridge from its title hash and the fourth letter of its title, as
+/// the CGB boot ROM does.
+///
+/// Unrecognized games and games not licensed by Nintendo get the default palettes, which are also
+/// the first combination.
+fn compatibility_palettes(title_hash: Option<u8>, fourth_letter: u8) -> CompatibilityPalettes {
+    let entry = title_hash.and_then(|title_hash| {
+        TITLE_HASHES.iter().enumerate().position(|(index, &hash)| {
+            hash == title_hash && (index < SHARED_TITLE_HASHES_START || TITLE_FOURTH_LETTERS[index - SHARED_TITLE_HASHES_START] == fourth_letter)
+        })
+    });
+    let Some(entry) = entry else {
+        return DEFAULT_COMPATIBILITY_PALETTES
+    };
+    palette_combination(TITLE_PALETTE_COMBINATIONS[entry] as usize)
+}
+
+/// Look up the colors of an entry of [`PALETTE_COMBINATIONS`].
+fn palette_combination(combination: usize) -> CompatibilityPalettes {
+    let [object0, object1, background] = PALETTE_COMBINATIONS[combination];
+    let palette = |first: u8| -> [u16; 4] { core::array::from_fn(|color| PALETTE_COLORS[first as usize + color]) };
+    CompatibilityPalettes {
+        background: palette(background),
+        object0: palette(object0),
+        object1: palette(object1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{DebugCartridge, EmulatedCartridge, NullCartridge};
+    use crate::instance::Emulator;
+    use crate::instance::tests::TestEmulator;
+    use crate::memory::BootROM;
+    use crate::memory::free_boot_rom::FREE_CGB_BOOT_ROM;
+
+    const BGP: u16 = 0xFF47;
+    const KEY0: u16 = 0xFF4C;
+    const OPRI: u16 = 0xFF6C;
+
+    /// Cartridge with only a header for a game licensed by Nintendo.
+    struct HeaderCartridge {
+        title: &'static [u8],
+        cgb_flag: u8
+    }
+    impl DebugCartridge for HeaderCartridge {
+        fn rom_bank_size(&self) -> Option<usize> {
+            None
+        }
+
+        fn rom_bank(&self) -> Option<usize> {
+            None
+        }
+
+        fn rom_data(&self) -> Option<&[u8]> {
+            None
+        }
+
+        fn ram_bank_size(&self) -> Option<usize> {
+            None
+        }
+
+        fn ram_bank(&self) -> Option<usize> {
+            None
+        }
+
+        fn ram_data(&self) -> Option<&[u8]> {
+            None
+        }
+
+        fn ram_data_mut(&mut self) -> Option<&mut [u8]> {
+            None
+        }
+    }
+    impl InstantMemory for HeaderCartridge {
+        fn read(&mut self, address: u16) -> u8 {
+            match address {
+                0x0134..=0x0142 => self.title.get(address as usize - 0x134).copied().unwrap_or(0x00),
+                0x0143 => self.cgb_flag,
+                0x014B => 0x01,
+                _ => 0x00
+            }
+        }
+        fn write(&mut self, _address: u16, _data: u8) {}
+    }
+
+    #[test]
+    fn dmg_boot_leaves_bgp_set() {
+        let mut emulator = TestEmulator::new_hle((), EmulatedCartridge::new(NullCartridge), (), (), Model::DMG);
+        assert_eq!(emulator.read(BGP), 0xFC);
+        assert_eq!(emulator.io.registers.key0.byte, 0x00);
+    }
+
+    #[test]
+    fn cgb_boot_selects_cgb_mode_for_cgb_cartridges() {
+        let mut emulator = Emulator::new_hle((), EmulatedCartridge::new(HeaderCartridge { title: b"TEST", cgb_flag: 0x80 }), (), (), Model::CGB);
+        assert_eq!(emulator.io.registers.key0.byte & 0x0C, 0x00);
+        assert_eq!(emulator.io.registers.object_priority.byte, 0x00);
+        assert_eq!(emulator.read(BGP), 0xFC);
+        assert!(emulator.get_compatibility_palettes().is_none());
+    }
+
+    #[test]
+    fn cgb_boot_selects_dmg_compatibility_mode_for_dmg_cartridges() {
+        let mut emulator = Emulator::new_hle((), EmulatedCartridge::new(HeaderCartridge { title: b"TEST", cgb_flag: 0x00 }), (), (), Model::CGB);
+        assert_eq!(emulator.io.registers.key0.byte, 0x04);
+        assert_eq!(emulator.io.registers.object_priority.byte, 0x01);
+        assert_eq!(emulator.read(BGP), 0xFC);
+        assert_eq!(emulator.get_compatibility_palettes(), Some(&DEFAULT_COMPATIBILITY_PALETTES));
+
+        // KEY0 is locked after boot.
+        emulator.write(KEY0, 0x00);
+        assert_eq!(emulator.read(KEY0), 0xFF);
+        assert_eq!(emulator.io.registers.key0.byte, 0x04);
+        assert_eq!(emulator.read(OPRI), 0x01);
+    }
+
+    #[test]
+    fn key0_is_writable_while_the_boot_rom_is_mapped() {
+        let boot_rom = BootROM::new_cgb(FREE_CGB_BOOT_ROM, Model::CGB).unwrap();
+        let mut emulator = TestEmulator::new((), EmulatedCartridge::new(NullCartridge), (), (), boot_rom, Model::CGB);
+        emulator.write(KEY0, 0x04);
+        assert_eq!(emulator.io.registers.key0.byte, 0x04);
+        emulator.write(0xFF50, 0x01);
+        emulator.write(KEY0, 0x00);
+        assert_eq!(emulator.io.registers.key0.byte, 0x04);
+    }
+
+    fn boot_palettes(title: &'static [u8]) -> Option<CompatibilityPalettes> {
+        let emulator = Emulator::new_hle((), EmulatedCartridge::new(HeaderCartridge { title, cgb_flag: 0x00 }), (), (), Model::CGB);
+        emulator.get_compatibility_palettes().copied()
+    }
+
+    #[test]
+    fn default_palettes_are_the_first_combination() {
+        assert_eq!(palette_combination(0), DEFAULT_COMPATIBILITY_PALETTES);
+        assert_eq!(compatibility_palettes(None, b'E'), DEFAULT_COMPATIBILITY_PALETTES);
+    }
+
+    #[test]
+    fn known_titles_get_their_own_palettes() {
+        let tetris = boot_palettes(b"TETRIS").unwrap();
+        assert_eq!(tetris.background, [0x7FFF, 0x03FF, 0x001F, 0x0000]);
+        assert_eq!(tetris.object0, tetris.background);
+        assert_eq!(tetris.object1, tetris.background);
+
+        let red = boot_palettes(b"POKEMON RED").unwrap();
+        assert_eq!(red.background, [0x7FFF, 0x421F, 0x1CF2, 0x0000]);
+        assert_eq!(red.object0, [0x7FFF, 0x1BEF, 0x0200, 0x0000]);
+        assert_eq!(red.object1, [0x7FFF, 0x421F, 0x1CF2, 0x0000]);
+    }
+
+    #[test]
+    fn shared_title_hashes_need_the_fourth_letter_to_match() {
+        let blue = boot_palettes(b"POKEMON BLUE").unwrap();
+        assert_eq!(blue.background, [0x7FFF, 0x7E8C, 0x7C00, 0x0000]);
+        assert_eq!(blue.object0, [0x7FFF, 0x421F, 0x1CF2, 0x0000]);
+
+        // Same title hash, different fourth letter.
+        assert_eq!(boot_palettes(b"POKXMON BLBE"), Some(DEFAULT_COMPATIBILITY_PALETTES));
+    }
+}
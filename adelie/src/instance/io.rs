@@ -1,10 +1,17 @@
 use crate::cartridge::Cartridge;
-use crate::instance::{Model, SOC_BASE_CLOCK_SPEED, StubbedInterface};
+use crate::instance::apu::APU;
+use crate::instance::sgb::{SGBCommand, SGBPacketReceiver, SuperGameBoy};
+use crate::instance::{Buttons, Model, SOC_BASE_CLOCK_SPEED, StubbedInterface};
 use crate::memory::{BootROM, WritableByte, HighRAM, InstantMemory, NullMemory, OAM, VideoRAM, WorkRAM, Memory, BufferedInstantMemory};
+use crate::serial::SerialDevice;
+use crate::infrared::InfraredDevice;
+use crate::util::Boxed;
 
-#[derive(Copy, Clone)]
-pub struct IO<Cart: Cartridge> {
+#[derive(Clone)]
+pub struct IO<Cart: Cartridge, Serial: SerialDevice, Infrared: InfraredDevice> {
     pub cartridge: Cart,
+    pub serial_device: Serial,
+    pub infrared_device: Infrared,
     pub boot_rom: BufferedInstantMemory<BootROM>,
     pub registers: IORegisters,
     pub video_ram: BufferedInstantMemory<VideoRAM>,
@@ -14,27 +21,57 @@ pub struct IO<Cart: Cartridge> {
     pub no_access: NullMemory,
     pub model: Model,
     pub double_speed_mode: bool,
+
+    /// SNES side of the Super Game Boy, if running on one.
+    pub sgb: Option<Boxed<SuperGameBoy>>,
 }
 
-#[derive(Copy, Clone, Default)]
+#[derive(Clone)]
 pub struct IORegisters {
     pub joypad_data: BufferedInstantMemory<JoypadData>,
-    pub serial_transfer: StubbedInterface<0x00>,
+    pub serial_transfer: BufferedInstantMemory<SerialTransfer>,
     pub timer_div: BufferedInstantMemory<TimerDIV>,
     pub interrupts: BufferedInstantMemory<Interrupts>,
-    pub audio: StubbedInterface<0x00>,
-    pub wave_pattern: StubbedInterface<0x00>,
+    pub audio: BufferedInstantMemory<APU>,
     pub lcd: BufferedInstantMemory<LCDData>,
     pub oam_dma: BufferedInstantMemory<OAMDMA>,
     pub disable_bootrom: BufferedInstantMemory<DisableBootROM>,
     pub vram_dma: StubbedInterface<0x00>,
     pub bg_obj_palettes: StubbedInterface<0x00>,
     pub prepare_speed_switch: StubbedInterface<0x00>,
-    pub infrared: StubbedInterface<0b10>,
+    pub infrared: BufferedInstantMemory<InfraredCommunication>,
     pub object_priority: WritableByte<1>,
+
+    /// KEY0, where bits 2-3 select CGB mode (0) or DMG compatibility mode (4). Only the boot ROM can
+    /// write it.
+    pub key0: WritableByte<0x0C>,
+    pub undocumented: BufferedInstantMemory<UndocumentedRegisters>,
     pub unused: StubbedInterface<0xFF>
 }
 
+impl IORegisters {
+    pub fn new(model: Model) -> Self {
+        Self {
+            joypad_data: BufferedInstantMemory::new(JoypadData::new(model)),
+            serial_transfer: BufferedInstantMemory::new(SerialTransfer::new(model)),
+            timer_div: Default::default(),
+            interrupts: Default::default(),
+            audio: BufferedInstantMemory::new(APU::new(model)),
+            lcd: Default::default(),
+            oam_dma: Default::default(),
+            disable_bootrom: Default::default(),
+            vram_dma: Default::default(),
+            bg_obj_palettes: Default::default(),
+            prepare_speed_switch: Default::default(),
+            infrared: Default::default(),
+            object_priority: Default::default(),
+            key0: Default::default(),
+            undocumented: Default::default(),
+            unused: Default::default()
+        }
+    }
+}
+
 pub(crate) const CARTRIDGE_ROM_START: u16 = 0x0000;
 pub(crate) const CARTRIDGE_ROM_MAIN_BANK_END: u16 = 0x3FFF;
 pub(crate) const CARTRIDGE_ROM_END: u16 = 0x7FFF;
@@ -48,8 +85,135 @@ pub(crate) const OAM_START: u16 = 0xFE00;
 pub(crate) const OAM_END: u16 = 0xFE9F;
 pub(crate) const HRAM_START: u16 = 0xFF80;
 pub(crate) const HRAM_END: u16 = 0xFFFE;
+const DIV_ADDRESS: u16 = 0xFF04;
+
+/// KEY0 bit that selects DMG compatibility mode.
+const KEY0_DMG_COMPATIBILITY: u8 = 0x04;
+
+pub(crate) const VBLANK_INTERRUPT: u8 = 0b00001;
+pub(crate) const STAT_INTERRUPT: u8 = 0b00010;
+pub(crate) const TIMER_INTERRUPT: u8 = 0b00100;
+pub(crate) const SERIAL_INTERRUPT: u8 = 0b01000;
+pub(crate) const JOYPAD_INTERRUPT: u8 = 0b10000;
+
+impl<Cart: Cartridge, Serial: SerialDevice, Infrared: InfraredDevice> IO<Cart, Serial, Infrared> {
+    /// Advance the system counter by one SoC clock, clocking the APU frame sequencer from it.
+    pub(crate) fn tick_div(&mut self) {
+        self.registers.timer_div.memory.tick_div();
+        self.update_div_apu();
+    }
+
+    /// Pass the DIV-APU bit of the system counter to the APU frame sequencer.
+    fn update_div_apu(&mut self) {
+        let div_apu_bit = self.registers.timer_div.memory.div_apu_bit(self.double_speed_mode);
+        self.registers.audio.memory.tick_div_apu(div_apu_bit);
+    }
+
+    /// Clock the serial port by one SoC clock.
+    pub(crate) fn tick_serial(&mut self) {
+        self.serial_device.tick(self.double_speed_mode);
+        let system_counter = self.registers.timer_div.memory.system_counter();
+        if self.registers.serial_transfer.memory.tick(system_counter, &mut self.serial_device) {
+            self.registers.interrupts.memory.interrupt_requested |= SERIAL_INTERRUPT;
+        }
+    }
+
+    /// Raise the joypad interrupt if any of P10-P13 went low.
+    ///
+    /// Returns `true` if one did, which also wakes the CPU from STOP regardless of IE.
+    pub(crate) fn tick_joypad(&mut self) -> bool {
+        let falling_edge = self.registers.joypad_data.memory.take_falling_edge();
+        if falling_edge {
+            self.registers.interrupts.memory.interrupt_requested |= JOYPAD_INTERRUPT;
+        }
+        falling_edge
+    }
+
+    /// Exchange light with the infrared device for one SoC clock.
+    pub(crate) fn tick_infrared(&mut self) {
+        let infrared = &mut self.registers.infrared.memory;
+        infrared.receiving = self.infrared_device.exchange_light(infrared.emitting);
+    }
+
+    /// Run the last SGB command the game sent through P1.
+    pub(crate) fn tick_sgb(&mut self) {
+        let Some(sgb) = self.sgb.as_mut() else {
+            return
+        };
+        let joypad = &mut self.registers.joypad_data.memory;
+        if let Some(command) = joypad.take_sgb_command() {
+            match command.multiplayer_request() {
+                Some(players) => joypad.set_sgb_players(players),
+                None => sgb.execute(&command)
+            }
+        }
+    }
+
+    /// Advance the LCD by one dot, requesting its interrupts.
+    pub(crate) fn tick_lcd(&mut self) -> LCDEvent {
+        let (event, stat_interrupt) = self.registers.lcd.memory.tick();
+        let interrupts = &mut self.registers.interrupts.memory;
+        if stat_interrupt {
+            interrupts.interrupt_requested |= STAT_INTERRUPT;
+        }
+        if event == LCDEvent::VBlank {
+            interrupts.interrupt_requested |= VBLANK_INTERRUPT;
+        }
+        event
+    }
+
+    /// Get the shade (after BGP) of the background at a dot of the screen.
+    ///
+    /// This is the only layer drawn so far; the window and objects are not rendered.
+    pub(crate) fn background_shade(&self, x: u8, y: u8) -> u8 {
+        let lcd = &self.registers.lcd.memory;
+
+        // LCDC bit 0 blanks the background to color 0.
+        let color = if (lcd.lcdc & 1) == 0 {
+            0
+        }
+        else {
+            let vram = &self.video_ram.memory.memory;
+            let (x, y) = (x.wrapping_add(lcd.scx) as usize, y.wrapping_add(lcd.scy) as usize);
+            let tile_map = if (lcd.lcdc & 0b1000) != 0 { 0x1C00 } else { 0x1800 };
+            let tile = vram[tile_map + (y / 8) * 32 + x / 8];
+            let tile_address = if (lcd.lcdc & 0b10000) != 0 {
+                tile as usize * 16
+            }
+            else {
+                (0x1000 + (tile as i8 as isize) * 16) as usize
+            };
+            let row = tile_address + (y % 8) * 2;
+            let bit = 7 - x % 8;
+            ((vram[row] >> bit) & 1) | (((vram[row + 1] >> bit) & 1) << 1)
+        };
+        (lcd.bgp >> (color * 2)) & 3
+    }
+
+    /// Finish a frame on the SGB, doing any pending VRAM transfer.
+    pub(crate) fn end_sgb_frame(&mut self) {
+        if let Some(sgb) = self.sgb.as_mut() {
+            sgb.end_frame(&self.video_ram.memory, self.registers.lcd.memory.lcdc);
+        }
+    }
+
+    /// Read a byte through the bus.
+    pub(crate) fn read(&mut self, address: u16) -> u8 {
+        let device = self.resolve_address_to_device(address);
+        device.set_data_lines(address, false, 0);
+        device.read_out()
+    }
+
+    /// Write a byte through the bus.
+    pub(crate) fn write(&mut self, address: u16, data: u8) {
+        self.resolve_address_to_device(address).set_data_lines(address, true, data);
+
+        // Resetting DIV can make the DIV-APU bit fall right away.
+        if address == DIV_ADDRESS {
+            self.update_div_apu();
+        }
+    }
 
-impl<Cart: Cartridge> IO<Cart> {
     fn resolve_address_to_device(&mut self, address: u16) -> &mut dyn Memory {
         // Redirect to /dev/null if OAM DMA in progress
         let is_cgb = self.model.is_cgb();
@@ -65,7 +229,9 @@ impl<Cart: Cartridge> IO<Cart> {
 
         match address {
             CARTRIDGE_ROM_START..=CARTRIDGE_ROM_END => {
-                if (self.registers.disable_bootrom.memory.byte[0] != 0) && (address < 0x100 || (address >= 0x300 && self.model.is_cgb())) {
+                // The CGB boot ROM leaves 0x100-0x1FF to the cartridge so it can read the header.
+                let boot_rom_address = address < 0x100 || (self.model.is_cgb() && (0x200..=0x8FF).contains(&address));
+                if boot_rom_address && self.registers.disable_bootrom.memory.is_mapped() {
                     &mut self.boot_rom
                 }
                 else {
@@ -88,7 +254,7 @@ impl<Cart: Cartridge> IO<Cart> {
                 0x0F        => &mut self.registers.interrupts,
                 0x10..=0x26 => &mut self.registers.audio,
                 0x27..=0x2F => &mut self.registers.unused,
-                0x30..=0x3F => &mut self.registers.wave_pattern,
+                0x30..=0x3F => &mut self.registers.audio,
                 0x46        => &mut self.registers.oam_dma,
                 0x40..=0x4B => &mut self.registers.lcd,
                 0x50        => &mut self.registers.disable_bootrom,
@@ -97,11 +263,13 @@ impl<Cart: Cartridge> IO<Cart> {
                 // Unused regardless
                 0x03        => &mut self.registers.unused,
                 0x08..=0x0E => &mut self.registers.unused,
+                0x4C if is_cgb && self.registers.disable_bootrom.memory.is_mapped() => &mut self.registers.key0,
                 0x4C        => &mut self.registers.unused,
                 0x4E        => &mut self.registers.unused,
                 0x57..=0x67 => &mut self.registers.unused,
                 0x6D..=0x6F => &mut self.registers.unused,
-                0x71..=0x7F => &mut self.registers.unused,
+                0x71        => &mut self.registers.unused,
+                0x78..=0x7F => &mut self.registers.unused,
 
                 // all registers below are CGB exclusive
                 _ if !self.model.is_cgb() => &mut self.registers.unused,
@@ -114,43 +282,368 @@ impl<Cart: Cartridge> IO<Cart> {
                 0x68..=0x6B => &mut self.registers.bg_obj_palettes,
                 0x6C        => &mut self.registers.object_priority,
                 0x70        => &mut self.work_ram.memory.bank,
+                0x74 if self.registers.key0.byte & KEY0_DMG_COMPATIBILITY != 0 => &mut self.registers.unused,
+                0x72..=0x75 => &mut self.registers.undocumented,
+                0x76 | 0x77 => &mut self.registers.audio,
             }
         }
     }
 }
 
+/// Width of the screen in dots.
+const SCREEN_WIDTH: u8 = 160;
+
+/// Dots (SoC clocks at base speed) per line.
+const DOTS_PER_LINE: u16 = 456;
+
+/// Lines per frame, including vblank.
+const LINES_PER_FRAME: u8 = 154;
+
+/// Lines drawn to the screen before vblank.
+const VISIBLE_LINES: u8 = 144;
+
+/// Dots spent in mode 2 (OAM scan) at the start of a visible line.
+const OAM_SCAN_DOTS: u16 = 80;
+
+/// Dots spent in mode 3 (drawing). This varies on hardware, but the shortest length is used here.
+const DRAWING_DOTS: u16 = 172;
+
+/// What the LCD did on a dot.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum LCDEvent {
+    None,
+
+    /// Output a dot of the screen.
+    Dot { x: u8, y: u8 },
+
+    /// Entered vblank.
+    VBlank,
+
+    /// A frame's worth of time passed with the LCD off.
+    FrameEnd
+}
+
+/// LCD registers (0xFF40-0xFF4B, except for OAM DMA) and timing.
+///
+/// While the LCD is off, frames are still timed so that frame counting keeps going, but LY stays at
+/// 0 and no interrupts are requested.
 #[derive(Copy, Clone, Default)]
 pub struct LCDData {
     pub lcdc: u8,
-    unused: u8
+
+    /// Bits 3-6 of STAT, which select the STAT interrupt sources.
+    stat_select: u8,
+
+    pub scy: u8,
+    pub scx: u8,
+    pub lyc: u8,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    pub wy: u8,
+    pub wx: u8,
+
+    /// Line being drawn (0-153).
+    line: u8,
+
+    /// Dot within the line (0-455).
+    dot: u16,
+
+    /// Last state of the STAT interrupt line, which requests the interrupt on a rising edge.
+    stat_line: bool
 }
 
 impl LCDData {
-    fn resolve_address_to_byte(&mut self, address: u16) -> &mut u8 {
-        debug_assert!(address >= 0xFF40 && address <= 0xFF4B, "{address:#04X} is not a valid address in LCD");
+    const ENABLE: u8 = 0b1000_0000;
+    const LYC_SELECT: u8 = 0b0100_0000;
+    const MODE_2_SELECT: u8 = 0b0010_0000;
+    const MODE_1_SELECT: u8 = 0b0001_0000;
+    const MODE_0_SELECT: u8 = 0b0000_1000;
+
+    /// Return `true` if the LCD is on.
+    pub(crate) fn enabled(&self) -> bool {
+        (self.lcdc & Self::ENABLE) != 0
+    }
+
+    /// Get LY, the line being drawn.
+    pub(crate) fn ly(&self) -> u8 {
+        if self.enabled() { self.line } else { 0 }
+    }
+
+    /// Get the PPU mode in the lower two bits of STAT.
+    pub(crate) fn mode(&self) -> u8 {
+        if !self.enabled() || (self.line < VISIBLE_LINES && self.dot >= OAM_SCAN_DOTS + DRAWING_DOTS) {
+            0
+        }
+        else if self.line >= VISIBLE_LINES {
+            1
+        }
+        else if self.dot < OAM_SCAN_DOTS {
+            2
+        }
+        else {
+            3
+        }
+    }
+
+    /// Advance by one dot.
+    ///
+    /// Returns the event for this dot and whether the STAT interrupt was requested.
+    pub(crate) fn tick(&mut self) -> (LCDEvent, bool) {
+        let drawing = OAM_SCAN_DOTS..OAM_SCAN_DOTS + SCREEN_WIDTH as u16;
+        let event = if self.line == VISIBLE_LINES && self.dot == 0 {
+            if self.enabled() { LCDEvent::VBlank } else { LCDEvent::FrameEnd }
+        }
+        else if self.enabled() && self.line < VISIBLE_LINES && drawing.contains(&self.dot) {
+            LCDEvent::Dot { x: (self.dot - OAM_SCAN_DOTS) as u8, y: self.line }
+        }
+        else {
+            LCDEvent::None
+        };
+
+        self.dot += 1;
+        if self.dot == DOTS_PER_LINE {
+            self.dot = 0;
+            self.line = (self.line + 1) % LINES_PER_FRAME;
+        }
+
+        let stat_line = self.stat_line();
+        let stat_interrupt = stat_line && !self.stat_line;
+        self.stat_line = stat_line;
+        (event, stat_interrupt)
+    }
+
+    /// Get the state of the STAT interrupt line, which is the OR of each selected source.
+    fn stat_line(&self) -> bool {
+        if !self.enabled() {
+            return false
+        }
+        let mode_select = match self.mode() {
+            0 => Self::MODE_0_SELECT,
+            1 => Self::MODE_1_SELECT,
+            2 => Self::MODE_2_SELECT,
+            _ => 0
+        };
+        (self.stat_select & mode_select) != 0 || ((self.stat_select & Self::LYC_SELECT) != 0 && self.line == self.lyc)
+    }
+
+    fn resolve_address_to_byte(&mut self, address: u16) -> Option<&mut u8> {
+        debug_assert!((0xFF40..=0xFF4B).contains(&address), "{address:#04X} is not a valid address in LCD");
         match (address & 0xF) as u8 {
-            0x0 => &mut self.lcdc,
-            0x1 => todo!(),
-            0x2 => todo!(),
-            0x3 => todo!(),
-            0x4 => todo!(),
-            0x5 => todo!(),
-            0x7 => todo!(),
-            0x8 => todo!(),
-            0x9 => todo!(),
-            0xA => todo!(),
-            _   => &mut self.unused, // not mapped to LCDData
+            0x0 => Some(&mut self.lcdc),
+            0x2 => Some(&mut self.scy),
+            0x3 => Some(&mut self.scx),
+            0x5 => Some(&mut self.lyc),
+            0x7 => Some(&mut self.bgp),
+            0x8 => Some(&mut self.obp0),
+            0x9 => Some(&mut self.obp1),
+            0xA => Some(&mut self.wy),
+            0xB => Some(&mut self.wx),
+            _   => None // STAT and LY are computed, and 0x6 is OAM DMA
         }
     }
 }
 
 impl InstantMemory for LCDData {
     fn read(&mut self, address: u16) -> u8 {
-        *self.resolve_address_to_byte(address)
+        match address {
+            0xFF41 => 0x80 | self.stat_select | (((self.ly() == self.lyc) as u8) << 2) | self.mode(),
+            0xFF44 => self.ly(),
+            _ => self.resolve_address_to_byte(address).map_or(0xFF, |byte| *byte)
+        }
     }
 
     fn write(&mut self, address: u16, data: u8) {
-        *self.resolve_address_to_byte(address) = data
+        match address {
+            0xFF40 => {
+                // Turning the LCD on or off starts again from the first line.
+                if ((self.lcdc ^ data) & Self::ENABLE) != 0 {
+                    self.line = 0;
+                    self.dot = 0;
+                }
+                self.lcdc = data;
+            },
+            0xFF41 => self.stat_select = data & 0b0111_1000,
+            0xFF44 => (),
+            _ => if let Some(byte) = self.resolve_address_to_byte(address) {
+                *byte = data
+            }
+        }
+    }
+}
+
+/// Serial transfer data (SB) and control (SC) registers.
+#[derive(Copy, Clone)]
+pub struct SerialTransfer {
+    data: u8,
+    control: u8,
+    cgb: bool,
+    bits_remaining: u8,
+    start_pending: bool,
+    last_clock_bit: bool
+}
+
+impl SerialTransfer {
+    const TRANSFER_ENABLE: u8 = 0b1000_0000;
+    const HIGH_SPEED: u8 = 0b0000_0010;
+    const INTERNAL_CLOCK: u8 = 0b0000_0001;
+
+    pub fn new(model: Model) -> Self {
+        Self {
+            data: 0,
+            control: 0,
+            cgb: model.is_cgb(),
+            bits_remaining: 0,
+            start_pending: false,
+            last_clock_bit: false
+        }
+    }
+
+    /// Clock the serial port, returning true if a transfer completed.
+    ///
+    /// With the internal clock, a bit is shifted on the falling edge of bit 8 of the system counter
+    /// (8192 Hz), or bit 3 (262144 Hz) with the CGB high speed bit set. Both are doubled in double
+    /// speed mode since the system counter runs twice as fast.
+    pub(crate) fn tick<Serial: SerialDevice>(&mut self, system_counter: u16, device: &mut Serial) -> bool {
+        let outgoing = (self.data & 0x80) != 0;
+
+        if (self.control & Self::INTERNAL_CLOCK) == 0 {
+            self.start_pending = false;
+            return match device.poll_external_clock(outgoing, self.bits_remaining > 0) {
+                Some(incoming) if self.bits_remaining > 0 => self.shift(incoming),
+                _ => false
+            }
+        }
+
+        if self.start_pending {
+            self.start_pending = false;
+            device.begin_transfer(self.data, self.cgb && (self.control & Self::HIGH_SPEED) != 0);
+        }
+
+        let clock_bit_index = if self.cgb && (self.control & Self::HIGH_SPEED) != 0 { 3 } else { 8 };
+        let clock_bit = (system_counter >> clock_bit_index) & 1 != 0;
+        let falling_edge = self.last_clock_bit && !clock_bit;
+        self.last_clock_bit = clock_bit;
+
+        if falling_edge && self.bits_remaining > 0 {
+            let incoming = device.exchange_bit(outgoing);
+            self.shift(incoming)
+        }
+        else {
+            false
+        }
+    }
+
+    fn shift(&mut self, incoming: bool) -> bool {
+        self.data = (self.data << 1) | (incoming as u8);
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.control &= !Self::TRANSFER_ENABLE;
+            true
+        }
+        else {
+            false
+        }
+    }
+}
+
+impl InstantMemory for SerialTransfer {
+    fn read(&mut self, address: u16) -> u8 {
+        match address {
+            0xFF01 => self.data,
+            0xFF02 => self.control | if self.cgb { 0b0111_1100 } else { 0b0111_1110 },
+            _ => unreachable!()
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0xFF01 => self.data = data,
+            0xFF02 => {
+                self.control = data;
+                if (data & Self::TRANSFER_ENABLE) != 0 {
+                    self.bits_remaining = 8;
+                    self.start_pending = (data & Self::INTERNAL_CLOCK) != 0;
+                }
+                else {
+                    self.bits_remaining = 0;
+                    self.start_pending = false;
+                }
+            },
+            _ => unreachable!()
+        }
+    }
+}
+
+/// Undocumented CGB registers at 0xFF72-0xFF75.
+///
+/// These have no known function, but 0xFF72-0xFF74 are fully readable/writable, and only bits
+/// 4-6 of 0xFF75 are. 0xFF74 is only accessible in CGB mode, and reads 0xFF in DMG compatibility
+/// mode.
+#[derive(Copy, Clone, Default)]
+pub struct UndocumentedRegisters {
+    bytes: [u8; 4]
+}
+
+impl UndocumentedRegisters {
+    const FF75_MASK: u8 = 0b0111_0000;
+}
+
+impl InstantMemory for UndocumentedRegisters {
+    fn read(&mut self, address: u16) -> u8 {
+        debug_assert!((0xFF72..=0xFF75).contains(&address), "{address:#04X} is not a valid undocumented register");
+        match address {
+            0xFF75 => self.bytes[3] | !Self::FF75_MASK,
+            _ => self.bytes[(address - 0xFF72) as usize]
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0xFF75 => self.bytes[3] = data & Self::FF75_MASK,
+            _ => self.bytes[(address - 0xFF72) as usize] = data
+        }
+    }
+
+    fn get_memory(&self) -> Option<&[u8]> {
+        Some(self.bytes.as_slice())
+    }
+
+    fn get_memory_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.bytes.as_mut_slice())
+    }
+}
+
+/// CGB infrared communications port (RP) at 0xFF56.
+#[derive(Copy, Clone, Default)]
+pub struct InfraredCommunication {
+    /// Bit 0: the LED is on.
+    pub emitting: bool,
+
+    /// Bits 6-7: data read enable. Light is only sensed if both bits are set.
+    pub read_enable: u8,
+
+    /// Light is currently hitting the sensor.
+    pub receiving: bool
+}
+
+impl InfraredCommunication {
+    const UNUSED_BITS: u8 = 0b0011_1100;
+    const READ_ENABLED: u8 = 0b11;
+}
+
+impl InstantMemory for InfraredCommunication {
+    fn read(&mut self, address: u16) -> u8 {
+        debug_assert_eq!(address, 0xFF56, "{address:#04X} is not RP");
+
+        // Bit 1 is low while light is received, but reads high if reading is not enabled.
+        let sensing = self.read_enable == Self::READ_ENABLED && self.receiving;
+        (self.read_enable << 6) | Self::UNUSED_BITS | (((!sensing) as u8) << 1) | (self.emitting as u8)
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.emitting = (data & 1) != 0;
+        self.read_enable = data >> 6;
     }
 }
 
@@ -171,66 +664,189 @@ impl InstantMemory for OAMDMA {
     }
 }
 
+/// Joypad register (P1).
 #[derive(Copy, Clone, Default)]
 pub struct JoypadData {
-    pub select_buttons: bool,
+    /// P14 is low, selecting the d-pad.
     pub select_dpad: bool,
-    pub a: bool,
-    pub b: bool,
-    pub start: bool,
-    pub select: bool,
-    pub up: bool,
-    pub down: bool,
-    pub left: bool,
-    pub right: bool,
+
+    /// P15 is low, selecting the buttons.
+    pub select_buttons: bool,
+
+    /// Buttons currently held on the first joypad.
+    pub buttons: Buttons,
+
+    /// Buttons held on the second to fourth joypads of an SGB.
+    sgb_buttons: [Buttons; 3],
+
+    /// P10-P13 lines that were low when last checked.
+    low_lines: u8,
+
+    /// A line went low since the last call to [`JoypadData::take_falling_edge`].
+    falling_edge: bool,
+
+    /// Number of times P1 was read, wrapping around.
+    input_polls: u32,
+
+    /// Decodes SGB command packets, if running on an SGB.
+    sgb_packets: Option<SGBPacketReceiver>,
+
+    /// SGB command received and not yet run.
+    sgb_command: Option<SGBCommand>,
+
+    /// Number of joypads requested with MLT_REQ.
+    sgb_players: u8,
+
+    /// Joypad the SGB is currently reading (0-3).
+    sgb_player: u8,
+
+    /// P15 was selected outside of a packet, so releasing both lines moves on to the next joypad.
+    sgb_player_selected: bool
 }
 
-impl InstantMemory for JoypadData {
-    fn read(&mut self, _address: u16) -> u8 {
-        let mut result = 0b1111;
+impl JoypadData {
+    pub fn new(model: Model) -> Self {
+        Self {
+            sgb_packets: model.is_sgb().then(SGBPacketReceiver::default),
+            sgb_players: 1,
+            ..Default::default()
+        }
+    }
 
-        if self.select_dpad {
-            result |= 0b10000;
-            result &= !((self.right as u8) << 0);
-            result &= !((self.left as u8) << 1);
-            result &= !((self.up as u8) << 2);
-            result &= !((self.down as u8) << 3);
+    /// Get which of P10-P13 are pulled low by a held button on a selected line.
+    ///
+    /// With more than one SGB joypad and neither line selected, the lines give the joypad being read
+    /// instead (0xF minus its index when read).
+    fn input_lines_low(&self) -> u8 {
+        if self.sgb_players > 1 && !self.select_dpad && !self.select_buttons {
+            return self.sgb_player
         }
 
+        let buttons = match self.sgb_player {
+            0 => self.buttons,
+            n => self.sgb_buttons[n as usize - 1]
+        };
+        let mut low = 0;
+        if self.select_dpad {
+            low |= buttons.bits() >> 4;
+        }
         if self.select_buttons {
-            result |= 0b100000;
-            result &= !((self.a as u8) << 0);
-            result &= !((self.b as u8) << 1);
-            result &= !((self.select as u8) << 2);
-            result &= !((self.start as u8) << 3);
+            low |= buttons.bits() & 0xF;
         }
+        low
+    }
 
-        result
+    fn update_lines(&mut self) {
+        let low_lines = self.input_lines_low();
+        self.falling_edge |= (low_lines & !self.low_lines) != 0;
+        self.low_lines = low_lines;
+    }
+
+    pub(crate) fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = buttons;
+        self.update_lines();
+    }
+
+    /// Set the buttons held on all four SGB joypads.
+    pub(crate) fn set_sgb_buttons(&mut self, buttons: [Buttons; 4]) {
+        self.buttons = buttons[0];
+        self.sgb_buttons.copy_from_slice(&buttons[1..]);
+        self.update_lines();
+    }
+
+    /// Get the buttons held on all four SGB joypads.
+    pub(crate) fn sgb_buttons(&self) -> [Buttons; 4] {
+        [self.buttons, self.sgb_buttons[0], self.sgb_buttons[1], self.sgb_buttons[2]]
+    }
+
+    /// Get the number of joypads the SGB reads.
+    pub(crate) fn sgb_players(&self) -> u8 {
+        self.sgb_players
+    }
+
+    /// Get the number of times P1 was read, wrapping around.
+    pub(crate) fn input_polls(&self) -> u32 {
+        self.input_polls
+    }
+
+    /// Return `true` if any of P10-P13 went from high to low since the last call.
+    pub(crate) fn take_falling_edge(&mut self) -> bool {
+        core::mem::take(&mut self.falling_edge)
+    }
+
+    /// Take the last SGB command received.
+    pub(crate) fn take_sgb_command(&mut self) -> Option<SGBCommand> {
+        self.sgb_command.take()
+    }
+
+    /// Set the number of joypads the SGB reads (MLT_REQ), starting again from the first one.
+    pub(crate) fn set_sgb_players(&mut self, players: u8) {
+        self.sgb_players = players;
+        self.sgb_player = 0;
+        self.update_lines();
+    }
+}
+
+impl InstantMemory for JoypadData {
+    fn read(&mut self, _address: u16) -> u8 {
+        self.input_polls = self.input_polls.wrapping_add(1);
+
+        // Bits 6-7 are unused and read as 1.
+        0b1100_0000
+            | ((!self.select_buttons as u8) << 5)
+            | ((!self.select_dpad as u8) << 4)
+            | (!self.input_lines_low() & 0xF)
     }
 
     fn write(&mut self, _address: u16, data: u8) {
-        self.select_dpad = (data & 0b10000) != 0;
-        self.select_buttons = (data & 0b100000) != 0;
+        // The SGB moves on to the next joypad when both lines are released after P15 was selected.
+        // Bits of a packet are sent the same way, so those are not counted.
+        let released = (data & 0b110000) == 0b110000;
+        if self.sgb_players > 1 && self.sgb_player_selected && released {
+            self.sgb_player = (self.sgb_player + 1) % self.sgb_players;
+        }
+
+        self.select_dpad = (data & 0b10000) == 0;
+        self.select_buttons = (data & 0b100000) == 0;
+        self.update_lines();
+
+        let was_receiving = self.sgb_packets.as_ref().is_some_and(SGBPacketReceiver::is_receiving);
+        if let Some(command) = self.sgb_packets.as_mut().and_then(|packets| packets.write(data)) {
+            self.sgb_command = Some(command);
+        }
+        let receiving = self.sgb_packets.as_ref().is_some_and(SGBPacketReceiver::is_receiving);
+        self.sgb_player_selected = self.select_buttons && !was_receiving && !receiving;
     }
 }
 
-#[derive(Copy, Clone)]
+/// Boot ROM disable register (0xFF50).
+///
+/// The boot ROM is mapped at reset. Writing any non-zero value unmaps it until the next reset.
+#[derive(Copy, Clone, Default)]
 pub struct DisableBootROM {
+    /// Bit 0 is set once the boot ROM is unmapped.
     pub byte: [u8; 1]
 }
-impl Default for DisableBootROM {
-    fn default() -> Self {
-        Self { byte: [0; 1] }
+
+impl DisableBootROM {
+    /// Return `true` if the boot ROM is still mapped.
+    pub(crate) fn is_mapped(&self) -> bool {
+        self.byte[0] == 0
+    }
+
+    /// Unmap the boot ROM.
+    pub(crate) fn unmap(&mut self) {
+        self.byte[0] = 1;
     }
 }
 
 impl InstantMemory for DisableBootROM {
     fn read(&mut self, _address: u16) -> u8 {
-        self.byte[0]
+        0xFE | self.byte[0]
     }
     fn write(&mut self, _address: u16, data: u8) {
-        if self.byte[0] != 0 {
-            self.byte[0] = data
+        if data != 0 {
+            self.unmap()
         }
     }
     fn get_memory(&self) -> Option<&[u8]> {
@@ -241,17 +857,18 @@ impl InstantMemory for DisableBootROM {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Default)]
 pub struct TimerDIV {
-    value: [u8; 4]
+    value: [u8; 4],
+
+    /// Lower 8 bits of the system counter (DIV is the upper 8 bits).
+    div_low: u8
 }
 impl TimerDIV {
-    pub(crate) fn tick_div(&mut self, soc_clock_count: u32) {
-        let tick_div = soc_clock_count % (SOC_BASE_CLOCK_SPEED / 16384);
-        if tick_div == 0 {
-            let div = self.get_timer_counter();
-            *div = div.wrapping_add(1);
-        }
+    /// Advance the system counter by one SoC clock.
+    pub(crate) fn tick_div(&mut self) {
+        let counter = self.system_counter().wrapping_add(1);
+        [self.value[0], self.div_low] = counter.to_be_bytes();
     }
     pub(crate) fn tick_timer(&mut self, soc_clock_count: u32) -> bool {
         let control = *self.get_timer_control();
@@ -277,6 +894,30 @@ impl TimerDIV {
         }
         false
     }
+
+    /// Get the full 16-bit system counter, of which DIV is the upper 8 bits.
+    pub fn system_counter(&self) -> u16 {
+        u16::from_be_bytes([self.value[0], self.div_low])
+    }
+
+    /// Get the system counter bit that clocks the APU frame sequencer.
+    ///
+    /// This is bit 4 of DIV, or bit 5 of DIV in double speed mode so that the frame sequencer
+    /// still runs at 512 Hz.
+    pub(crate) fn div_apu_bit(&self, double_speed_mode: bool) -> bool {
+        let bit = if double_speed_mode { 13 } else { 12 };
+        (self.system_counter() >> bit) & 1 != 0
+    }
+
+    pub(crate) fn set_system_counter(&mut self, counter: u16) {
+        [self.value[0], self.div_low] = counter.to_be_bytes();
+    }
+
+    fn reset_system_counter(&mut self) {
+        self.value[0] = 0;
+        self.div_low = 0;
+    }
+
     pub fn get_div(&mut self) -> &mut u8 {
         &mut self.value[0]
     }
@@ -290,11 +931,6 @@ impl TimerDIV {
         &mut self.value[3]
     }
 }
-impl Default for TimerDIV {
-    fn default() -> Self {
-        Self { value: [0,0,0,0] }
-    }
-}
 impl InstantMemory for TimerDIV {
     fn read(&mut self, address: u16) -> u8 {
         match address & 3 {
@@ -308,7 +944,7 @@ impl InstantMemory for TimerDIV {
 
     fn write(&mut self, address: u16, data: u8) {
         match address & 3 {
-            0 => *self.get_div() = 0,              // DIV
+            0 => self.reset_system_counter(),      // DIV
             1 => (),                               // TIMA
             2 => *self.get_timer_modulo() = data,  // TMA
             3 => *self.get_timer_control() = data, // TAC
@@ -351,3 +987,308 @@ impl InstantMemory for Interrupts {
         *self.resolve_address_to_byte(address) = data
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{EmulatedCartridge, NullCartridge};
+    use crate::instance::{Emulator, Model};
+    use crate::instance::tests::{new_emulator, run, TestEmulator};
+    use crate::memory::BootROM;
+    use crate::memory::free_boot_rom::{FREE_CGB_BOOT_ROM, FREE_DMG_BOOT_ROM};
+
+    const LCDC: u16 = 0xFF40;
+    const STAT: u16 = 0xFF41;
+    const SCY: u16 = 0xFF42;
+    const LY: u16 = 0xFF44;
+    const LYC: u16 = 0xFF45;
+    const WX: u16 = 0xFF4B;
+    const IF: u16 = 0xFF0F;
+
+    const LCD_ON: u8 = 0x91;
+    const DOTS_PER_FRAME: u32 = DOTS_PER_LINE as u32 * LINES_PER_FRAME as u32;
+
+    fn take_interrupt(emulator: &mut TestEmulator, interrupt: u8) -> bool {
+        let requested = emulator.read(IF) & interrupt != 0;
+        emulator.write(IF, 0);
+        requested
+    }
+
+    /// Turn the LCD on with the given STAT interrupt sources and run to the given line and dot.
+    fn lcd_at(stat_select: u8, line: u32, dot: u32) -> TestEmulator {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        emulator.write(STAT, stat_select);
+        emulator.write(LCDC, LCD_ON);
+        run(&mut emulator, line * DOTS_PER_LINE as u32 + dot);
+        emulator.write(IF, 0);
+        emulator
+    }
+
+    #[test]
+    fn ly_and_stat_follow_lcd_timing() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        emulator.write(LYC, 2);
+        emulator.write(STAT, 0x40);
+        emulator.write(LCDC, LCD_ON);
+        assert_eq!(emulator.read(LY), 0);
+        assert_eq!(emulator.read(STAT), 0xC2);
+
+        run(&mut emulator, 80);
+        assert_eq!(emulator.read(STAT) & 3, 3);
+        run(&mut emulator, 172);
+        assert_eq!(emulator.read(STAT) & 3, 0);
+        run(&mut emulator, 456 - 252);
+        assert_eq!(emulator.read(LY), 1);
+        assert_eq!(emulator.read(IF) & STAT_INTERRUPT, 0);
+
+        run(&mut emulator, 456);
+        assert_eq!(emulator.read(LY), 2);
+        assert_eq!(emulator.read(STAT), 0xC6);
+        assert_eq!(emulator.read(IF) & STAT_INTERRUPT, STAT_INTERRUPT);
+
+        emulator.write(LCDC, 0x11);
+        assert_eq!(emulator.read(LY), 0);
+        assert_eq!(emulator.read(STAT) & 3, 0);
+    }
+
+    #[test]
+    fn modes_follow_each_line_and_frame() {
+        let mut emulator = lcd_at(0, 1, 0);
+        let mut modes = [0u16; 4];
+        for _ in 0..DOTS_PER_LINE {
+            modes[(emulator.read(STAT) & 3) as usize] += 1;
+            run(&mut emulator, 1);
+        }
+        assert_eq!(modes, [DOTS_PER_LINE - OAM_SCAN_DOTS - DRAWING_DOTS, 0, OAM_SCAN_DOTS, DRAWING_DOTS]);
+        assert_eq!(emulator.read(LY), 2);
+
+        run(&mut emulator, 142 * DOTS_PER_LINE as u32);
+        assert_eq!(emulator.read(LY), 144);
+        assert_eq!(emulator.read(STAT) & 3, 1);
+        run(&mut emulator, 10 * DOTS_PER_LINE as u32 - 1);
+        assert_eq!(emulator.read(LY), 153);
+        assert_eq!(emulator.read(STAT) & 3, 1);
+        run(&mut emulator, 1);
+        assert_eq!(emulator.read(LY), 0);
+        assert_eq!(emulator.read(STAT) & 3, 2);
+    }
+
+    #[test]
+    fn stat_interrupt_is_requested_for_each_selected_mode() {
+        // HBlank starts after OAM scan and drawing.
+        let mut emulator = lcd_at(0x08, 1, 251);
+        assert!(!take_interrupt(&mut emulator, STAT_INTERRUPT));
+        run(&mut emulator, 1);
+        assert!(take_interrupt(&mut emulator, STAT_INTERRUPT));
+
+        // OAM scan starts each visible line.
+        let mut emulator = lcd_at(0x20, 1, 455);
+        assert!(!take_interrupt(&mut emulator, STAT_INTERRUPT));
+        run(&mut emulator, 1);
+        assert!(take_interrupt(&mut emulator, STAT_INTERRUPT));
+
+        // VBlank starts on line 144.
+        let mut emulator = lcd_at(0x10, 143, 455);
+        assert!(!take_interrupt(&mut emulator, STAT_INTERRUPT));
+        run(&mut emulator, 1);
+        assert!(take_interrupt(&mut emulator, STAT_INTERRUPT));
+
+        // Unselected modes request nothing.
+        let mut emulator = lcd_at(0, 0, 0);
+        run(&mut emulator, DOTS_PER_FRAME);
+        assert!(!take_interrupt(&mut emulator, STAT_INTERRUPT));
+    }
+
+    #[test]
+    fn stat_interrupt_needs_a_rising_edge() {
+        // With HBlank and OAM scan both selected, the line stays high from one into the other.
+        let mut emulator = lcd_at(0x28, 1, 252);
+        run(&mut emulator, DOTS_PER_LINE as u32 - 252);
+        assert_eq!(emulator.read(STAT) & 3, 2);
+        assert!(!take_interrupt(&mut emulator, STAT_INTERRUPT));
+
+        // Drawing pulls it low again.
+        run(&mut emulator, (OAM_SCAN_DOTS + DRAWING_DOTS) as u32);
+        assert!(take_interrupt(&mut emulator, STAT_INTERRUPT));
+    }
+
+    #[test]
+    fn lyc_interrupt_is_requested_on_the_matching_line() {
+        let mut emulator = lcd_at(0x40, 0, 0);
+        emulator.write(LYC, 100);
+        run(&mut emulator, 100 * DOTS_PER_LINE as u32 - 1);
+        assert!(!take_interrupt(&mut emulator, STAT_INTERRUPT));
+        assert_eq!(emulator.read(STAT) & 0x04, 0);
+        run(&mut emulator, 1);
+        assert!(take_interrupt(&mut emulator, STAT_INTERRUPT));
+        assert_eq!(emulator.read(STAT) & 0x04, 0x04);
+
+        run(&mut emulator, DOTS_PER_LINE as u32);
+        assert_eq!(emulator.read(STAT) & 0x04, 0);
+        assert!(!take_interrupt(&mut emulator, STAT_INTERRUPT));
+    }
+
+    #[test]
+    fn vblank_interrupt_is_requested_after_the_last_visible_line() {
+        let mut emulator = lcd_at(0, 144, 0);
+        assert!(!take_interrupt(&mut emulator, VBLANK_INTERRUPT));
+        run(&mut emulator, 1);
+        assert!(take_interrupt(&mut emulator, VBLANK_INTERRUPT));
+
+        run(&mut emulator, DOTS_PER_FRAME - 1);
+        assert!(!take_interrupt(&mut emulator, VBLANK_INTERRUPT));
+        run(&mut emulator, 1);
+        assert!(take_interrupt(&mut emulator, VBLANK_INTERRUPT));
+    }
+
+    #[test]
+    fn lcd_off_holds_ly_and_requests_nothing() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        emulator.write(STAT, 0x78);
+        emulator.write(LYC, 0);
+        run(&mut emulator, DOTS_PER_FRAME * 2);
+        assert_eq!(emulator.read(LY), 0);
+        assert_eq!(emulator.read(STAT) & 3, 0);
+        assert!(!take_interrupt(&mut emulator, STAT_INTERRUPT | VBLANK_INTERRUPT));
+    }
+
+    #[test]
+    fn lcd_keeps_its_speed_in_double_speed_mode() {
+        let mut emulator = new_emulator((), (), Model::CGB);
+        emulator.io.double_speed_mode = true;
+        emulator.write(LCDC, LCD_ON);
+        run(&mut emulator, 2 * OAM_SCAN_DOTS as u32 - 1);
+        assert_eq!(emulator.read(STAT) & 3, 2);
+        run(&mut emulator, 1);
+        assert_eq!(emulator.read(STAT) & 3, 3);
+        run(&mut emulator, 2 * (DOTS_PER_LINE - OAM_SCAN_DOTS) as u32);
+        assert_eq!(emulator.read(LY), 1);
+    }
+
+    #[test]
+    fn lcd_registers_read_back() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        for (offset, address) in (SCY..=WX).enumerate() {
+            if matches!(address, STAT | LY | 0xFF46) {
+                continue
+            }
+            emulator.write(address, 0x10 + offset as u8);
+            assert_eq!(emulator.read(address), 0x10 + offset as u8, "{address:#06X}");
+        }
+
+        // LY is read-only, and only the interrupt selection bits of STAT are writable.
+        emulator.write(LY, 0x12);
+        assert_eq!(emulator.read(LY), 0);
+        emulator.write(LYC, 1);
+        emulator.write(STAT, 0xFF);
+        assert_eq!(emulator.read(STAT), 0xF8);
+    }
+
+    const BOOT: u16 = 0xFF50;
+
+    fn new_emulator_with_boot_rom(boot_rom: BootROM, model: Model) -> TestEmulator {
+        Emulator::new((), EmulatedCartridge::new(NullCartridge), (), (), boot_rom, model)
+    }
+
+    #[test]
+    fn boot_rom_is_mapped_at_reset() {
+        let mut emulator = new_emulator_with_boot_rom(BootROM::new_dmg(FREE_DMG_BOOT_ROM, Model::DMG).unwrap(), Model::DMG);
+        assert_eq!(emulator.read(BOOT), 0xFE);
+        for address in [0x0000, 0x0001, 0x00FE, 0x00FF] {
+            assert_eq!(emulator.read(address), FREE_DMG_BOOT_ROM[address as usize]);
+        }
+
+        // Only 0x0000-0x00FF is mapped on DMG models.
+        assert_eq!(emulator.read(0x0100), 0xFF);
+        assert_eq!(emulator.read(0x0200), 0xFF);
+    }
+
+    #[test]
+    fn writing_non_zero_unmaps_the_boot_rom_until_reset() {
+        let mut emulator = new_emulator_with_boot_rom(BootROM::new_dmg(FREE_DMG_BOOT_ROM, Model::DMG).unwrap(), Model::DMG);
+        emulator.write(BOOT, 0x00);
+        assert_eq!(emulator.read(0x0000), FREE_DMG_BOOT_ROM[0]);
+
+        emulator.write(BOOT, 0x10);
+        assert_eq!(emulator.read(BOOT), 0xFF);
+        assert_eq!(emulator.read(0x0000), 0xFF);
+
+        // Writing 0 afterwards does not map it again.
+        emulator.write(BOOT, 0x00);
+        assert_eq!(emulator.read(BOOT), 0xFF);
+        assert_eq!(emulator.read(0x0000), 0xFF);
+    }
+
+    #[test]
+    fn cgb_boot_rom_leaves_the_header_to_the_cartridge() {
+        let mut emulator = new_emulator_with_boot_rom(BootROM::new_cgb(FREE_CGB_BOOT_ROM, Model::CGB).unwrap(), Model::CGB);
+        assert_eq!(emulator.read(0x0000), FREE_CGB_BOOT_ROM[0]);
+        assert_eq!(emulator.read(0x00FF), FREE_CGB_BOOT_ROM[0xFF]);
+        for address in [0x0100, 0x0134, 0x01FF] {
+            assert_eq!(emulator.read(address), 0xFF);
+        }
+        assert_eq!(emulator.read(0x0200), FREE_CGB_BOOT_ROM[0x100]);
+        assert_eq!(emulator.read(0x08FF), FREE_CGB_BOOT_ROM[0x7FF]);
+        assert_eq!(emulator.read(0x0900), 0xFF);
+
+        emulator.write(BOOT, 0x11);
+        assert_eq!(emulator.read(0x0000), 0xFF);
+        assert_eq!(emulator.read(0x0200), 0xFF);
+    }
+
+    #[test]
+    fn free_boot_roms_end_their_first_page_by_writing_the_ff50_latch() {
+        // The last instruction before 0x0100 is LDH ($50), A with A = 1. Without a CPU, this only
+        // checks the bytes and what the latch does once written.
+        for boot_rom in [&FREE_DMG_BOOT_ROM[..], &FREE_CGB_BOOT_ROM[..]] {
+            assert_eq!(&boot_rom[0xFE..0x100], &[0xE0, 0x50]);
+        }
+
+        let mut emulator = new_emulator_with_boot_rom(BootROM::new_dmg(FREE_DMG_BOOT_ROM, Model::DMG).unwrap(), Model::DMG);
+        emulator.write(BOOT, 0x01);
+        assert_eq!(emulator.read(0x0100), 0xFF);
+        assert_eq!(emulator.read(0x0000), 0xFF);
+    }
+
+    #[test]
+    fn hle_boot_starts_with_the_boot_rom_unmapped() {
+        let mut emulator = TestEmulator::new_hle((), EmulatedCartridge::new(NullCartridge), (), (), Model::CGB);
+        assert_eq!(emulator.read(BOOT), 0xFF);
+        assert_eq!(emulator.read(0x0000), 0xFF);
+        assert_eq!(emulator.get_cpu_registers().pc, 0x0100);
+    }
+
+    #[test]
+    fn undocumented_registers_keep_only_their_writable_bits() {
+        let mut emulator = new_emulator((), (), Model::CGB);
+        for data in [0x00, 0xFF, 0xA5] {
+            for address in 0xFF72..=0xFF75 {
+                emulator.write(address, data);
+            }
+            assert_eq!(emulator.read(0xFF72), data);
+            assert_eq!(emulator.read(0xFF73), data);
+            assert_eq!(emulator.read(0xFF74), data);
+            assert_eq!(emulator.read(0xFF75), data | 0x8F);
+        }
+    }
+
+    #[test]
+    fn ff74_is_locked_in_dmg_compatibility_mode() {
+        let mut emulator = new_emulator((), (), Model::CGB);
+        emulator.io.registers.key0.byte = KEY0_DMG_COMPATIBILITY;
+        emulator.write(0xFF72, 0x12);
+        emulator.write(0xFF74, 0x34);
+        assert_eq!(emulator.read(0xFF72), 0x12);
+        assert_eq!(emulator.read(0xFF74), 0xFF);
+        assert_eq!(emulator.io.registers.undocumented.memory.bytes[2], 0x00);
+    }
+
+    #[test]
+    fn cgb_registers_are_unmapped_on_dmg() {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        for address in 0xFF72..=0xFF77 {
+            emulator.write(address, 0x00);
+            assert_eq!(emulator.read(address), 0xFF, "{address:#06X}");
+        }
+    }
+}
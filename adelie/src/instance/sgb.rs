@@ -0,0 +1,748 @@
+//! Super Game Boy functionality.
+//!
+//! Games talk to the SNES side by sending command packets through P14 and P15. The SNES colorizes
+//! the Game Boy's output with four palettes chosen per 8x8 tile of the screen, and draws a border
+//! around it.
+
+use crate::instance::{Color, SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH};
+use crate::memory::VideoRAM;
+
+/// Bytes in one packet.
+const PACKET_SIZE: usize = 16;
+
+/// Largest number of packets a command can be sent in.
+const MAX_PACKETS: usize = 7;
+
+/// Width of the Game Boy screen in tiles.
+const SCREEN_TILE_WIDTH: usize = 20;
+
+/// Height of the Game Boy screen in tiles.
+const SCREEN_TILE_HEIGHT: usize = 18;
+
+const SCREEN_WIDTH: usize = SCREEN_TILE_WIDTH * 8;
+const SCREEN_HEIGHT: usize = SCREEN_TILE_HEIGHT * 8;
+
+/// Position of the Game Boy screen within the border.
+const SCREEN_X: usize = (SGB_SCREEN_WIDTH - SCREEN_WIDTH) / 2;
+const SCREEN_Y: usize = (SGB_SCREEN_HEIGHT - SCREEN_HEIGHT) / 2;
+
+/// Width of the border tile map in tiles.
+const BORDER_MAP_WIDTH: usize = 32;
+
+/// Bytes in one 4 bpp border tile.
+const BORDER_TILE_SIZE: usize = 32;
+
+/// Number of border tiles, sent in two halves with CHR_TRN.
+const BORDER_TILE_COUNT: usize = 256;
+
+/// Border palettes 4-7 are usable.
+const BORDER_PALETTE_COUNT: usize = 4;
+
+/// Offset of the palettes in a PCT_TRN transfer, after the tile map.
+const BORDER_PALETTE_OFFSET: usize = 0x800;
+
+/// Bytes sent by a VRAM transfer.
+const TRANSFER_SIZE: usize = 0x1000;
+
+/// Number of system palettes set with PAL_TRN.
+const SYSTEM_PALETTE_COUNT: usize = 512;
+
+/// Number of attribute files set with ATTR_TRN.
+const ATTRIBUTE_FILE_COUNT: usize = 45;
+
+/// Bytes in an attribute file (2 bits per tile).
+const ATTRIBUTE_FILE_SIZE: usize = SCREEN_TILE_WIDTH * SCREEN_TILE_HEIGHT / 4;
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const ATTR_TRN: u8 = 0x15;
+const ATTR_SET: u8 = 0x16;
+const MASK_EN: u8 = 0x17;
+
+/// A command received through P1, made of one to seven packets.
+#[derive(Copy, Clone)]
+pub(crate) struct SGBCommand {
+    data: [u8; PACKET_SIZE * MAX_PACKETS]
+}
+
+impl SGBCommand {
+    /// Get the command code.
+    fn code(&self) -> u8 {
+        self.data[0] >> 3
+    }
+
+    /// Get the number of players requested if this is MLT_REQ.
+    ///
+    /// Multiplayer is handled by the joypad rather than the SNES side.
+    pub(crate) fn multiplayer_request(&self) -> Option<u8> {
+        (self.code() == MLT_REQ).then(|| [1, 2, 1, 4][(self.data[1] & 3) as usize])
+    }
+}
+
+/// Decodes command packets sent by pulsing P14 and P15.
+///
+/// Pulling both lines low starts a packet. Each bit is then sent by pulling P14 (0) or P15 (1) low
+/// and releasing both, least significant bit first. 128 bits are followed by a 0 stop bit.
+#[derive(Copy, Clone)]
+pub(crate) struct SGBPacketReceiver {
+    /// P14 and P15 as last written (bits 4 and 5).
+    lines: u8,
+
+    /// Receiving a packet after a reset pulse.
+    receiving: bool,
+
+    /// Bits of the current packet received so far.
+    bits: usize,
+
+    /// Packets of the current command received so far.
+    packets: usize,
+
+    /// Command being received.
+    command: SGBCommand
+}
+
+impl Default for SGBPacketReceiver {
+    fn default() -> Self {
+        Self {
+            lines: 0x30,
+            receiving: false,
+            bits: 0,
+            packets: 0,
+            command: SGBCommand { data: [0; PACKET_SIZE * MAX_PACKETS] }
+        }
+    }
+}
+
+impl SGBPacketReceiver {
+    /// Return `true` if a packet is being received.
+    pub(crate) fn is_receiving(&self) -> bool {
+        self.receiving
+    }
+
+    /// Handle a write to P1, returning the command once all of its packets have been received.
+    pub(crate) fn write(&mut self, data: u8) -> Option<SGBCommand> {
+        let lines = data & 0x30;
+        let previous = core::mem::replace(&mut self.lines, lines);
+
+        if lines == 0x00 {
+            self.receiving = true;
+            self.bits = 0;
+            let packet = self.packets * PACKET_SIZE;
+            self.command.data[packet..packet + PACKET_SIZE].fill(0);
+            return None
+        }
+
+        // Bits are only taken once the lines were released in between.
+        if !self.receiving || previous != 0x30 || lines == 0x30 {
+            return None
+        }
+        let bit = lines == 0x10;
+
+        // Stop bit
+        if self.bits == PACKET_SIZE * 8 {
+            self.receiving = false;
+            if bit {
+                self.packets = 0;
+                return None
+            }
+            return self.finish_packet()
+        }
+
+        if bit {
+            self.command.data[self.packets * PACKET_SIZE + self.bits / 8] |= 1 << (self.bits % 8);
+        }
+        self.bits += 1;
+        None
+    }
+
+    fn finish_packet(&mut self) -> Option<SGBCommand> {
+        self.packets += 1;
+        let length = (self.command.data[0] & 7) as usize;
+        if length == 0 {
+            self.packets = 0;
+            return None
+        }
+        if self.packets < length {
+            return None
+        }
+        self.packets = 0;
+        Some(self.command)
+    }
+}
+
+/// What the SNES shows instead of the Game Boy screen (MASK_EN).
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum SGBMask {
+    /// Show the Game Boy screen.
+    Cancel,
+
+    /// Keep showing the last frame.
+    Freeze,
+
+    /// Show black.
+    Black,
+
+    /// Show color 0.
+    Color0
+}
+
+/// VRAM transfer to do on the next frame.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum SGBTransfer {
+    Palettes,
+    Attributes,
+
+    /// Border tiles 0x00-0x7F (false) or 0x80-0xFF (true).
+    BorderTiles(bool),
+
+    /// Border tile map and palettes.
+    Border
+}
+
+/// State of the SNES side of the Super Game Boy.
+#[derive(Copy, Clone)]
+pub(crate) struct SuperGameBoy {
+    /// Palettes in RGB555. Color 0 is shared by all four.
+    palettes: [[u16; 4]; 4],
+
+    /// Palettes set with PAL_TRN, selected with PAL_SET.
+    system_palettes: [[u16; 4]; SYSTEM_PALETTE_COUNT],
+
+    /// Attribute files set with ATTR_TRN, selected with ATTR_SET or PAL_SET.
+    attribute_files: [[u8; ATTRIBUTE_FILE_SIZE]; ATTRIBUTE_FILE_COUNT],
+
+    /// Palette for each tile of the screen.
+    attributes: [u8; SCREEN_TILE_WIDTH * SCREEN_TILE_HEIGHT],
+
+    mask: SGBMask,
+
+    /// Mask to apply at the start of the next frame.
+    pending_mask: SGBMask,
+
+    transfer: Option<SGBTransfer>,
+
+    /// Shades of the last frame, kept while frozen.
+    screen: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+
+    /// Border tiles in SNES 4 bpp format.
+    border_tiles: [u8; BORDER_TILE_SIZE * BORDER_TILE_COUNT],
+
+    /// Border tile map entries: tile number (bits 0-7), palette (bits 10-12), X flip (bit 14) and
+    /// Y flip (bit 15).
+    border_map: [u16; BORDER_MAP_WIDTH * (SGB_SCREEN_HEIGHT / 8)],
+
+    /// Border palettes 4-7 in RGB555. Color 0 is transparent.
+    border_palettes: [[u16; 16]; BORDER_PALETTE_COUNT]
+}
+
+impl Default for SuperGameBoy {
+    fn default() -> Self {
+        // Grayscale until the game sets its own palettes.
+        let palette = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+        Self {
+            palettes: [palette; 4],
+            system_palettes: [[0; 4]; SYSTEM_PALETTE_COUNT],
+            attribute_files: [[0; ATTRIBUTE_FILE_SIZE]; ATTRIBUTE_FILE_COUNT],
+            attributes: [0; SCREEN_TILE_WIDTH * SCREEN_TILE_HEIGHT],
+            mask: SGBMask::Cancel,
+            pending_mask: SGBMask::Cancel,
+            transfer: None,
+            screen: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            border_tiles: [0; BORDER_TILE_SIZE * BORDER_TILE_COUNT],
+            border_map: [0; BORDER_MAP_WIDTH * (SGB_SCREEN_HEIGHT / 8)],
+            border_palettes: [[0; 16]; BORDER_PALETTE_COUNT]
+        }
+    }
+}
+
+impl SuperGameBoy {
+    /// Run a command other than MLT_REQ.
+    pub(crate) fn execute(&mut self, command: &SGBCommand) {
+        let data = &command.data;
+        match command.code() {
+            PAL01 => self.set_palette_pair(0, 1, data),
+            PAL23 => self.set_palette_pair(2, 3, data),
+            PAL03 => self.set_palette_pair(0, 3, data),
+            PAL12 => self.set_palette_pair(1, 2, data),
+            ATTR_BLK => self.attribute_blocks(data),
+            ATTR_LIN => self.attribute_lines(data),
+            ATTR_DIV => self.attribute_divide(data),
+            ATTR_CHR => self.attribute_characters(data),
+            PAL_SET => {
+                for (palette, number) in self.palettes.iter_mut().zip(data[1..9].chunks_exact(2)) {
+                    let number = u16::from_le_bytes([number[0], number[1]]) as usize % SYSTEM_PALETTE_COUNT;
+                    *palette = self.system_palettes[number];
+                }
+                self.share_color_0(self.palettes[0][0]);
+                if data[9] & 0x80 != 0 {
+                    self.apply_attribute_file(data[9] & 0x3F);
+                }
+                if data[9] & 0x40 != 0 {
+                    self.pending_mask = SGBMask::Cancel;
+                }
+            },
+            PAL_TRN => self.transfer = Some(SGBTransfer::Palettes),
+            ATTR_TRN => self.transfer = Some(SGBTransfer::Attributes),
+            CHR_TRN => self.transfer = Some(SGBTransfer::BorderTiles(data[1] & 1 != 0)),
+            PCT_TRN => self.transfer = Some(SGBTransfer::Border),
+            ATTR_SET => {
+                self.apply_attribute_file(data[1] & 0x3F);
+                if data[1] & 0x40 != 0 {
+                    self.pending_mask = SGBMask::Cancel;
+                }
+            },
+            MASK_EN => {
+                self.pending_mask = match data[1] & 3 {
+                    0 => SGBMask::Cancel,
+                    1 => SGBMask::Freeze,
+                    2 => SGBMask::Black,
+                    _ => SGBMask::Color0
+                };
+            },
+            _ => {}
+        }
+    }
+
+    /// Colorize a dot the Game Boy outputs, given its shade after BGP (0 = lightest).
+    pub(crate) fn output_dot(&mut self, x: u8, y: u8, shade: u8) -> Color {
+        let (x, y) = (x as usize, y as usize);
+        if self.mask == SGBMask::Cancel {
+            self.screen[y * SCREEN_WIDTH + x] = shade & 3;
+        }
+        rgb555_to_color(self.screen_color(x, y))
+    }
+
+    /// Get the color shown at a dot of the Game Boy screen, in RGB555.
+    fn screen_color(&self, x: usize, y: usize) -> u16 {
+        match self.mask {
+            SGBMask::Black => 0x0000,
+            SGBMask::Color0 => self.palettes[0][0],
+            SGBMask::Cancel | SGBMask::Freeze => {
+                let palette = self.attributes[(y / 8) * SCREEN_TILE_WIDTH + x / 8];
+                self.palettes[palette as usize][self.screen[y * SCREEN_WIDTH + x] as usize]
+            }
+        }
+    }
+
+    /// Get the color of the border at a dot of the SNES screen in RGB555, or `None` if transparent.
+    fn border_color(&self, x: usize, y: usize) -> Option<u16> {
+        let entry = self.border_map[(y / 8) * BORDER_MAP_WIDTH + x / 8];
+        let tile = (entry & 0xFF) as usize;
+        // Palettes 4-7 are numbered 0-3 here.
+        let palette = ((entry >> 10) & 3) as usize;
+        let column = if entry & 0x4000 != 0 { 7 - x % 8 } else { x % 8 };
+        let row = if entry & 0x8000 != 0 { 7 - y % 8 } else { y % 8 };
+
+        // Bitplanes 0 and 1 are interleaved in the first 16 bytes, and 2 and 3 in the next 16.
+        let tile_data = &self.border_tiles[tile * BORDER_TILE_SIZE..][..BORDER_TILE_SIZE];
+        let bit = 7 - column;
+        let color = [tile_data[row * 2], tile_data[row * 2 + 1], tile_data[16 + row * 2], tile_data[16 + row * 2 + 1]]
+            .iter()
+            .enumerate()
+            .fold(0, |color, (plane, byte)| color | (((byte >> bit) & 1) << plane)) as usize;
+
+        if color == 0 {
+            return None
+        }
+        Some(self.border_palettes[palette][color])
+    }
+
+    /// Render the border by itself. Transparent areas, including where the Game Boy screen goes,
+    /// show color 0.
+    pub(crate) fn render_border(&self, image: &mut [Color; SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT]) {
+        for (i, pixel) in image.iter_mut().enumerate() {
+            let (x, y) = (i % SGB_SCREEN_WIDTH, i / SGB_SCREEN_WIDTH);
+            *pixel = rgb555_to_color(self.border_color(x, y).unwrap_or(self.palettes[0][0]));
+        }
+    }
+
+    /// Render the Game Boy screen inset in the border, as the SNES displays it.
+    pub(crate) fn render_screen(&self, image: &mut [Color; SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT]) {
+        for (i, pixel) in image.iter_mut().enumerate() {
+            let (x, y) = (i % SGB_SCREEN_WIDTH, i / SGB_SCREEN_WIDTH);
+            let in_screen = (SCREEN_X..SCREEN_X + SCREEN_WIDTH).contains(&x) && (SCREEN_Y..SCREEN_Y + SCREEN_HEIGHT).contains(&y);
+            let color = match self.border_color(x, y) {
+                Some(color) => color,
+                None if in_screen => self.screen_color(x - SCREEN_X, y - SCREEN_Y),
+                None => self.palettes[0][0]
+            };
+            *pixel = rgb555_to_color(color);
+        }
+    }
+
+    /// Finish a frame, doing any pending VRAM transfer from what the Game Boy displayed.
+    pub(crate) fn end_frame(&mut self, video_ram: &VideoRAM, lcdc: u8) {
+        self.mask = self.pending_mask;
+
+        let Some(transfer) = self.transfer.take() else {
+            return
+        };
+        let data = read_transfer(video_ram, lcdc);
+        match transfer {
+            SGBTransfer::Palettes => {
+                for (palette, colors) in self.system_palettes.iter_mut().zip(data.chunks_exact(8)) {
+                    for (color, bytes) in palette.iter_mut().zip(colors.chunks_exact(2)) {
+                        *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+                    }
+                }
+            },
+            SGBTransfer::Attributes => {
+                for (file, bytes) in self.attribute_files.iter_mut().zip(data.chunks_exact(ATTRIBUTE_FILE_SIZE)) {
+                    file.copy_from_slice(bytes);
+                }
+            },
+            SGBTransfer::BorderTiles(high) => {
+                let offset = if high { TRANSFER_SIZE } else { 0 };
+                self.border_tiles[offset..offset + TRANSFER_SIZE].copy_from_slice(&data);
+            },
+            SGBTransfer::Border => {
+                for (entry, bytes) in self.border_map.iter_mut().zip(data.chunks_exact(2)) {
+                    *entry = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+                let palettes = data[BORDER_PALETTE_OFFSET..].chunks_exact(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]));
+                for (color, value) in self.border_palettes.iter_mut().flatten().zip(palettes) {
+                    *color = value;
+                }
+            }
+        }
+    }
+
+    /// Set colors 1-3 of two palettes and the shared color 0 (PAL01, PAL23, PAL03, PAL12).
+    fn set_palette_pair(&mut self, first: usize, second: usize, data: &[u8]) {
+        let color = |i: usize| u16::from_le_bytes([data[1 + i * 2], data[2 + i * 2]]);
+        self.share_color_0(color(0));
+        for i in 1..4 {
+            self.palettes[first][i] = color(i);
+            self.palettes[second][i] = color(i + 3);
+        }
+    }
+
+    fn share_color_0(&mut self, color: u16) {
+        for palette in &mut self.palettes {
+            palette[0] = color;
+        }
+    }
+
+    fn set_attribute(&mut self, x: usize, y: usize, palette: u8) {
+        if x < SCREEN_TILE_WIDTH && y < SCREEN_TILE_HEIGHT {
+            self.attributes[y * SCREEN_TILE_WIDTH + x] = palette & 3;
+        }
+    }
+
+    /// Color the inside, border and/or outside of rectangles (ATTR_BLK).
+    fn attribute_blocks(&mut self, data: &[u8]) {
+        let count = (data[1] as usize).min(18);
+        for block in data[2..].chunks_exact(6).take(count) {
+            let mut control = block[0] & 7;
+            let inside = block[1] & 3;
+            let mut border = (block[1] >> 2) & 3;
+            let outside = (block[1] >> 4) & 3;
+
+            // The border takes the palette of the inside or outside if it was not given its own.
+            if control == 0b001 {
+                control = 0b011;
+                border = inside;
+            }
+            else if control == 0b100 {
+                control = 0b110;
+                border = outside;
+            }
+
+            let (x1, y1, x2, y2) = (block[2] as usize, block[3] as usize, block[4] as usize, block[5] as usize);
+            for y in 0..SCREEN_TILE_HEIGHT {
+                for x in 0..SCREEN_TILE_WIDTH {
+                    let within = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+                    let on_border = within && (x == x1 || x == x2 || y == y1 || y == y2);
+                    if on_border {
+                        if control & 0b010 != 0 {
+                            self.set_attribute(x, y, border);
+                        }
+                    }
+                    else if within {
+                        if control & 0b001 != 0 {
+                            self.set_attribute(x, y, inside);
+                        }
+                    }
+                    else if control & 0b100 != 0 {
+                        self.set_attribute(x, y, outside);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Color whole rows or columns (ATTR_LIN).
+    fn attribute_lines(&mut self, data: &[u8]) {
+        let count = (data[1] as usize).min(data.len() - 2);
+        for &line in &data[2..2 + count] {
+            let number = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 3;
+            let horizontal = line & 0x80 != 0;
+            if horizontal {
+                for x in 0..SCREEN_TILE_WIDTH {
+                    self.set_attribute(x, number, palette);
+                }
+            }
+            else {
+                for y in 0..SCREEN_TILE_HEIGHT {
+                    self.set_attribute(number, y, palette);
+                }
+            }
+        }
+    }
+
+    /// Split the screen in two with a line in between (ATTR_DIV).
+    fn attribute_divide(&mut self, data: &[u8]) {
+        let after = data[1] & 3;
+        let before = (data[1] >> 2) & 3;
+        let on_line = (data[1] >> 4) & 3;
+        let top_bottom = data[1] & 0x40 != 0;
+        let line = data[2] as usize;
+        for y in 0..SCREEN_TILE_HEIGHT {
+            for x in 0..SCREEN_TILE_WIDTH {
+                let position = if top_bottom { y } else { x };
+                let palette = match position.cmp(&line) {
+                    core::cmp::Ordering::Less => before,
+                    core::cmp::Ordering::Equal => on_line,
+                    core::cmp::Ordering::Greater => after
+                };
+                self.set_attribute(x, y, palette);
+            }
+        }
+    }
+
+    /// Color individual tiles, 2 bits each starting from the upper bits (ATTR_CHR).
+    fn attribute_characters(&mut self, data: &[u8]) {
+        let (mut x, mut y) = (data[1] as usize, data[2] as usize);
+        let count = (u16::from_le_bytes([data[3], data[4]]) as usize).min(SCREEN_TILE_WIDTH * SCREEN_TILE_HEIGHT);
+        let top_to_bottom = data[5] & 1 != 0;
+        for i in 0..count {
+            let Some(&byte) = data.get(6 + i / 4) else {
+                break
+            };
+            let palette = (byte >> (6 - (i % 4) * 2)) & 3;
+            self.set_attribute(x, y, palette);
+
+            if top_to_bottom {
+                y += 1;
+                if y >= SCREEN_TILE_HEIGHT {
+                    y = 0;
+                    x = (x + 1) % SCREEN_TILE_WIDTH;
+                }
+            }
+            else {
+                x += 1;
+                if x >= SCREEN_TILE_WIDTH {
+                    x = 0;
+                    y = (y + 1) % SCREEN_TILE_HEIGHT;
+                }
+            }
+        }
+    }
+
+    fn apply_attribute_file(&mut self, file: u8) {
+        let Some(file) = self.attribute_files.get(file as usize) else {
+            return
+        };
+        for (i, attribute) in self.attributes.iter_mut().enumerate() {
+            *attribute = (file[i / 4] >> (6 - (i % 4) * 2)) & 3;
+        }
+    }
+}
+
+/// Read the 4 KiB the game displays for a VRAM transfer.
+///
+/// The game shows 256 tiles in order from the top left of the background, 20 per row. Their tile
+/// data is what gets sent.
+fn read_transfer(video_ram: &VideoRAM, lcdc: u8) -> [u8; TRANSFER_SIZE] {
+    let vram = &video_ram.memory;
+    let tile_map = if lcdc & 0b1000 != 0 { 0x1C00 } else { 0x1800 };
+    let unsigned_tiles = lcdc & 0b10000 != 0;
+
+    let mut data = [0u8; TRANSFER_SIZE];
+    for (i, tile_data) in data.chunks_exact_mut(16).enumerate() {
+        let tile = vram[tile_map + (i / SCREEN_TILE_WIDTH) * 32 + i % SCREEN_TILE_WIDTH];
+        let address = if unsigned_tiles {
+            tile as usize * 16
+        }
+        else {
+            (0x1000 + (tile as i8 as isize) * 16) as usize
+        };
+        tile_data.copy_from_slice(&vram[address..address + 16]);
+    }
+    data
+}
+
+/// Expand an RGB555 color to 8 bits per channel.
+fn rgb555_to_color(color: u16) -> Color {
+    let expand = |c: u16| {
+        let c = (c & 0x1F) as u8;
+        (c << 3) | (c >> 2)
+    };
+    Color { red: expand(color), green: expand(color >> 5), blue: expand(color >> 10) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::{Emulator, EmulatorCallbacks, InstantMemoryType, Model};
+    use crate::instance::tests::{fill_background, new_recording_emulator, run, SCREEN_WIDTH, SCREEN_HEIGHT};
+    use crate::cartridge::{EmulatedCartridge, NullCartridge};
+
+    const P1: u16 = 0xFF00;
+    const LCDC: u16 = 0xFF40;
+    const BGP: u16 = 0xFF47;
+    const CLOCKS_PER_FRAME: u32 = 154 * 456;
+
+    /// Send a packet through P1 as a game would.
+    pub(crate) fn send_packet<Callbacks: EmulatorCallbacks<EmulatedCartridge<NullCartridge>>>(
+        emulator: &mut Emulator<EmulatedCartridge<NullCartridge>, Callbacks>,
+        packet: &[u8; PACKET_SIZE]
+    ) {
+        emulator.write(P1, 0x00);
+        emulator.write(P1, 0x30);
+        let bits = packet.iter().flat_map(|byte| (0..8).map(move |bit| (byte >> bit) & 1 != 0));
+        for bit in bits.chain([false]) {
+            emulator.write(P1, if bit { 0x10 } else { 0x20 });
+            emulator.write(P1, 0x30);
+        }
+        run(emulator, 1);
+    }
+
+    /// Build a packet from its first bytes, padding the rest with zeros.
+    pub(crate) fn packet(bytes: &[u8]) -> [u8; PACKET_SIZE] {
+        let mut packet = [0; PACKET_SIZE];
+        packet[..bytes.len()].copy_from_slice(bytes);
+        packet
+    }
+
+    #[test]
+    fn dots_are_colorized_with_the_game_palettes() {
+        let mut emulator = new_recording_emulator(Model::SGB);
+        fill_background(&mut emulator, 3);
+        emulator.write(BGP, 0b11_10_01_00);
+        emulator.write(LCDC, 0x91);
+
+        // PAL01: color 0 = 0x7FFF, palette 0 colors 1-3 = 0x001F, 0x03E0, 0x7C00
+        send_packet(&mut emulator, &packet(&[
+            (PAL01 << 3) | 1,
+            0xFF, 0x7F,
+            0x1F, 0x00, 0xE0, 0x03, 0x00, 0x7C
+        ]));
+        run(&mut emulator, CLOCKS_PER_FRAME);
+
+        let recorder = emulator.callbacks.as_ref().unwrap();
+        assert_eq!(recorder.screen[0], rgb555_to_color(0x7C00));
+        assert_eq!(recorder.screen[SCREEN_WIDTH * SCREEN_HEIGHT - 1], rgb555_to_color(0x7C00));
+    }
+
+    #[test]
+    fn mask_applies_from_the_next_frame() {
+        let mut emulator = new_recording_emulator(Model::SGB);
+        fill_background(&mut emulator, 0);
+        emulator.write(BGP, 0b11_10_01_00);
+        emulator.write(LCDC, 0x91);
+
+        send_packet(&mut emulator, &packet(&[(MASK_EN << 3) | 1, 2]));
+        run(&mut emulator, CLOCKS_PER_FRAME);
+        let recorder = emulator.callbacks.as_ref().unwrap();
+        assert_eq!(recorder.screen[0], rgb555_to_color(0x7FFF));
+
+        run(&mut emulator, CLOCKS_PER_FRAME);
+        let recorder = emulator.callbacks.as_ref().unwrap();
+        assert_eq!(recorder.screen[0], rgb555_to_color(0x0000));
+
+        send_packet(&mut emulator, &packet(&[(MASK_EN << 3) | 1, 0]));
+        run(&mut emulator, CLOCKS_PER_FRAME * 2);
+        let recorder = emulator.callbacks.as_ref().unwrap();
+        assert_eq!(recorder.screen[0], rgb555_to_color(0x7FFF));
+    }
+
+    /// Lay out the background so that VRAM transfers send 0x8000-0x8FFF in order.
+    fn prepare_transfer<Callbacks: EmulatorCallbacks<EmulatedCartridge<NullCartridge>>>(
+        emulator: &mut Emulator<EmulatedCartridge<NullCartridge>, Callbacks>,
+        data: &[u8; TRANSFER_SIZE]
+    ) {
+        let vram = emulator.get_internal_memory_mut(InstantMemoryType::VRAM).get_memory_mut().unwrap();
+        vram[..TRANSFER_SIZE].copy_from_slice(data);
+        for i in 0..BORDER_TILE_COUNT {
+            vram[0x1800 + (i / SCREEN_TILE_WIDTH) * 32 + i % SCREEN_TILE_WIDTH] = i as u8;
+        }
+    }
+
+    #[test]
+    fn border_is_transferred_at_the_end_of_the_frame() {
+        extern crate std;
+
+        let mut emulator = new_recording_emulator(Model::SGB);
+        emulator.write(BGP, 0b11_10_01_00);
+        emulator.write(LCDC, 0x91);
+
+        // Border tile 1 is color 1 everywhere.
+        let mut data = [0u8; TRANSFER_SIZE];
+        for row in 0..8 {
+            data[BORDER_TILE_SIZE + row * 2] = 0xFF;
+        }
+        prepare_transfer(&mut emulator, &data);
+        send_packet(&mut emulator, &packet(&[(CHR_TRN << 3) | 1, 0]));
+        run(&mut emulator, CLOCKS_PER_FRAME);
+
+        // Put tile 1 with palette 4 in the top left corner, with color 1 of palette 4 being red.
+        let mut data = [0u8; TRANSFER_SIZE];
+        data[..2].copy_from_slice(&(0x0001u16 | (4 << 10)).to_le_bytes());
+        data[BORDER_PALETTE_OFFSET + 2..BORDER_PALETTE_OFFSET + 4].copy_from_slice(&0x001Fu16.to_le_bytes());
+        prepare_transfer(&mut emulator, &data);
+        send_packet(&mut emulator, &packet(&[(PCT_TRN << 3) | 1]));
+
+        let mut image = std::vec![Color::default(); SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT];
+        let image: &mut [Color; SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT] = image.as_mut_slice().try_into().unwrap();
+        assert!(emulator.render_sgb_border(image));
+        assert_eq!(image[0], rgb555_to_color(0x7FFF));
+
+        run(&mut emulator, CLOCKS_PER_FRAME);
+        emulator.render_sgb_screen(image);
+        assert_eq!(image[0], rgb555_to_color(0x001F));
+        assert_eq!(image[7 * SGB_SCREEN_WIDTH + 7], rgb555_to_color(0x001F));
+        assert_eq!(image[8], rgb555_to_color(0x7FFF));
+
+        // The Game Boy screen shows through the transparent border. Its background still holds the
+        // transfer layout, so the top left dot is color 0.
+        let screen = SCREEN_Y * SGB_SCREEN_WIDTH + SCREEN_X;
+        assert_eq!(image[screen], emulator.callbacks.as_ref().unwrap().screen[0]);
+    }
+
+    #[test]
+    fn packets_do_not_move_on_to_the_next_joypad() {
+        let mut emulator = new_recording_emulator(Model::SGB);
+        let read_player = |emulator: &mut Emulator<_, _>| {
+            emulator.write(P1, 0x30);
+            0xF - (emulator.read(P1) & 0xF)
+        };
+
+        // MLT_REQ for two joypads
+        send_packet(&mut emulator, &packet(&[(MLT_REQ << 3) | 1, 0x01]));
+        assert_eq!(read_player(&mut emulator), 0);
+
+        send_packet(&mut emulator, &packet(&[(PAL01 << 3) | 1, 0xFF, 0x7F]));
+        assert_eq!(read_player(&mut emulator), 0);
+
+        // Reading the buttons moves on to the next joypad once both lines are released.
+        for expected in [1, 0, 1] {
+            emulator.write(P1, 0x10);
+            emulator.read(P1);
+            emulator.write(P1, 0x30);
+            assert_eq!(read_player(&mut emulator), expected);
+        }
+    }
+}
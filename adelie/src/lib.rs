@@ -1,5 +1,6 @@
 #![no_std]
 #![allow(unused)] // TODO: Remove this later
+#![allow(clippy::upper_case_acronyms, clippy::enum_variant_names)]
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
@@ -11,4 +12,10 @@ extern crate std;
 pub mod memory;
 pub mod cartridge;
 pub mod instance;
+pub mod serial;
+pub mod infrared;
+#[cfg(feature = "alloc")]
+pub mod audio;
+#[cfg(feature = "alloc")]
+pub mod movie;
 mod util;
@@ -1,5 +1,10 @@
 //! Memory controller functionality.
 
+pub mod free_boot_rom;
+
+use core::fmt::{Display, Formatter};
+use crate::instance::Model;
+
 /// Trait for any memory controller.
 pub trait Memory {
     /// Set the data lines.
@@ -195,7 +200,7 @@ pub struct OAM {
 impl OAM {
     #[inline(always)]
     fn resolve_address_to_byte(&mut self, address: u16) -> &mut u8 {
-        debug_assert!(address >= 0xFE00 && address <= 0xFE9F, "address {address:#04X} is not in OAM");
+        debug_assert!((0xFE00..=0xFE9F).contains(&address), "address {address:#04X} is not in OAM");
         &mut self.memory[(address & 0xFF) as usize]
     }
 }
@@ -234,7 +239,7 @@ pub struct HighRAM {
 impl HighRAM {
     #[inline(always)]
     fn resolve_address_to_byte(&mut self, address: u16) -> &mut u8 {
-        debug_assert!(address >= 0xFF80 && address < 0xFFFF, "address {address:#04X} is not in HRAM");
+        debug_assert!((0xFF80..0xFFFF).contains(&address), "address {address:#04X} is not in HRAM");
         &mut self.memory[(address & 0x7F) as usize]
     }
 }
@@ -276,12 +281,52 @@ impl Memory for NullMemory {
 
 const BOOT_ROM_LOW_SIZE: usize = 256;
 const BOOT_ROM_HIGH_SIZE: usize = 1792;
-const BOOT_ROM_PADDING: usize = 512; // used for the ROM
+const BOOT_ROM_PADDING: usize = 256; // 0x100-0x1FF, where the cartridge header is visible
 
 pub type DMGBootROM = [u8; BOOT_ROM_LOW_SIZE];
 pub type CGBBootROMPadded = [u8; BOOT_ROM_LOW_SIZE + BOOT_ROM_PADDING + BOOT_ROM_HIGH_SIZE];
 pub type CGBBootROM = [u8; BOOT_ROM_LOW_SIZE + BOOT_ROM_HIGH_SIZE];
 
+/// CRC-32 of each known boot ROM image (padded for CGB models), and what it is.
+///
+/// Models sharing a boot ROM are listed once, under [`Model::boot_rom_model`].
+const KNOWN_BOOT_ROMS: [(u32, BootROMKind); 10] = [
+    (0xC2F5CC97, BootROMKind::Dumped(Model::DMG0)),
+    (0x59C8598E, BootROMKind::Dumped(Model::DMG)),
+    (0xE6920754, BootROMKind::Dumped(Model::MGB)),
+    (0xEC8A83B9, BootROMKind::Dumped(Model::SGB)),
+    (0x53D0DD63, BootROMKind::Dumped(Model::SGB2)),
+    (0xE8EF5318, BootROMKind::Dumped(Model::CGB0)),
+    (0x41884E46, BootROMKind::Dumped(Model::CGB)),
+    (0xFFD6B1F1, BootROMKind::Dumped(Model::AGB)),
+    (0x2DD6251B, BootROMKind::FreeDMG), // free_boot_rom::FREE_DMG_BOOT_ROM
+    (0xB3F3F796, BootROMKind::FreeCGB), // free_boot_rom::FREE_CGB_BOOT_ROM
+];
+
+/// Boot ROM identified by its hash.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BootROMKind {
+    /// Dumped from the given model, or from the model it shares its boot ROM with.
+    Dumped(Model),
+
+    /// The free DMG boot ROM, which works on every DMG, MGB and SGB model.
+    FreeDMG,
+
+    /// The free CGB boot ROM, which works on every CGB and AGB model.
+    FreeCGB
+}
+
+impl BootROMKind {
+    /// Return `true` if this boot ROM can be used on `model`.
+    pub fn supports(self, model: Model) -> bool {
+        match self {
+            Self::Dumped(dumped) => dumped == model.boot_rom_model(),
+            Self::FreeDMG => model.is_dmg(),
+            Self::FreeCGB => model.is_cgb()
+        }
+    }
+}
+
 /// Mapped
 #[derive(Copy, Clone)]
 pub struct BootROM {
@@ -289,17 +334,76 @@ pub struct BootROM {
 }
 
 impl BootROM {
-    pub fn new_dmg(data: DMGBootROM) -> Self {
+    /// Load a DMG, MGB or SGB boot ROM, checking that it is the one for `model`.
+    pub fn new_dmg(data: DMGBootROM, model: Model) -> Result<Self, BootROMError> {
+        let boot_rom = Self::new_dmg_unchecked(data);
+        boot_rom.check_model(model)?;
+        Ok(boot_rom)
+    }
+
+    /// Load a CGB or AGB boot ROM as dumped (with 0x100-0x1FF included), checking that it is the one
+    /// for `model`.
+    pub fn new_cgb_padded(data: CGBBootROMPadded, model: Model) -> Result<Self, BootROMError> {
+        let boot_rom = Self::new_cgb_padded_unchecked(data);
+        boot_rom.check_model(model)?;
+        Ok(boot_rom)
+    }
+
+    /// Load a CGB or AGB boot ROM without 0x100-0x1FF, checking that it is the one for `model`.
+    ///
+    /// The image is identified as if 0x100-0x1FF were zero-filled, as in a padded image.
+    pub fn new_cgb(data: CGBBootROM, model: Model) -> Result<Self, BootROMError> {
+        let boot_rom = Self::new_cgb_unchecked(data);
+        boot_rom.check_model(model)?;
+        Ok(boot_rom)
+    }
+
+    /// Load a DMG, MGB or SGB boot ROM without identifying it, such as a custom boot ROM.
+    pub fn new_dmg_unchecked(data: DMGBootROM) -> Self {
         Self::from_low_high(data, [0u8; BOOT_ROM_HIGH_SIZE])
     }
-    pub fn new_cgb_padded(data: CGBBootROMPadded) -> Self {
+
+    /// Load a padded CGB or AGB boot ROM without identifying it, such as a custom boot ROM.
+    pub fn new_cgb_padded_unchecked(data: CGBBootROMPadded) -> Self {
         let low: [u8; BOOT_ROM_LOW_SIZE] = data[0..BOOT_ROM_LOW_SIZE].try_into().unwrap();
         let high: [u8; BOOT_ROM_HIGH_SIZE] = data[BOOT_ROM_LOW_SIZE + BOOT_ROM_PADDING..].try_into().unwrap();
         Self::from_low_high(low, high)
     }
-    pub fn new_cgb(data: CGBBootROM) -> Self {
+
+    /// Load a CGB or AGB boot ROM without identifying it, such as a custom boot ROM.
+    pub fn new_cgb_unchecked(data: CGBBootROM) -> Self {
         Self { data }
     }
+
+    /// Identify the boot ROM by its hash.
+    ///
+    /// Returns `None` if it is not a known boot ROM. The CGB boot ROM is identified as dumped from
+    /// [`Model::CGB`], though it is also accepted for [`Model::CGBE`].
+    pub fn identify(&self) -> Option<BootROMKind> {
+        let crc = self.crc32();
+        KNOWN_BOOT_ROMS.iter().find(|(known, _)| *known == crc).map(|(_, kind)| *kind)
+    }
+
+    /// Compute the CRC-32 of the boot ROM as it would be dumped: 256 bytes for DMG-type boot ROMs
+    /// (when the upper part is empty), or 2304 bytes with 0x100-0x1FF zero-filled otherwise.
+    pub fn crc32(&self) -> u32 {
+        let (low, high) = self.data.split_at(BOOT_ROM_LOW_SIZE);
+        if high.iter().all(|b| *b == 0) {
+            return crate::util::crc32(low)
+        }
+        let crc = crate::util::crc32(low);
+        let crc = crate::util::crc32_update(crc, &[0u8; BOOT_ROM_PADDING]);
+        crate::util::crc32_update(crc, high)
+    }
+
+    fn check_model(&self, model: Model) -> Result<(), BootROMError> {
+        match self.identify() {
+            Some(identified) if identified.supports(model) => Ok(()),
+            Some(identified) => Err(BootROMError::ModelMismatch { requested: model, identified }),
+            None => Err(BootROMError::Unrecognized(self.crc32()))
+        }
+    }
+
     fn from_low_high(low: [u8; BOOT_ROM_LOW_SIZE], high: [u8; BOOT_ROM_HIGH_SIZE]) -> Self {
         let mut data = [0u8; BOOT_ROM_LOW_SIZE + BOOT_ROM_HIGH_SIZE];
         data[..BOOT_ROM_LOW_SIZE].copy_from_slice(low.as_slice());
@@ -310,6 +414,24 @@ impl BootROM {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub enum BootROMError {
+    /// The hash did not match any known boot ROM.
+    Unrecognized(u32),
+
+    /// The boot ROM is for a different model.
+    ModelMismatch { requested: Model, identified: BootROMKind }
+}
+impl Display for BootROMError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Unrecognized(crc) => f.write_fmt(format_args!("Unrecognized boot ROM (CRC-32 {crc:#010X})")),
+            Self::ModelMismatch { requested, identified: BootROMKind::Dumped(identified) } => f.write_fmt(format_args!("Boot ROM is for {identified:?}, not {requested:?}")),
+            Self::ModelMismatch { requested, identified } => f.write_fmt(format_args!("Boot ROM is {identified:?}, which does not support {requested:?}")),
+        }
+    }
+}
+
 impl Default for BootROM {
     fn default() -> Self {
         Self {
@@ -326,7 +448,7 @@ impl InstantMemory for BootROM {
         if address < BOOT_ROM_LOW_SIZE {
             low[address]
         }
-        else if address >= 0x200 && address <= 0x8FF {
+        else if (0x200..=0x8FF).contains(&address) {
             high[address - 0x200]
         }
         else {
@@ -363,3 +485,39 @@ impl<const MASK: u8> Memory for WritableByte<MASK> {
         self.byte
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::free_boot_rom::{FREE_CGB_BOOT_ROM, FREE_DMG_BOOT_ROM};
+
+    #[test]
+    fn cgbe_runs_the_cgb_boot_rom() {
+        assert!(BootROMKind::Dumped(Model::CGB).supports(Model::CGBE));
+        assert!(!BootROMKind::Dumped(Model::CGB).supports(Model::CGB0));
+        assert!(!BootROMKind::Dumped(Model::DMG).supports(Model::MGB));
+    }
+
+    #[test]
+    fn free_boot_roms_run_on_their_whole_family() {
+        let dmg = BootROM::new_dmg(FREE_DMG_BOOT_ROM, Model::DMG).unwrap();
+        assert_eq!(dmg.identify(), Some(BootROMKind::FreeDMG));
+        for model in [Model::DMG0, Model::DMG, Model::MGB, Model::SGB, Model::SGB2] {
+            assert!(BootROM::new_dmg(FREE_DMG_BOOT_ROM, model).is_ok(), "{model:?}");
+            assert_eq!(
+                BootROM::new_cgb(FREE_CGB_BOOT_ROM, model).err(),
+                Some(BootROMError::ModelMismatch { requested: model, identified: BootROMKind::FreeCGB })
+            );
+        }
+
+        let cgb = BootROM::new_cgb(FREE_CGB_BOOT_ROM, Model::CGB).unwrap();
+        assert_eq!(cgb.identify(), Some(BootROMKind::FreeCGB));
+        for model in [Model::CGB0, Model::CGB, Model::CGBE, Model::AGB] {
+            assert!(BootROM::new_cgb(FREE_CGB_BOOT_ROM, model).is_ok(), "{model:?}");
+            assert_eq!(
+                BootROM::new_dmg(FREE_DMG_BOOT_ROM, model).err(),
+                Some(BootROMError::ModelMismatch { requested: model, identified: BootROMKind::FreeDMG })
+            );
+        }
+    }
+}
@@ -0,0 +1,529 @@
+//! Free boot ROMs.
+//!
+//! These are original boot ROMs that can be used in place of ones dumped from hardware. They clear
+//! VRAM, scroll a plain "ADELIE" logo down to the middle of the screen, and play a sound. Then they
+//! check the cartridge header like the original boot ROMs do: the logo at 0x104-0x133 has to match,
+//! which is checked by its 16-bit sum and XOR rather than by storing a copy, and the header checksum
+//! at 0x14D has to match 0x134-0x14C. If either check fails, the boot ROM locks up.
+//!
+//! Otherwise, the registers are left in the documented post-boot state of the DMG or CGB (the same
+//! state [`Emulator::new_hle`](crate::instance::Emulator::new_hle) sets for those models). Each can
+//! be used on any model of its family, where the registers differ slightly from that model's own
+//! boot ROM. FF50 is written at 0x00FE, so execution continues at 0x0100 with the boot ROM unmapped.
+//!
+//! The CGB boot ROM also sets KEY0, OPRI and the compatibility palettes for DMG cartridges, always
+//! using the default palettes.
+//!
+//! The source for each is in `free_boot_rom/dmg.asm` and `free_boot_rom/cgb.asm`, which can be built
+//! with RGBDS. The tests assemble them and check that they match the bytes here.
+
+use super::{CGBBootROM, DMGBootROM, BOOT_ROM_LOW_SIZE};
+
+/// Free boot ROM for the DMG family, identified as [`BootROMKind::FreeDMG`](super::BootROMKind::FreeDMG).
+pub const FREE_DMG_BOOT_ROM: DMGBootROM = build(&[(0x0000, DMG_MAIN), (0x00FE, HANDOFF)]);
+
+/// Free boot ROM for the CGB family without 0x100-0x1FF, identified as [`BootROMKind::FreeCGB`](super::BootROMKind::FreeCGB).
+pub const FREE_CGB_BOOT_ROM: CGBBootROM = build(&[(0x0000, CGB_ENTRY), (0x00FE, HANDOFF), (BOOT_ROM_LOW_SIZE, CGB_MAIN)]); // 0x0200, as the image has no 0x100-0x1FF
+
+/// Place each segment at its offset, leaving the rest zero-filled.
+const fn build<const N: usize>(segments: &[(usize, &[u8])]) -> [u8; N] {
+    let mut data = [0u8; N];
+    let mut s = 0;
+    while s < segments.len() {
+        let (offset, bytes) = segments[s];
+        let mut i = 0;
+        while i < bytes.len() {
+            data[offset + i] = bytes[i];
+            i += 1;
+        }
+        s += 1;
+    }
+    data
+}
+
+/// Last instruction of both boot ROMs, at 0x00FE.
+const HANDOFF: &[u8] = &[
+    // Handoff:
+    0xE0, 0x50,               // ldh ($50), a  ; unmap the boot ROM
+];
+
+/// DMG boot ROM code and logo, at 0x0000.
+const DMG_MAIN: &[u8] = &[
+    0x31, 0xFE, 0xFF,         // ld sp, $FFFE
+    0xAF,                     // xor a  ; clear VRAM
+    0x21, 0xFF, 0x9F,         // ld hl, $9FFF
+    // ClearVRAM:
+    0x32,                     // ld (hl-), a
+    0xCB, 0x7C,               // bit 7, h
+    0x20, 0xFB,               // jr nz, ClearVRAM
+    0x21, 0x26, 0xFF,         // ld hl, $FF26  ; power on the APU before writing to it
+    0x36, 0x80,               // ld (hl), $80  ; NR52
+    0x2E, 0x11,               // ld l, $11
+    0x36, 0x80,               // ld (hl), $80  ; NR11
+    0x2C,                     // inc l
+    0x36, 0xF3,               // ld (hl), $F3  ; NR12
+    0x2E, 0x25,               // ld l, $25
+    0x36, 0xF3,               // ld (hl), $F3  ; NR51
+    0x2D,                     // dec l
+    0x36, 0x77,               // ld (hl), $77  ; NR50
+    0x3E, 0xFC,               // ld a, $FC
+    0xE0, 0x47,               // ldh ($47), a  ; BGP
+    0x11, 0xAC, 0x00,         // ld de, Logo  ; copy the logo into tiles 1-6, using both bitplanes
+    0x21, 0x10, 0x80,         // ld hl, $8010
+    0x06, 0x30,               // ld b, $30
+    // CopyLogo:
+    0x1A,                     // ld a, (de)
+    0x13,                     // inc de
+    0x22,                     // ld (hl+), a
+    0x22,                     // ld (hl+), a
+    0x05,                     // dec b
+    0x20, 0xF9,               // jr nz, CopyLogo
+    0x21, 0x07, 0x99,         // ld hl, $9907  ; place it in the middle of the screen
+    0x3E, 0x01,               // ld a, $01
+    // PlaceLogo:
+    0x22,                     // ld (hl+), a
+    0x3C,                     // inc a
+    0xFE, 0x07,               // cp $07
+    0x20, 0xFA,               // jr nz, PlaceLogo
+    0x3E, 0x64,               // ld a, $64  ; scroll the logo in from the bottom, one line per frame
+    0x57,                     // ld d, a
+    0xE0, 0x42,               // ldh ($42), a  ; SCY
+    0x3E, 0x91,               // ld a, $91
+    0xE0, 0x40,               // ldh ($40), a  ; LCDC
+    // ScrollLogo:
+    // WaitVBlank:
+    0xF0, 0x44,               // ldh a, ($44)  ; LY
+    0xFE, 0x90,               // cp $90
+    0x20, 0xFA,               // jr nz, WaitVBlank
+    // WaitVBlankEnd:
+    0xF0, 0x44,               // ldh a, ($44)
+    0xFE, 0x90,               // cp $90
+    0x28, 0xFA,               // jr z, WaitVBlankEnd
+    0x15,                     // dec d
+    0x7A,                     // ld a, d
+    0xE0, 0x42,               // ldh ($42), a
+    0x20, 0xEE,               // jr nz, ScrollLogo
+    0x3E, 0xC1,               // ld a, $C1  ; sound
+    0xE0, 0x13,               // ldh ($13), a  ; NR13
+    0x3E, 0x87,               // ld a, $87
+    0xE0, 0x14,               // ldh ($14), a  ; NR14
+    0x21, 0x04, 0x01,         // ld hl, $0104  ; sum and XOR the cartridge's logo
+    0x11, 0x00, 0x00,         // ld de, $0000
+    0x4B,                     // ld c, e
+    0x06, 0x30,               // ld b, $30
+    // CheckLogo:
+    0x2A,                     // ld a, (hl+)
+    0x47,                     // ld b, a
+    0xA9,                     // xor c
+    0x4F,                     // ld c, a
+    0x78,                     // ld a, b
+    0x83,                     // add e
+    0x5F,                     // ld e, a
+    0x30, 0x01,               // jr nc, CheckLogoNoCarry
+    0x14,                     // inc d
+    // CheckLogoNoCarry:
+    0x7D,                     // ld a, l
+    0xFE, 0x34,               // cp $34
+    0x20, 0xF1,               // jr nz, CheckLogo
+    0x7B,                     // ld a, e
+    0xFE, 0x46,               // cp $46
+    0x20, 0x2D,               // jr nz, Lock
+    0x7A,                     // ld a, d
+    0xFE, 0x15,               // cp $15
+    0x20, 0x28,               // jr nz, Lock
+    0x79,                     // ld a, c
+    0xFE, 0x86,               // cp $86
+    0x20, 0x23,               // jr nz, Lock
+    0xAF,                     // xor a  ; header checksum over 0x134-0x14C
+    0x06, 0x19,               // ld b, $19
+    // CheckHeader:
+    0x96,                     // sub (hl)
+    0x3D,                     // dec a
+    0x23,                     // inc hl
+    0x05,                     // dec b
+    0x20, 0xFA,               // jr nz, CheckHeader
+    0xBE,                     // cp (hl)
+    0x20, 0x17,               // jr nz, Lock
+    0x7E,                     // ld a, (hl)  ; A = $01, F = Z, plus H and C if the checksum is non-zero
+    0x01, 0xB0, 0x01,         // ld bc, $01B0
+    0xA7,                     // and a
+    0x20, 0x02,               // jr nz, SetFlags
+    0x0E, 0x80,               // ld c, $80
+    // SetFlags:
+    0xC5,                     // push bc
+    0xF1,                     // pop af
+    0x01, 0x13, 0x00,         // ld bc, $0013
+    0x11, 0xD8, 0x00,         // ld de, $00D8
+    0x21, 0x4D, 0x01,         // ld hl, $014D
+    0xC3, 0xFE, 0x00,         // jp Handoff
+    // Lock:
+    0x18, 0xFE,               // jr Lock
+    // Logo: "ADELIE"
+    0x38, 0x6C, 0xC6, 0xC6, 0xFE, 0xC6, 0xC6, 0x00,
+    0xF8, 0xCC, 0xC6, 0xC6, 0xC6, 0xCC, 0xF8, 0x00,
+    0xFE, 0xC0, 0xC0, 0xFC, 0xC0, 0xC0, 0xFE, 0x00,
+    0xC0, 0xC0, 0xC0, 0xC0, 0xC0, 0xC0, 0xFE, 0x00,
+    0x7E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x7E, 0x00,
+    0xFE, 0xC0, 0xC0, 0xFC, 0xC0, 0xC0, 0xFE, 0x00,
+];
+
+/// CGB boot ROM entry point, at 0x0000.
+const CGB_ENTRY: &[u8] = &[
+    0x31, 0xFE, 0xFF,         // ld sp, $FFFE
+    0xC3, 0x00, 0x02,         // jp Main
+];
+
+/// CGB boot ROM code and data, at 0x0200.
+const CGB_MAIN: &[u8] = &[
+    // Main:
+    0x3E, 0x01,               // ld a, $01  ; clear both VRAM banks
+    0xE0, 0x4F,               // ldh ($4F), a  ; VBK
+    0xCD, 0x12, 0x03,         // call ClearVRAM
+    0xAF,                     // xor a
+    0xE0, 0x4F,               // ldh ($4F), a
+    0xCD, 0x12, 0x03,         // call ClearVRAM
+    0x21, 0x26, 0xFF,         // ld hl, $FF26  ; power on the APU before writing to it
+    0x36, 0x80,               // ld (hl), $80  ; NR52
+    0x2E, 0x11,               // ld l, $11
+    0x36, 0x80,               // ld (hl), $80  ; NR11
+    0x2C,                     // inc l
+    0x36, 0xF3,               // ld (hl), $F3  ; NR12
+    0x2E, 0x25,               // ld l, $25
+    0x36, 0xF3,               // ld (hl), $F3  ; NR51
+    0x2D,                     // dec l
+    0x36, 0x77,               // ld (hl), $77  ; NR50
+    0x3E, 0xFC,               // ld a, $FC
+    0xE0, 0x47,               // ldh ($47), a  ; BGP
+    0x3E, 0x80,               // ld a, $80  ; background palette 0, used for the logo
+    0xE0, 0x68,               // ldh ($68), a  ; BCPS
+    0x21, 0x22, 0x03,         // ld hl, BackgroundPalette
+    0x06, 0x08,               // ld b, $08
+    0x0E, 0x69,               // ld c, $69  ; BCPD
+    0xCD, 0x1C, 0x03,         // call CopyPalette
+    0x11, 0x3A, 0x03,         // ld de, Logo  ; copy the logo into tiles 1-6, using both bitplanes
+    0x21, 0x10, 0x80,         // ld hl, $8010
+    0x06, 0x30,               // ld b, $30
+    // CopyLogo:
+    0x1A,                     // ld a, (de)
+    0x13,                     // inc de
+    0x22,                     // ld (hl+), a
+    0x22,                     // ld (hl+), a
+    0x05,                     // dec b
+    0x20, 0xF9,               // jr nz, CopyLogo
+    0x21, 0x07, 0x99,         // ld hl, $9907  ; place it in the middle of the screen
+    0x3E, 0x01,               // ld a, $01
+    // PlaceLogo:
+    0x22,                     // ld (hl+), a
+    0x3C,                     // inc a
+    0xFE, 0x07,               // cp $07
+    0x20, 0xFA,               // jr nz, PlaceLogo
+    0x3E, 0x64,               // ld a, $64  ; scroll the logo in from the bottom, one line per frame
+    0x57,                     // ld d, a
+    0xE0, 0x42,               // ldh ($42), a  ; SCY
+    0x3E, 0x91,               // ld a, $91
+    0xE0, 0x40,               // ldh ($40), a  ; LCDC
+    // ScrollLogo:
+    // WaitVBlank:
+    0xF0, 0x44,               // ldh a, ($44)  ; LY
+    0xFE, 0x90,               // cp $90
+    0x20, 0xFA,               // jr nz, WaitVBlank
+    // WaitVBlankEnd:
+    0xF0, 0x44,               // ldh a, ($44)
+    0xFE, 0x90,               // cp $90
+    0x28, 0xFA,               // jr z, WaitVBlankEnd
+    0x15,                     // dec d
+    0x7A,                     // ld a, d
+    0xE0, 0x42,               // ldh ($42), a
+    0x20, 0xEE,               // jr nz, ScrollLogo
+    0x3E, 0xC1,               // ld a, $C1  ; sound
+    0xE0, 0x13,               // ldh ($13), a  ; NR13
+    0x3E, 0x87,               // ld a, $87
+    0xE0, 0x14,               // ldh ($14), a  ; NR14
+    0x21, 0x04, 0x01,         // ld hl, $0104  ; sum and XOR the cartridge's logo
+    0x11, 0x00, 0x00,         // ld de, $0000
+    0x4B,                     // ld c, e
+    // CheckLogo:
+    0x2A,                     // ld a, (hl+)
+    0x47,                     // ld b, a
+    0xA9,                     // xor c
+    0x4F,                     // ld c, a
+    0x78,                     // ld a, b
+    0x83,                     // add e
+    0x5F,                     // ld e, a
+    0x30, 0x01,               // jr nc, CheckLogoNoCarry
+    0x14,                     // inc d
+    // CheckLogoNoCarry:
+    0x7D,                     // ld a, l
+    0xFE, 0x34,               // cp $34
+    0x20, 0xF1,               // jr nz, CheckLogo
+    0x7B,                     // ld a, e
+    0xFE, 0x46,               // cp $46
+    0xC2, 0x10, 0x03,         // jp nz, Lock
+    0x7A,                     // ld a, d
+    0xFE, 0x15,               // cp $15
+    0xC2, 0x10, 0x03,         // jp nz, Lock
+    0x79,                     // ld a, c
+    0xFE, 0x86,               // cp $86
+    0xC2, 0x10, 0x03,         // jp nz, Lock
+    0xAF,                     // xor a  ; header checksum over 0x134-0x14C
+    0x06, 0x19,               // ld b, $19
+    // CheckHeader:
+    0x96,                     // sub (hl)
+    0x3D,                     // dec a
+    0x23,                     // inc hl
+    0x05,                     // dec b
+    0x20, 0xFA,               // jr nz, CheckHeader
+    0xBE,                     // cp (hl)
+    0xC2, 0x10, 0x03,         // jp nz, Lock
+    0xFA, 0x43, 0x01,         // ld a, ($0143)  ; CGB flag
+    0xCB, 0x7F,               // bit 7, a
+    0x28, 0x1A,               // jr z, DMGMode
+    0xE0, 0x4C,               // ldh ($4C), a  ; KEY0
+    0x3E, 0x80,               // ld a, $80  ; reset background palette 0 to white
+    0xE0, 0x68,               // ldh ($68), a
+    0x3E, 0xFF,               // ld a, $FF
+    0x06, 0x08,               // ld b, $08
+    // ResetPalette:
+    0xE0, 0x69,               // ldh ($69), a
+    0x05,                     // dec b
+    0x20, 0xFB,               // jr nz, ResetPalette
+    0x01, 0x00, 0x00,         // ld bc, $0000
+    0x11, 0x56, 0xFF,         // ld de, $FF56
+    0x21, 0x0D, 0x00,         // ld hl, $000D
+    0x18, 0x45,               // jr Finish
+    // DMGMode:
+    0x3E, 0x04,               // ld a, $04  ; DMG compatibility mode
+    0xE0, 0x4C,               // ldh ($4C), a  ; KEY0
+    0x3E, 0x01,               // ld a, $01
+    0xE0, 0x6C,               // ldh ($6C), a  ; OPRI
+    0x3E, 0x80,               // ld a, $80  ; object palettes 0 and 1 (background palette 0 is already set)
+    0xE0, 0x6A,               // ldh ($6A), a  ; OCPS
+    0x21, 0x2A, 0x03,         // ld hl, ObjectPalettes
+    0x06, 0x10,               // ld b, $10
+    0x0E, 0x6B,               // ld c, $6B  ; OCPD
+    0xCD, 0x1C, 0x03,         // call CopyPalette
+    0x06, 0x00,               // ld b, $00  ; B = sum of the title if licensed by Nintendo
+    0xFA, 0x4B, 0x01,         // ld a, ($014B)  ; old licensee
+    0xFE, 0x01,               // cp $01
+    0x28, 0x12,               // jr z, HashTitle
+    0xFE, 0x33,               // cp $33
+    0x20, 0x1A,               // jr nz, TitleHashed
+    0xFA, 0x44, 0x01,         // ld a, ($0144)  ; new licensee
+    0xFE, 0x30,               // cp $30
+    0x20, 0x13,               // jr nz, TitleHashed
+    0xFA, 0x45, 0x01,         // ld a, ($0145)
+    0xFE, 0x31,               // cp $31
+    0x20, 0x0C,               // jr nz, TitleHashed
+    // HashTitle:
+    0x21, 0x34, 0x01,         // ld hl, $0134
+    0x0E, 0x10,               // ld c, $10
+    0xAF,                     // xor a
+    // HashTitleLoop:
+    0x86,                     // add (hl)
+    0x23,                     // inc hl
+    0x0D,                     // dec c
+    0x20, 0xFB,               // jr nz, HashTitleLoop
+    0x47,                     // ld b, a
+    // TitleHashed:
+    0x0E, 0x00,               // ld c, $00
+    0x11, 0x08, 0x00,         // ld de, $0008
+    0x21, 0x7C, 0x00,         // ld hl, $007C
+    // Finish:
+    0xAF,                     // xor a  ; F = Z
+    0x3E, 0x11,               // ld a, $11
+    0xC3, 0xFE, 0x00,         // jp Handoff
+    // Lock:
+    0x18, 0xFE,               // jr Lock
+    // ClearVRAM:
+    0xAF,                     // xor a
+    0x21, 0xFF, 0x9F,         // ld hl, $9FFF
+    // ClearVRAMLoop:
+    0x32,                     // ld (hl-), a
+    0xCB, 0x7C,               // bit 7, h
+    0x20, 0xFB,               // jr nz, ClearVRAMLoop
+    0xC9,                     // ret
+    // CopyPalette: copy B bytes from HL to the palette data port at $FF00+C
+    0x2A,                     // ld a, (hl+)
+    0xE2,                     // ldh (c), a
+    0x05,                     // dec b
+    0x20, 0xFB,               // jr nz, CopyPalette
+    0xC9,                     // ret
+    // BackgroundPalette:
+    0xFF, 0x7F, 0xEF, 0x1B, 0x80, 0x61, 0x00, 0x00,
+    // ObjectPalettes:
+    0xFF, 0x7F, 0x1F, 0x42, 0xF2, 0x1C, 0x00, 0x00,
+    0xFF, 0x7F, 0x1F, 0x42, 0xF2, 0x1C, 0x00, 0x00,
+    // Logo: "ADELIE"
+    0x38, 0x6C, 0xC6, 0xC6, 0xFE, 0xC6, 0xC6, 0x00,
+    0xF8, 0xCC, 0xC6, 0xC6, 0xC6, 0xCC, 0xF8, 0x00,
+    0xFE, 0xC0, 0xC0, 0xFC, 0xC0, 0xC0, 0xFE, 0x00,
+    0xC0, 0xC0, 0xC0, 0xC0, 0xC0, 0xC0, 0xFE, 0x00,
+    0x7E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x7E, 0x00,
+    0xFE, 0xC0, 0xC0, 0xFC, 0xC0, 0xC0, 0xFE, 0x00,
+];
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use super::super::BOOT_ROM_HIGH_SIZE;
+    use std::collections::BTreeMap;
+    use std::vec::Vec;
+
+    const DMG_SOURCE: &str = include_str!("free_boot_rom/dmg.asm");
+    const CGB_SOURCE: &str = include_str!("free_boot_rom/cgb.asm");
+
+    /// Size of an assembled image, including the cartridge header area the CGB image leaves out.
+    const IMAGE_SIZE: usize = 0x900;
+
+    const R8: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+    const R16: [&str; 4] = ["bc", "de", "hl", "sp"];
+    const STACK_R16: [&str; 4] = ["bc", "de", "hl", "af"];
+    const CONDITIONS: [&str; 4] = ["nz", "z", "nc", "c"];
+    const ALU: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+
+    /// A label used by an instruction, resolved once every label is known.
+    struct Fixup {
+        label: &'static str,
+        address: usize,
+        relative: bool
+    }
+
+    fn index_of(table: &[&str], operand: &str) -> Option<u8> {
+        table.iter().position(|r| *r == operand).map(|i| i as u8)
+    }
+
+    fn number(operand: &str) -> Option<u16> {
+        u16::from_str_radix(operand.strip_prefix('$')?, 16).ok()
+    }
+
+    fn bracketed(operand: &str) -> Option<&str> {
+        operand.strip_prefix('[')?.strip_suffix(']')
+    }
+
+    /// Assemble the subset of RGBDS syntax the free boot ROMs use.
+    ///
+    /// Panics on anything else, an undefined or duplicate label, or a relative jump out of range.
+    fn assemble(source: &'static str) -> [u8; IMAGE_SIZE] {
+        let mut image = [0u8; IMAGE_SIZE];
+        let mut address = 0;
+        let mut labels = BTreeMap::new();
+        let mut fixups = Vec::new();
+
+        for line in source.lines() {
+            let mut line = line.split(';').next().unwrap().trim();
+            if let Some(section) = line.strip_prefix("SECTION") {
+                let origin = section.split_once("ROM0[").unwrap().1.trim_end_matches(']');
+                address = number(origin).unwrap() as usize;
+                continue
+            }
+            if let Some((label, rest)) = line.split_once(':') {
+                assert!(labels.insert(label, address).is_none(), "duplicate label {label}");
+                line = rest.trim();
+            }
+            if line.is_empty() {
+                continue
+            }
+
+            let (mnemonic, operands) = line.split_once(' ').unwrap_or((line, ""));
+            let mut operands: Vec<&str> = operands.split(',').map(str::trim).filter(|o| !o.is_empty()).collect();
+            if ALU.contains(&mnemonic) && operands.len() == 2 && operands[0] == "a" {
+                operands.remove(0);
+            }
+
+            let mut label = |label: &'static str, offset: usize, relative: bool| {
+                fixups.push(Fixup { label, address: address + offset, relative });
+            };
+            let mut word = |opcode: u8, operand: &'static str| -> Vec<u8> {
+                match number(operand) {
+                    Some(value) => std::vec![opcode, value as u8, (value >> 8) as u8],
+                    None => {
+                        label(operand, 1, false);
+                        std::vec![opcode, 0, 0]
+                    }
+                }
+            };
+
+            let bytes: Vec<u8> = match (mnemonic, operands.as_slice()) {
+                ("db", bytes) => bytes.iter().map(|b| number(b).unwrap() as u8).collect(),
+                ("ret", []) => std::vec![0xC9],
+                ("ld", ["[hl+]", "a"]) => std::vec![0x22],
+                ("ld", ["a", "[hl+]"]) => std::vec![0x2A],
+                ("ld", ["[hl-]", "a"]) => std::vec![0x32],
+                ("ld", ["a", "[hl-]"]) => std::vec![0x3A],
+                ("ld", ["a", "[de]"]) => std::vec![0x1A],
+                ("ld", ["a", source]) if bracketed(source).is_some_and(|a| number(a).is_some()) => word(0xFA, bracketed(source).unwrap()),
+                ("ld", [destination, "a"]) if bracketed(destination).is_some_and(|a| number(a).is_some()) => word(0xEA, bracketed(destination).unwrap()),
+                ("ld", [destination, source]) if index_of(&R8, destination).is_some() && index_of(&R8, source).is_some() => {
+                    std::vec![0x40 | index_of(&R8, destination).unwrap() << 3 | index_of(&R8, source).unwrap()]
+                },
+                ("ld", [destination, value]) if index_of(&R8, destination).is_some() => {
+                    std::vec![0x06 | index_of(&R8, destination).unwrap() << 3, number(value).unwrap() as u8]
+                },
+                ("ld", [destination, value]) if index_of(&R16, destination).is_some() => word(0x01 | index_of(&R16, destination).unwrap() << 4, value),
+                ("ldh", ["[c]", "a"]) => std::vec![0xE2],
+                ("ldh", [destination, "a"]) => std::vec![0xE0, (number(bracketed(destination).unwrap()).unwrap() - 0xFF00) as u8],
+                ("ldh", ["a", source]) => std::vec![0xF0, (number(bracketed(source).unwrap()).unwrap() - 0xFF00) as u8],
+                ("inc", [r]) if index_of(&R8, r).is_some() => std::vec![0x04 | index_of(&R8, r).unwrap() << 3],
+                ("dec", [r]) if index_of(&R8, r).is_some() => std::vec![0x05 | index_of(&R8, r).unwrap() << 3],
+                ("inc", [r]) => std::vec![0x03 | index_of(&R16, r).unwrap() << 4],
+                ("dec", [r]) => std::vec![0x0B | index_of(&R16, r).unwrap() << 4],
+                (operation, [r]) if ALU.contains(&operation) && index_of(&R8, r).is_some() => {
+                    std::vec![0x80 | index_of(&ALU, operation).unwrap() << 3 | index_of(&R8, r).unwrap()]
+                },
+                (operation, [value]) if ALU.contains(&operation) => std::vec![0xC6 | index_of(&ALU, operation).unwrap() << 3, number(value).unwrap() as u8],
+                ("bit", [bit, r]) => std::vec![0xCB, 0x40 | bit.parse::<u8>().unwrap() << 3 | index_of(&R8, r).unwrap()],
+                ("push", [r]) => std::vec![0xC5 | index_of(&STACK_R16, r).unwrap() << 4],
+                ("pop", [r]) => std::vec![0xC1 | index_of(&STACK_R16, r).unwrap() << 4],
+                ("jr", [target]) => {
+                    label(target, 1, true);
+                    std::vec![0x18, 0]
+                },
+                ("jr", [condition, target]) => {
+                    label(target, 1, true);
+                    std::vec![0x20 | index_of(&CONDITIONS, condition).unwrap() << 3, 0]
+                },
+                ("jp", [target]) => word(0xC3, target),
+                ("jp", [condition, target]) => word(0xC2 | index_of(&CONDITIONS, condition).unwrap() << 3, target),
+                ("call", [target]) => word(0xCD, target),
+                _ => panic!("unsupported instruction: {line}")
+            };
+            image[address..address + bytes.len()].copy_from_slice(&bytes);
+            address += bytes.len();
+        }
+
+        for fixup in fixups {
+            let target = *labels.get(fixup.label).unwrap_or_else(|| panic!("undefined label {}", fixup.label));
+            if fixup.relative {
+                let offset = target as isize - (fixup.address as isize + 1);
+                assert!((-128..=127).contains(&offset), "jump to {} out of range", fixup.label);
+                image[fixup.address] = offset as i8 as u8;
+            }
+            else {
+                image[fixup.address..fixup.address + 2].copy_from_slice(&(target as u16).to_le_bytes());
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn dmg_boot_rom_matches_its_source() {
+        let image = assemble(DMG_SOURCE);
+        assert_eq!(image[..BOOT_ROM_LOW_SIZE], FREE_DMG_BOOT_ROM);
+        assert!(image[BOOT_ROM_LOW_SIZE..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn cgb_boot_rom_matches_its_source() {
+        let image = assemble(CGB_SOURCE);
+        assert_eq!(image[..BOOT_ROM_LOW_SIZE], FREE_CGB_BOOT_ROM[..BOOT_ROM_LOW_SIZE]);
+        assert!(image[BOOT_ROM_LOW_SIZE..0x200].iter().all(|b| *b == 0));
+        assert_eq!(image[0x200..0x200 + BOOT_ROM_HIGH_SIZE], FREE_CGB_BOOT_ROM[BOOT_ROM_LOW_SIZE..]);
+    }
+
+    #[test]
+    fn assembler_resolves_labels_and_relative_jumps() {
+        let image = assemble("SECTION \"Test\", ROM0[$0000]\nBack:\n    jr Back\n    jr nz, Ahead\n    jp Ahead\nAhead:\n    call Back\n");
+        assert_eq!(image[..10], [0x18, 0xFE, 0x20, 0x03, 0xC3, 0x07, 0x00, 0xCD, 0x00, 0x00]);
+    }
+}
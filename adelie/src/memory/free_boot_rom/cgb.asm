@@ -0,0 +1,204 @@
+; Free CGB boot ROM. See free_boot_rom.rs for what it does.
+;
+; Build with RGBDS:
+;     rgbasm -o cgb.o cgb.asm
+;     rgblink -x -p 0 -o cgb.bin cgb.o
+;
+; This produces a padded image with $0100-$01FF zero-filled. FREE_CGB_BOOT_ROM in free_boot_rom.rs
+; holds the assembled bytes without that range. Its tests assemble this file and check that the two
+; match, so update both together.
+
+SECTION "Entry", ROM0[$0000]
+    ld sp, $FFFE
+    jp Main
+
+SECTION "Handoff", ROM0[$00FE]
+Handoff:
+    ldh [$FF50], a       ; unmap the boot ROM
+
+SECTION "Main", ROM0[$0200]
+Main:
+    ld a, $01            ; clear both VRAM banks
+    ldh [$FF4F], a       ; VBK
+    call ClearVRAM
+    xor a
+    ldh [$FF4F], a
+    call ClearVRAM
+    ld hl, $FF26         ; power on the APU before writing to it
+    ld [hl], $80         ; NR52
+    ld l, $11
+    ld [hl], $80         ; NR11
+    inc l
+    ld [hl], $F3         ; NR12
+    ld l, $25
+    ld [hl], $F3         ; NR51
+    dec l
+    ld [hl], $77         ; NR50
+    ld a, $FC
+    ldh [$FF47], a       ; BGP
+    ld a, $80            ; background palette 0, used for the logo
+    ldh [$FF68], a       ; BCPS
+    ld hl, BackgroundPalette
+    ld b, $08
+    ld c, $69            ; BCPD
+    call CopyPalette
+    ld de, Logo          ; copy the logo into tiles 1-6, using both bitplanes
+    ld hl, $8010
+    ld b, $30
+CopyLogo:
+    ld a, [de]
+    inc de
+    ld [hl+], a
+    ld [hl+], a
+    dec b
+    jr nz, CopyLogo
+    ld hl, $9907         ; place it in the middle of the screen
+    ld a, $01
+PlaceLogo:
+    ld [hl+], a
+    inc a
+    cp $07
+    jr nz, PlaceLogo
+    ld a, $64            ; scroll the logo in from the bottom, one line per frame
+    ld d, a
+    ldh [$FF42], a       ; SCY
+    ld a, $91
+    ldh [$FF40], a       ; LCDC
+ScrollLogo:
+WaitVBlank:
+    ldh a, [$FF44]       ; LY
+    cp $90
+    jr nz, WaitVBlank
+WaitVBlankEnd:
+    ldh a, [$FF44]
+    cp $90
+    jr z, WaitVBlankEnd
+    dec d
+    ld a, d
+    ldh [$FF42], a
+    jr nz, ScrollLogo
+    ld a, $C1            ; sound
+    ldh [$FF13], a       ; NR13
+    ld a, $87
+    ldh [$FF14], a       ; NR14
+    ld hl, $0104         ; sum and XOR the cartridge's logo
+    ld de, $0000
+    ld c, e
+CheckLogo:
+    ld a, [hl+]
+    ld b, a
+    xor c
+    ld c, a
+    ld a, b
+    add a, e
+    ld e, a
+    jr nc, CheckLogoNoCarry
+    inc d
+CheckLogoNoCarry:
+    ld a, l
+    cp $34
+    jr nz, CheckLogo
+    ld a, e
+    cp $46
+    jp nz, Lock
+    ld a, d
+    cp $15
+    jp nz, Lock
+    ld a, c
+    cp $86
+    jp nz, Lock
+    xor a                ; header checksum over 0x134-0x14C
+    ld b, $19
+CheckHeader:
+    sub [hl]
+    dec a
+    inc hl
+    dec b
+    jr nz, CheckHeader
+    cp [hl]
+    jp nz, Lock
+    ld a, [$0143]        ; CGB flag
+    bit 7, a
+    jr z, DMGMode
+    ldh [$FF4C], a       ; KEY0
+    ld a, $80            ; reset background palette 0 to white
+    ldh [$FF68], a
+    ld a, $FF
+    ld b, $08
+ResetPalette:
+    ldh [$FF69], a
+    dec b
+    jr nz, ResetPalette
+    ld bc, $0000
+    ld de, $FF56
+    ld hl, $000D
+    jr Finish
+DMGMode:
+    ld a, $04            ; DMG compatibility mode
+    ldh [$FF4C], a       ; KEY0
+    ld a, $01
+    ldh [$FF6C], a       ; OPRI
+    ld a, $80            ; object palettes 0 and 1 (background palette 0 is already set)
+    ldh [$FF6A], a       ; OCPS
+    ld hl, ObjectPalettes
+    ld b, $10
+    ld c, $6B            ; OCPD
+    call CopyPalette
+    ld b, $00            ; B = sum of the title if licensed by Nintendo
+    ld a, [$014B]        ; old licensee
+    cp $01
+    jr z, HashTitle
+    cp $33
+    jr nz, TitleHashed
+    ld a, [$0144]        ; new licensee
+    cp $30
+    jr nz, TitleHashed
+    ld a, [$0145]
+    cp $31
+    jr nz, TitleHashed
+HashTitle:
+    ld hl, $0134
+    ld c, $10
+    xor a
+HashTitleLoop:
+    add a, [hl]
+    inc hl
+    dec c
+    jr nz, HashTitleLoop
+    ld b, a
+TitleHashed:
+    ld c, $00
+    ld de, $0008
+    ld hl, $007C
+Finish:
+    xor a                ; F = Z
+    ld a, $11
+    jp Handoff
+Lock:
+    jr Lock
+ClearVRAM:
+    xor a
+    ld hl, $9FFF
+ClearVRAMLoop:
+    ld [hl-], a
+    bit 7, h
+    jr nz, ClearVRAMLoop
+    ret
+CopyPalette: ; copy B bytes from HL to the palette data port at $FF00+C
+    ld a, [hl+]
+    ldh [c], a
+    dec b
+    jr nz, CopyPalette
+    ret
+BackgroundPalette:
+    db $FF, $7F, $EF, $1B, $80, $61, $00, $00
+ObjectPalettes:
+    db $FF, $7F, $1F, $42, $F2, $1C, $00, $00
+    db $FF, $7F, $1F, $42, $F2, $1C, $00, $00
+Logo: ; "ADELIE"
+    db $38, $6C, $C6, $C6, $FE, $C6, $C6, $00
+    db $F8, $CC, $C6, $C6, $C6, $CC, $F8, $00
+    db $FE, $C0, $C0, $FC, $C0, $C0, $FE, $00
+    db $C0, $C0, $C0, $C0, $C0, $C0, $FE, $00
+    db $7E, $18, $18, $18, $18, $18, $7E, $00
+    db $FE, $C0, $C0, $FC, $C0, $C0, $FE, $00
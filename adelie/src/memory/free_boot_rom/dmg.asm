@@ -0,0 +1,130 @@
+; Free DMG boot ROM. See free_boot_rom.rs for what it does.
+;
+; Build with RGBDS:
+;     rgbasm -o dmg.o dmg.asm
+;     rgblink -x -p 0 -o dmg.bin dmg.o
+;
+; FREE_DMG_BOOT_ROM in free_boot_rom.rs holds the assembled bytes. Its tests assemble this file and
+; check that the two match, so update both together.
+
+SECTION "Main", ROM0[$0000]
+    ld sp, $FFFE
+    xor a                ; clear VRAM
+    ld hl, $9FFF
+ClearVRAM:
+    ld [hl-], a
+    bit 7, h
+    jr nz, ClearVRAM
+    ld hl, $FF26         ; power on the APU before writing to it
+    ld [hl], $80         ; NR52
+    ld l, $11
+    ld [hl], $80         ; NR11
+    inc l
+    ld [hl], $F3         ; NR12
+    ld l, $25
+    ld [hl], $F3         ; NR51
+    dec l
+    ld [hl], $77         ; NR50
+    ld a, $FC
+    ldh [$FF47], a       ; BGP
+    ld de, Logo          ; copy the logo into tiles 1-6, using both bitplanes
+    ld hl, $8010
+    ld b, $30
+CopyLogo:
+    ld a, [de]
+    inc de
+    ld [hl+], a
+    ld [hl+], a
+    dec b
+    jr nz, CopyLogo
+    ld hl, $9907         ; place it in the middle of the screen
+    ld a, $01
+PlaceLogo:
+    ld [hl+], a
+    inc a
+    cp $07
+    jr nz, PlaceLogo
+    ld a, $64            ; scroll the logo in from the bottom, one line per frame
+    ld d, a
+    ldh [$FF42], a       ; SCY
+    ld a, $91
+    ldh [$FF40], a       ; LCDC
+ScrollLogo:
+WaitVBlank:
+    ldh a, [$FF44]       ; LY
+    cp $90
+    jr nz, WaitVBlank
+WaitVBlankEnd:
+    ldh a, [$FF44]
+    cp $90
+    jr z, WaitVBlankEnd
+    dec d
+    ld a, d
+    ldh [$FF42], a
+    jr nz, ScrollLogo
+    ld a, $C1            ; sound
+    ldh [$FF13], a       ; NR13
+    ld a, $87
+    ldh [$FF14], a       ; NR14
+    ld hl, $0104         ; sum and XOR the cartridge's logo
+    ld de, $0000
+    ld c, e
+    ld b, $30
+CheckLogo:
+    ld a, [hl+]
+    ld b, a
+    xor c
+    ld c, a
+    ld a, b
+    add a, e
+    ld e, a
+    jr nc, CheckLogoNoCarry
+    inc d
+CheckLogoNoCarry:
+    ld a, l
+    cp $34
+    jr nz, CheckLogo
+    ld a, e
+    cp $46
+    jr nz, Lock
+    ld a, d
+    cp $15
+    jr nz, Lock
+    ld a, c
+    cp $86
+    jr nz, Lock
+    xor a                ; header checksum over 0x134-0x14C
+    ld b, $19
+CheckHeader:
+    sub [hl]
+    dec a
+    inc hl
+    dec b
+    jr nz, CheckHeader
+    cp [hl]
+    jr nz, Lock
+    ld a, [hl]           ; A = $01, F = Z, plus H and C if the checksum is non-zero
+    ld bc, $01B0
+    and a
+    jr nz, SetFlags
+    ld c, $80
+SetFlags:
+    push bc
+    pop af
+    ld bc, $0013
+    ld de, $00D8
+    ld hl, $014D
+    jp Handoff
+Lock:
+    jr Lock
+Logo: ; "ADELIE"
+    db $38, $6C, $C6, $C6, $FE, $C6, $C6, $00
+    db $F8, $CC, $C6, $C6, $C6, $CC, $F8, $00
+    db $FE, $C0, $C0, $FC, $C0, $C0, $FE, $00
+    db $C0, $C0, $C0, $C0, $C0, $C0, $FE, $00
+    db $7E, $18, $18, $18, $18, $18, $7E, $00
+    db $FE, $C0, $C0, $FC, $C0, $C0, $FE, $00
+
+SECTION "Handoff", ROM0[$00FE]
+Handoff:
+    ldh [$FF50], a       ; unmap the boot ROM
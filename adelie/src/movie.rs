@@ -0,0 +1,491 @@
+//! Input movies for recording and replaying joypad input.
+//!
+//! Movies are stored in this format, with all integers in little endian:
+//!
+//! | Size | Field                                                             |
+//! |------|-------------------------------------------------------------------|
+//! | 4    | Magic bytes ("ADMV")                                              |
+//! | 1    | Version (2)                                                       |
+//! | 1    | Model                                                             |
+//! | 1    | Boot mode (0 = boot ROM, 1 = HLE)                                 |
+//! | 4    | Cartridge header hash (see [`Emulator::cartridge_header_hash`])   |
+//! | 1    | Granularity (0 = per frame, 1 = per input poll)                   |
+//! | 1    | Start (0 = power on, 1 = save state)                              |
+//! | 4    | Save state length (only if starting from a save state)            |
+//! | n    | Save state (only if starting from a save state)                   |
+//! | 4    | Number of inputs                                                  |
+//!
+//! Each input is then stored as one byte of [`Buttons`] and one byte that is 1 if a state hash
+//! follows as 4 more bytes, or 0 if not.
+
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use crate::cartridge::Cartridge;
+use crate::infrared::InfraredDevice;
+use crate::instance::{BootMode, Buttons, Emulator, EmulatorCallbacks, Model};
+use crate::serial::SerialDevice;
+
+const MAGIC: &[u8; 4] = b"ADMV";
+const VERSION: u8 = 2;
+
+/// How often an input is recorded.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MovieGranularity {
+    /// One input per frame.
+    Frame,
+
+    /// One input per time the game reads P1 with a line selected.
+    Poll
+}
+
+/// State the movie starts from.
+#[derive(Clone, PartialEq, Debug)]
+pub enum MovieStart {
+    /// The console is powered on.
+    PowerOn,
+
+    /// An embedded save state is loaded. Loading it is up to the frontend.
+    SaveState(Vec<u8>)
+}
+
+/// One recorded input.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MovieInput {
+    /// Buttons held for this input.
+    pub buttons: Buttons,
+
+    /// [`Emulator::state_hash`] at the time the input was applied, if recorded.
+    pub hash: Option<u32>
+}
+
+/// A recorded sequence of joypad inputs.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Movie {
+    pub model: Model,
+    pub boot_mode: BootMode,
+
+    /// [`Emulator::cartridge_header_hash`] of the recording emulator.
+    pub cartridge_header_hash: u32,
+    pub granularity: MovieGranularity,
+    pub start: MovieStart,
+    pub inputs: Vec<MovieInput>
+}
+
+impl Movie {
+    /// Create an empty movie for the model, boot mode and cartridge of `emulator`.
+    pub fn new<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart, Serial, Infrared>, Serial: SerialDevice, Infrared: InfraredDevice>(
+        emulator: &Emulator<Cart, Callbacks, Serial, Infrared>,
+        granularity: MovieGranularity,
+        start: MovieStart
+    ) -> Self {
+        Self {
+            model: emulator.get_model(),
+            boot_mode: emulator.get_boot_mode(),
+            cartridge_header_hash: emulator.cartridge_header_hash(),
+            granularity,
+            start,
+            inputs: Vec::new()
+        }
+    }
+
+    /// Encode the movie.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(model_to_byte(self.model));
+        bytes.push(match self.boot_mode {
+            BootMode::BootROM => 0,
+            BootMode::HLE => 1
+        });
+        bytes.extend_from_slice(&self.cartridge_header_hash.to_le_bytes());
+        bytes.push(match self.granularity {
+            MovieGranularity::Frame => 0,
+            MovieGranularity::Poll => 1
+        });
+        match &self.start {
+            MovieStart::PowerOn => bytes.push(0),
+            MovieStart::SaveState(state) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&(state.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(state);
+            }
+        }
+        bytes.extend_from_slice(&(self.inputs.len() as u32).to_le_bytes());
+        for input in &self.inputs {
+            bytes.push(input.buttons.bits());
+            match input.hash {
+                Some(hash) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&hash.to_le_bytes());
+                },
+                None => bytes.push(0)
+            }
+        }
+        bytes
+    }
+
+    /// Decode a movie.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MovieError> {
+        let mut reader = MovieReader { bytes };
+        if reader.read(4)? != MAGIC {
+            return Err(MovieError::InvalidMagic)
+        }
+        let version = reader.read_u8()?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version))
+        }
+        let model_byte = reader.read_u8()?;
+        let model = model_from_byte(model_byte).ok_or(MovieError::UnknownModel(model_byte))?;
+        let boot_mode = match reader.read_u8()? {
+            0 => BootMode::BootROM,
+            1 => BootMode::HLE,
+            n => return Err(MovieError::UnknownBootMode(n))
+        };
+        let cartridge_header_hash = reader.read_u32()?;
+        let granularity = match reader.read_u8()? {
+            0 => MovieGranularity::Frame,
+            1 => MovieGranularity::Poll,
+            n => return Err(MovieError::UnknownGranularity(n))
+        };
+        let start = match reader.read_u8()? {
+            0 => MovieStart::PowerOn,
+            1 => {
+                let length = reader.read_u32()? as usize;
+                MovieStart::SaveState(reader.read(length)?.to_vec())
+            },
+            n => return Err(MovieError::UnknownStart(n))
+        };
+
+        let count = reader.read_u32()? as usize;
+        let mut inputs = Vec::with_capacity(count.min(reader.bytes.len() / 2));
+        for _ in 0..count {
+            let buttons = Buttons::from_bits(reader.read_u8()?);
+            let hash = match reader.read_u8()? {
+                0 => None,
+                _ => Some(reader.read_u32()?)
+            };
+            inputs.push(MovieInput { buttons, hash });
+        }
+
+        if !reader.bytes.is_empty() {
+            return Err(MovieError::TrailingData)
+        }
+
+        Ok(Self { model, boot_mode, cartridge_header_hash, granularity, start, inputs })
+    }
+}
+
+struct MovieReader<'a> {
+    bytes: &'a [u8]
+}
+
+impl<'a> MovieReader<'a> {
+    fn read(&mut self, length: usize) -> Result<&'a [u8], MovieError> {
+        if self.bytes.len() < length {
+            return Err(MovieError::Truncated)
+        }
+        let (data, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(data)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, MovieError> {
+        Ok(self.read(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, MovieError> {
+        Ok(u32::from_le_bytes(self.read(4)?.try_into().unwrap()))
+    }
+}
+
+fn model_to_byte(model: Model) -> u8 {
+    match model {
+        Model::DMG => 0,
+        Model::CGB => 1,
+        Model::AGB => 2,
+        Model::DMG0 => 3,
+        Model::MGB => 4,
+        Model::SGB => 5,
+        Model::SGB2 => 6,
+        Model::CGB0 => 7,
+        Model::CGBE => 8
+    }
+}
+
+fn model_from_byte(byte: u8) -> Option<Model> {
+    match byte {
+        0 => Some(Model::DMG),
+        1 => Some(Model::CGB),
+        2 => Some(Model::AGB),
+        3 => Some(Model::DMG0),
+        4 => Some(Model::MGB),
+        5 => Some(Model::SGB),
+        6 => Some(Model::SGB2),
+        7 => Some(Model::CGB0),
+        8 => Some(Model::CGBE),
+        _ => None
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MovieError {
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    UnknownModel(u8),
+    UnknownBootMode(u8),
+    UnknownGranularity(u8),
+    UnknownStart(u8),
+    Truncated,
+    TrailingData,
+
+    /// The movie was recorded on a different model than the one playing it back.
+    ModelMismatch { movie: Model, emulator: Model },
+
+    /// The movie was recorded with a boot ROM and played back with HLE boot, or vice versa.
+    BootModeMismatch { movie: BootMode, emulator: BootMode },
+
+    /// The movie was recorded with a different cartridge than the one inserted.
+    CartridgeMismatch { movie: u32, emulator: u32 },
+
+    /// The state hash did not match the recording when applying the input at `index`.
+    Desync { index: usize, expected: u32, actual: u32 }
+}
+impl Display for MovieError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidMagic => f.write_str("Not a movie file"),
+            Self::UnsupportedVersion(v) => f.write_fmt(format_args!("Unsupported movie version ({v})")),
+            Self::UnknownModel(m) => f.write_fmt(format_args!("Unknown model ({m:#02X})")),
+            Self::UnknownBootMode(b) => f.write_fmt(format_args!("Unknown boot mode ({b:#02X})")),
+            Self::UnknownGranularity(g) => f.write_fmt(format_args!("Unknown granularity ({g:#02X})")),
+            Self::UnknownStart(s) => f.write_fmt(format_args!("Unknown start state ({s:#02X})")),
+            Self::Truncated => f.write_str("Movie is truncated"),
+            Self::TrailingData => f.write_str("Movie has trailing data"),
+            Self::ModelMismatch { movie, emulator } => f.write_fmt(format_args!("Movie was recorded on {movie:?}, but the emulator is {emulator:?}")),
+            Self::BootModeMismatch { movie, emulator } => f.write_fmt(format_args!("Movie was recorded with {movie:?} boot, but the emulator uses {emulator:?} boot")),
+            Self::CartridgeMismatch { movie, emulator } => f.write_fmt(format_args!("Movie was recorded with a different cartridge (header hash {movie:#010X}, inserted cartridge has {emulator:#010X})")),
+            Self::Desync { index, expected, actual } => f.write_fmt(format_args!("Desync at input {index} (expected hash {expected:#010X}, got {actual:#010X})")),
+        }
+    }
+}
+
+/// Records inputs into a [`Movie`].
+///
+/// Call [`MovieRecorder::record`] instead of [`Emulator::set_buttons`] at every frame or input poll,
+/// depending on the movie's granularity.
+pub struct MovieRecorder {
+    movie: Movie,
+    record_hashes: bool
+}
+
+impl MovieRecorder {
+    /// Start recording. If `record_hashes` is set, the state hash is stored with every input.
+    pub fn new(movie: Movie, record_hashes: bool) -> Self {
+        Self { movie, record_hashes }
+    }
+
+    /// Apply and record the buttons for the next frame or poll.
+    pub fn record<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart, Serial, Infrared>, Serial: SerialDevice, Infrared: InfraredDevice>(
+        &mut self,
+        emulator: &mut Emulator<Cart, Callbacks, Serial, Infrared>,
+        buttons: Buttons
+    ) {
+        let hash = if self.record_hashes { Some(emulator.state_hash()) } else { None };
+        emulator.set_buttons(buttons);
+        self.movie.inputs.push(MovieInput { buttons, hash });
+    }
+
+    /// Get the movie recorded so far.
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    /// Stop recording.
+    pub fn into_movie(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Replays a [`Movie`].
+///
+/// The emulator must be in the movie's start state. Call [`MoviePlayer::play`] at the same points
+/// [`MovieRecorder::record`] was called while recording.
+pub struct MoviePlayer {
+    movie: Movie,
+    position: usize
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        Self { movie, position: 0 }
+    }
+
+    /// Apply the buttons for the next frame or poll.
+    ///
+    /// Returns `Ok(false)` once the movie has ended, or an error if the emulator's model, boot mode
+    /// or cartridge differs from the recording, or the state hash does not match the recording.
+    pub fn play<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart, Serial, Infrared>, Serial: SerialDevice, Infrared: InfraredDevice>(
+        &mut self,
+        emulator: &mut Emulator<Cart, Callbacks, Serial, Infrared>
+    ) -> Result<bool, MovieError> {
+        if emulator.get_model() != self.movie.model {
+            return Err(MovieError::ModelMismatch { movie: self.movie.model, emulator: emulator.get_model() })
+        }
+        if emulator.get_boot_mode() != self.movie.boot_mode {
+            return Err(MovieError::BootModeMismatch { movie: self.movie.boot_mode, emulator: emulator.get_boot_mode() })
+        }
+        if emulator.cartridge_header_hash() != self.movie.cartridge_header_hash {
+            return Err(MovieError::CartridgeMismatch { movie: self.movie.cartridge_header_hash, emulator: emulator.cartridge_header_hash() })
+        }
+
+        let Some(input) = self.movie.inputs.get(self.position) else {
+            return Ok(false)
+        };
+
+        if let Some(expected) = input.hash {
+            let actual = emulator.state_hash();
+            if actual != expected {
+                return Err(MovieError::Desync { index: self.position, expected, actual })
+            }
+        }
+
+        emulator.set_buttons(input.buttons);
+        self.position += 1;
+        Ok(true)
+    }
+
+    /// Get the index of the next input to be played.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Return `true` if every input has been played.
+    pub fn finished(&self) -> bool {
+        self.position >= self.movie.inputs.len()
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{EmulatedCartridge, NullCartridge};
+    use crate::instance::tests::{new_emulator, run, TestEmulator, CLOCKS_PER_FRAME};
+
+    const P1: u16 = 0xFF00;
+    const WRAM: u16 = 0xC000;
+
+    const INPUTS: [Buttons; 6] = [Buttons::NONE, Buttons::RIGHT, Buttons::A, Buttons::UP, Buttons::NONE, Buttons::START];
+
+    /// Run a frame, then read P1 and store it in WRAM as a game would. Returns what was read.
+    fn run_frame(emulator: &mut TestEmulator, frame: u16) -> u8 {
+        run(emulator, CLOCKS_PER_FRAME);
+        emulator.write(P1, 0x20);
+        let d_pad = emulator.read(P1) & 0xF;
+        emulator.write(P1, 0x10);
+        let buttons = emulator.read(P1) & 0xF;
+        let joypad = (d_pad << 4) | buttons;
+        emulator.write(WRAM + frame, joypad);
+        joypad
+    }
+
+    fn record() -> (Movie, Vec<u8>) {
+        let mut emulator = new_emulator((), (), Model::DMG);
+        let mut recorder = MovieRecorder::new(Movie::new(&emulator, MovieGranularity::Frame, MovieStart::PowerOn), true);
+        let mut reads = Vec::new();
+        for (frame, buttons) in INPUTS.into_iter().enumerate() {
+            recorder.record(&mut emulator, buttons);
+            reads.push(run_frame(&mut emulator, frame as u16));
+        }
+        (recorder.into_movie(), reads)
+    }
+
+    #[test]
+    fn replay_matches_recording() {
+        let (movie, recorded_reads) = record();
+        let bytes = movie.to_bytes();
+        let movie = Movie::from_bytes(&bytes).unwrap();
+        assert_eq!(movie.inputs.len(), INPUTS.len());
+        assert!(movie.inputs.iter().all(|input| input.hash.is_some()));
+
+        let mut emulator = new_emulator((), (), Model::DMG);
+        let mut player = MoviePlayer::new(movie);
+        let mut reads = Vec::new();
+        let mut frame = 0;
+        while player.play(&mut emulator).unwrap() {
+            reads.push(run_frame(&mut emulator, frame));
+            frame += 1;
+        }
+        assert!(player.finished());
+        assert_eq!(reads, recorded_reads);
+        assert_ne!(reads[0], reads[1]);
+    }
+
+    #[test]
+    fn replay_detects_desync() {
+        let (movie, _) = record();
+        let mut emulator = new_emulator((), (), Model::DMG);
+        let mut player = MoviePlayer::new(movie);
+        assert_eq!(player.play(&mut emulator), Ok(true));
+        run_frame(&mut emulator, 0);
+
+        // The game would have stored something else.
+        emulator.write(WRAM, 0x5A);
+        assert!(matches!(player.play(&mut emulator), Err(MovieError::Desync { index: 1, .. })));
+    }
+
+    #[test]
+    fn replay_requires_the_recorded_model() {
+        let (movie, _) = record();
+        let mut emulator = new_emulator((), (), Model::MGB);
+        let mut player = MoviePlayer::new(movie);
+        assert_eq!(player.play(&mut emulator), Err(MovieError::ModelMismatch { movie: Model::DMG, emulator: Model::MGB }));
+        assert_eq!(player.position(), 0);
+    }
+
+    #[test]
+    fn replay_requires_the_recorded_boot_mode() {
+        let (movie, _) = record();
+        assert_eq!(movie.boot_mode, BootMode::BootROM);
+        let mut emulator = TestEmulator::new_hle((), EmulatedCartridge::new(NullCartridge), (), (), Model::DMG);
+        let mut player = MoviePlayer::new(movie);
+        assert_eq!(player.play(&mut emulator), Err(MovieError::BootModeMismatch { movie: BootMode::BootROM, emulator: BootMode::HLE }));
+        assert_eq!(player.position(), 0);
+    }
+
+    #[test]
+    fn replay_requires_the_recorded_cartridge() {
+        let (mut movie, _) = record();
+        let mut emulator = new_emulator((), (), Model::DMG);
+        assert_eq!(movie.cartridge_header_hash, emulator.cartridge_header_hash());
+
+        movie.cartridge_header_hash ^= 1;
+        let mut player = MoviePlayer::new(movie);
+        assert_eq!(
+            player.play(&mut emulator),
+            Err(MovieError::CartridgeMismatch { movie: emulator.cartridge_header_hash() ^ 1, emulator: emulator.cartridge_header_hash() })
+        );
+        assert_eq!(player.position(), 0);
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let mut movie = Movie {
+            model: Model::CGBE,
+            boot_mode: BootMode::HLE,
+            cartridge_header_hash: 0x12345678,
+            granularity: MovieGranularity::Poll,
+            start: MovieStart::SaveState(alloc::vec![1, 2, 3]),
+            inputs: Vec::new()
+        };
+        movie.inputs.push(MovieInput { buttons: Buttons::B | Buttons::LEFT, hash: None });
+        movie.inputs.push(MovieInput { buttons: Buttons::NONE, hash: Some(0xDEADBEEF) });
+        let bytes = movie.to_bytes();
+        assert_eq!(Movie::from_bytes(&bytes), Ok(movie));
+        assert_eq!(Movie::from_bytes(&bytes[..bytes.len() - 1]), Err(MovieError::Truncated));
+    }
+}
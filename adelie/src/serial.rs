@@ -0,0 +1,231 @@
+//! Serial (link) port devices.
+
+pub mod link_cable;
+pub mod four_player_adapter;
+#[cfg(feature = "std")]
+pub mod bgb;
+#[cfg(feature = "alloc")]
+pub mod printer;
+#[cfg(feature = "alloc")]
+pub mod mobile_adapter;
+
+/// Denotes a device that can be connected to the serial port.
+///
+/// The serial port shifts one bit out of SB (SO) and one bit in (SI) on each clock pulse. The clock
+/// is either generated by the Game Boy (internal clock) or by the connected device (external clock).
+pub trait SerialDevice {
+    /// Called every SoC clock, before the serial port is clocked.
+    ///
+    /// `double_speed` is set in CGB double speed mode, where each SoC clock is half as long.
+    fn tick(&mut self, double_speed: bool) {}
+
+    /// Called when the Game Boy starts a transfer using its internal clock.
+    ///
+    /// `outgoing` is the value of SB at the start of the transfer. Each of its bits will then be
+    /// passed to [`exchange_bit`](SerialDevice::exchange_bit), most significant bit first.
+    /// `high_speed` is set if the CGB high speed bit of SC is set, which shifts bits 32 times as
+    /// fast.
+    fn begin_transfer(&mut self, outgoing: u8, high_speed: bool) {}
+
+    /// Exchange one bit with the device on a clock pulse generated by the Game Boy.
+    ///
+    /// `outgoing` is the bit on SO, and the return value is the bit on SI.
+    fn exchange_bit(&mut self, outgoing: bool) -> bool;
+
+    /// Called every SoC clock while the serial port is set to use an external clock.
+    ///
+    /// `outgoing` is the bit currently on SO. Return the bit on SI if the device pulses the clock
+    /// on this SoC clock, or `None` if it does not.
+    ///
+    /// This is called whether or not a transfer was started. `transferring` is set if one was and
+    /// it is waiting for pulses; otherwise, pulses are ignored by the Game Boy.
+    fn poll_external_clock(&mut self, outgoing: bool, transferring: bool) -> Option<bool> {
+        None
+    }
+}
+
+/// Nothing is connected, so SI is pulled high and the external clock never pulses.
+impl SerialDevice for () {
+    fn exchange_bit(&mut self, _outgoing: bool) -> bool {
+        true
+    }
+}
+
+/// Denotes a device that communicates a whole byte at a time with the Game Boy as clock master.
+///
+/// Use [`ByteSerialAdapter`] to connect it to the serial port.
+pub trait ByteSerialDevice {
+    /// Exchange a byte with the Game Boy.
+    ///
+    /// `outgoing` is the byte sent by the Game Boy, and the return value is the byte shifted back
+    /// during the same transfer. Real devices prepare this byte before the transfer starts, so it
+    /// should not depend on `outgoing`.
+    fn exchange_byte(&mut self, outgoing: u8) -> u8;
+}
+
+/// Connects a [`ByteSerialDevice`] to the serial port.
+#[derive(Copy, Clone, Default)]
+pub struct ByteSerialAdapter<T: ByteSerialDevice> {
+    device: T,
+    incoming: u8,
+    bits_remaining: u8
+}
+
+impl<T: ByteSerialDevice> ByteSerialAdapter<T> {
+    pub fn new(device: T) -> Self {
+        Self { device, incoming: 0xFF, bits_remaining: 0 }
+    }
+
+    /// Get a reference to the device.
+    pub fn device(&self) -> &T {
+        &self.device
+    }
+
+    /// Get a mutable reference to the device.
+    pub fn device_mut(&mut self) -> &mut T {
+        &mut self.device
+    }
+
+    /// Disconnect the device.
+    pub fn into_device(self) -> T {
+        self.device
+    }
+}
+
+impl<T: ByteSerialDevice> SerialDevice for ByteSerialAdapter<T> {
+    fn begin_transfer(&mut self, outgoing: u8, _high_speed: bool) {
+        self.incoming = self.device.exchange_byte(outgoing);
+        self.bits_remaining = 8;
+    }
+
+    fn exchange_bit(&mut self, _outgoing: bool) -> bool {
+        // Pulses without a transfer (e.g. an aborted transfer restarted mid-byte) read as high.
+        if self.bits_remaining == 0 {
+            return true
+        }
+        self.bits_remaining -= 1;
+        (self.incoming >> self.bits_remaining) & 1 != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::Model;
+    use crate::instance::tests::{new_emulator, run};
+
+    const SB: u16 = 0xFF01;
+    const SC: u16 = 0xFF02;
+    const IF: u16 = 0xFF0F;
+    const SERIAL_INTERRUPT: u8 = 0b01000;
+
+    /// Records the bits shifted out, replying with a fixed byte.
+    #[derive(Default)]
+    struct RecordingDevice {
+        started: Option<u8>,
+        received: u8,
+        reply: u8,
+        bits: u8,
+
+        /// Pulse the external clock every this many polls, or never if 0.
+        external_period: u32,
+        polls: u32
+    }
+
+    impl RecordingDevice {
+        fn next_reply_bit(&mut self, outgoing: bool) -> bool {
+            self.received = (self.received << 1) | (outgoing as u8);
+            let bit = (self.reply >> (7 - self.bits)) & 1 != 0;
+            self.bits += 1;
+            bit
+        }
+    }
+
+    impl SerialDevice for RecordingDevice {
+        fn begin_transfer(&mut self, outgoing: u8, _high_speed: bool) {
+            self.started = Some(outgoing);
+        }
+
+        fn exchange_bit(&mut self, outgoing: bool) -> bool {
+            self.next_reply_bit(outgoing)
+        }
+
+        fn poll_external_clock(&mut self, outgoing: bool, _transferring: bool) -> Option<bool> {
+            if self.external_period == 0 || self.bits == 8 {
+                return None
+            }
+            self.polls += 1;
+            (self.polls % self.external_period == 0).then(|| self.next_reply_bit(outgoing))
+        }
+    }
+
+    #[test]
+    fn internal_clock_shifts_byte_through_device() {
+        let device = RecordingDevice { reply: 0x3C, ..Default::default() };
+        let mut emulator = new_emulator(device, (), Model::DMG);
+        emulator.write(SB, 0xA5);
+        emulator.write(SC, 0x81);
+
+        // One bit is shifted every 512 SoC clocks (8192 Hz).
+        run(&mut emulator, 512 * 4);
+        assert_eq!(emulator.read(SC) & 0x80, 0x80);
+        assert_eq!(emulator.read(IF) & SERIAL_INTERRUPT, 0);
+
+        run(&mut emulator, 512 * 4);
+        assert_eq!(emulator.read(SB), 0x3C);
+        assert_eq!(emulator.read(SC), 0x7F);
+        assert_eq!(emulator.read(IF) & SERIAL_INTERRUPT, SERIAL_INTERRUPT);
+
+        let device = emulator.get_serial_device();
+        assert_eq!(device.started, Some(0xA5));
+        assert_eq!(device.received, 0xA5);
+    }
+
+    #[test]
+    fn cgb_high_speed_clock_shifts_every_16_soc_clocks() {
+        let device = RecordingDevice { reply: 0x96, ..Default::default() };
+        let mut emulator = new_emulator(device, (), Model::CGB);
+        emulator.write(SB, 0x11);
+        emulator.write(SC, 0x83);
+
+        run(&mut emulator, 16 * 8);
+        assert_eq!(emulator.read(SB), 0x96);
+        assert_eq!(emulator.read(IF) & SERIAL_INTERRUPT, SERIAL_INTERRUPT);
+        assert_eq!(emulator.get_serial_device().received, 0x11);
+    }
+
+    #[test]
+    fn external_clock_shifts_on_device_pulses() {
+        let device = RecordingDevice { reply: 0xC3, external_period: 100, ..Default::default() };
+        let mut emulator = new_emulator(device, (), Model::DMG);
+        emulator.write(SB, 0x5A);
+        emulator.write(SC, 0x80);
+
+        run(&mut emulator, 799);
+        assert_eq!(emulator.read(IF) & SERIAL_INTERRUPT, 0);
+        run(&mut emulator, 1);
+        assert_eq!(emulator.read(SB), 0xC3);
+        assert_eq!(emulator.read(SC) & 0x80, 0);
+        assert_eq!(emulator.read(IF) & SERIAL_INTERRUPT, SERIAL_INTERRUPT);
+
+        let device = emulator.get_serial_device();
+        assert_eq!(device.started, None);
+        assert_eq!(device.received, 0x5A);
+    }
+
+    #[test]
+    fn byte_serial_adapter_exchanges_whole_bytes() {
+        struct Echo;
+        impl ByteSerialDevice for Echo {
+            fn exchange_byte(&mut self, outgoing: u8) -> u8 {
+                !outgoing
+            }
+        }
+
+        let mut emulator = new_emulator(ByteSerialAdapter::new(Echo), (), Model::DMG);
+        emulator.write(SB, 0x0F);
+        emulator.write(SC, 0x81);
+        run(&mut emulator, 512 * 8);
+        assert_eq!(emulator.read(SB), 0xF0);
+    }
+}
@@ -0,0 +1,687 @@
+//! BGB link cable protocol (version 1.4) over TCP.
+//!
+//! Every packet is 8 bytes:
+//!
+//! | Offset | Size | Field                                       |
+//! |--------|------|---------------------------------------------|
+//! | 0      | 1    | Command                                     |
+//! | 1      | 1    | Argument 1                                  |
+//! | 2      | 1    | Argument 2                                  |
+//! | 3      | 1    | Argument 3                                  |
+//! | 4      | 4    | Timestamp (little endian, 2 MiHz, 31 bits)  |
+//!
+//! Commands:
+//!
+//! | Command | Name         | Arguments                                                        |
+//! |---------|--------------|------------------------------------------------------------------|
+//! | 1       | Version      | 1, 4, 0; must be the first packet sent by both sides             |
+//! | 101     | Joypad       | Ignored                                                          |
+//! | 104     | Sync1        | Data, control (0x81, +2 high speed, +4 double speed); from master |
+//! | 105     | Sync2        | Data, 0x80; reply from the slave to sync1                         |
+//! | 106     | Sync3        | 0 = timestamp sync, 1 = no reply to sync1 (slave not ready)       |
+//! | 108     | Status       | Bit 0 running, bit 1 paused, bit 2 supports reconnect            |
+//! | 109     | Disconnect   | The other side wants to disconnect                               |
+//!
+//! The side whose Game Boy starts a transfer with its internal clock sends sync1 and waits for
+//! sync2 (or sync3 with argument 1) before shifting the reply in. If the other side's Game Boy is
+//! waiting for a transfer on its external clock, the received byte is shifted in as clock pulses at
+//! the rate given by the sync1 control bits, then sync2 is sent back with the bits it shifted out.
+//! Otherwise, it declines with sync3 and argument 1. A sync1 is held for a short while before it
+//! is declined, since the Game Boy may only just be about to start waiting for the transfer.
+//!
+//! Both sides also send sync3 with argument 0 regularly so the other side knows how far along its
+//! emulation is.
+
+use core::fmt::{Display, Formatter};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use crate::serial::SerialDevice;
+
+const COMMAND_VERSION: u8 = 1;
+const COMMAND_JOYPAD: u8 = 101;
+const COMMAND_SYNC1: u8 = 104;
+const COMMAND_SYNC2: u8 = 105;
+const COMMAND_SYNC3: u8 = 106;
+const COMMAND_STATUS: u8 = 108;
+const COMMAND_DISCONNECT: u8 = 109;
+
+const VERSION: (u8, u8, u8) = (1, 4, 0);
+
+const SYNC1_CONTROL: u8 = 0x81;
+const SYNC1_HIGH_SPEED: u8 = 0b010;
+const SYNC1_DOUBLE_SPEED: u8 = 0b100;
+const SYNC2_CONTROL: u8 = 0x80;
+const SYNC3_TIMESTAMP: u8 = 0;
+const SYNC3_NOT_READY: u8 = 1;
+const STATUS_RUNNING: u8 = 0b001;
+
+/// 2 MiHz timestamp ticks between sync3 timestamp packets (about 8 ms).
+const TIMESTAMP_SYNC_INTERVAL: u32 = 0x4000;
+
+/// 2 MiHz timestamp ticks between checks for packets from the other side (about 0.5 ms). This
+/// divides [`TIMESTAMP_SYNC_INTERVAL`].
+const POLL_INTERVAL: u32 = 0x400;
+
+/// 2 MiHz timestamp ticks to hold a sync1 for a transfer on the external clock before declining it
+/// (about two frames).
+const SYNC1_HOLD_TIME: u32 = 0x10000;
+
+/// Real time to wait for the other side to reply to a sync1 before giving up on it.
+const SYNC1_REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Real time to sleep between reads while waiting for a reply.
+const REPLY_POLL_DELAY: Duration = Duration::from_micros(100);
+
+/// SoC clocks between external clock pulses when receiving a byte at the normal serial clock
+/// (8192 Hz), without double speed on either side.
+const SOC_CLOCKS_PER_EXTERNAL_PULSE: u32 = 512;
+
+/// A packet in the BGB link protocol.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct BGBPacket {
+    pub command: u8,
+    pub arguments: [u8; 3],
+    pub timestamp: u32
+}
+
+impl BGBPacket {
+    pub const fn new(command: u8, arguments: [u8; 3], timestamp: u32) -> Self {
+        Self { command, arguments, timestamp }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let t = (self.timestamp & 0x7FFFFFFF).to_le_bytes();
+        [self.command, self.arguments[0], self.arguments[1], self.arguments[2], t[0], t[1], t[2], t[3]]
+    }
+
+    pub fn from_bytes(bytes: [u8; 8]) -> Self {
+        Self {
+            command: bytes[0],
+            arguments: [bytes[1], bytes[2], bytes[3]],
+            timestamp: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) & 0x7FFFFFFF
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BGBLinkError {
+    IO(std::io::Error),
+    UnsupportedVersion { major: u8, minor: u8, patch: u8 },
+    UnexpectedPacket(u8),
+    TimedOut,
+    Disconnected
+}
+
+impl Display for BGBLinkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::IO(e) => f.write_fmt(format_args!("I/O error: {e}")),
+            Self::UnsupportedVersion { major, minor, patch } => f.write_fmt(format_args!("Unsupported protocol version {major}.{minor}.{patch}")),
+            Self::UnexpectedPacket(c) => f.write_fmt(format_args!("Unexpected packet (command {c})")),
+            Self::TimedOut => f.write_str("The other side did not reply in time"),
+            Self::Disconnected => f.write_str("The other side disconnected")
+        }
+    }
+}
+
+impl From<std::io::Error> for BGBLinkError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+/// A byte received from the other side that is being clocked into the Game Boy.
+#[derive(Copy, Clone)]
+struct ExternalTransfer {
+    incoming: u8,
+    outgoing: u8,
+    bits_remaining: u8,
+    clocks_per_pulse: u32,
+    clocks_until_pulse: u32
+}
+
+/// Serial device that links with another emulator using the BGB link protocol.
+///
+/// If the connection is lost, this behaves as if nothing is connected, and the cause can be
+/// retrieved with [`BGBLink::error`].
+pub struct BGBLink {
+    stream: Option<TcpStream>,
+    error: Option<BGBLinkError>,
+
+    /// Emulated time in 2 MiHz ticks, used for timestamps.
+    timestamp: u32,
+    soc_clocks: u8,
+    double_speed: bool,
+    remote_timestamp: u32,
+    remote_status: u8,
+
+    /// Byte being shifted in as clock master.
+    internal_incoming: u8,
+    internal_bits_remaining: u8,
+
+    /// Data and control bits of a sync1 that has not been answered yet, and when it arrived.
+    remote_sync1: Option<(u8, u8, u32)>,
+    external_transfer: Option<ExternalTransfer>,
+
+    receive_buffer: [u8; 8],
+    received_length: usize
+}
+
+impl BGBLink {
+    /// Connect to another emulator listening at the given address.
+    pub fn connect<A: ToSocketAddrs>(address: A) -> Result<Self, BGBLinkError> {
+        Self::handshake(TcpStream::connect(address)?)
+    }
+
+    /// Wait for another emulator to connect at the given address.
+    pub fn listen<A: ToSocketAddrs>(address: A) -> Result<Self, BGBLinkError> {
+        Self::accept(&TcpListener::bind(address)?)
+    }
+
+    /// Wait for another emulator to connect to an existing listener.
+    pub fn accept(listener: &TcpListener) -> Result<Self, BGBLinkError> {
+        let (stream, _) = listener.accept()?;
+        Self::handshake(stream)
+    }
+
+    /// Get the error that caused the link to disconnect, if any.
+    pub fn error(&self) -> Option<&BGBLinkError> {
+        self.error.as_ref()
+    }
+
+    /// Return true if still connected.
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Get the last status flags sent by the other side.
+    pub fn remote_status(&self) -> u8 {
+        self.remote_status
+    }
+
+    /// Get the last timestamp sent by the other side.
+    pub fn remote_timestamp(&self) -> u32 {
+        self.remote_timestamp
+    }
+
+    /// Tell the other side we are disconnecting and close the connection.
+    pub fn disconnect(&mut self) {
+        let _ = self.send(BGBPacket::new(COMMAND_DISCONNECT, [0; 3], self.timestamp));
+        self.stream = None;
+    }
+
+    fn handshake(stream: TcpStream) -> Result<Self, BGBLinkError> {
+        stream.set_nodelay(true)?;
+
+        let mut link = Self {
+            stream: Some(stream),
+            error: None,
+            timestamp: 0,
+            soc_clocks: 0,
+            double_speed: false,
+            remote_timestamp: 0,
+            remote_status: 0,
+            internal_incoming: 0xFF,
+            internal_bits_remaining: 0,
+            remote_sync1: None,
+            external_transfer: None,
+            receive_buffer: [0; 8],
+            received_length: 0
+        };
+
+        // The handshake blocks, after which the stream is only read when data is available.
+        link.send(BGBPacket::new(COMMAND_VERSION, [VERSION.0, VERSION.1, VERSION.2], 0))?;
+        let version = link.receive()?.ok_or(BGBLinkError::Disconnected)?;
+        if version.command != COMMAND_VERSION {
+            return Err(BGBLinkError::UnexpectedPacket(version.command))
+        }
+        let [major, minor, patch] = version.arguments;
+        if (major, minor, patch) != VERSION {
+            return Err(BGBLinkError::UnsupportedVersion { major, minor, patch })
+        }
+
+        link.send(BGBPacket::new(COMMAND_STATUS, [STATUS_RUNNING, 0, 0], 0))?;
+        link.stream.as_ref().unwrap().set_nonblocking(true)?;
+        Ok(link)
+    }
+
+    fn send(&mut self, packet: BGBPacket) -> Result<(), BGBLinkError> {
+        let stream = self.stream.as_mut().ok_or(BGBLinkError::Disconnected)?;
+        let bytes = packet.to_bytes();
+        let mut written = 0;
+        while written < bytes.len() {
+            match stream.write(&bytes[written..]) {
+                Ok(0) => return Err(BGBLinkError::Disconnected),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(REPLY_POLL_DELAY),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into())
+            }
+        }
+        Ok(())
+    }
+
+    /// Receive a packet, returning `None` if no complete packet is available yet.
+    fn receive(&mut self) -> Result<Option<BGBPacket>, BGBLinkError> {
+        let stream = self.stream.as_mut().ok_or(BGBLinkError::Disconnected)?;
+        while self.received_length < 8 {
+            match stream.read(&mut self.receive_buffer[self.received_length..]) {
+                Ok(0) => return Err(BGBLinkError::Disconnected),
+                Ok(n) => self.received_length += n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into())
+            }
+        }
+        self.received_length = 0;
+        Ok(Some(BGBPacket::from_bytes(self.receive_buffer)))
+    }
+
+    /// Handle packets that can arrive at any time, returning the packet if it was not handled.
+    fn handle_packet(&mut self, packet: BGBPacket) -> Result<Option<BGBPacket>, BGBLinkError> {
+        self.remote_timestamp = packet.timestamp;
+        match packet.command {
+            COMMAND_JOYPAD => Ok(None),
+            COMMAND_STATUS => {
+                self.remote_status = packet.arguments[0];
+                Ok(None)
+            },
+            COMMAND_SYNC3 if packet.arguments[0] == SYNC3_TIMESTAMP => Ok(None),
+            COMMAND_DISCONNECT => Err(BGBLinkError::Disconnected),
+            _ => Ok(Some(packet))
+        }
+    }
+
+    fn fail(&mut self, error: BGBLinkError) {
+        self.stream = None;
+        self.error = Some(error);
+        self.remote_sync1 = None;
+        self.external_transfer = None;
+        self.internal_bits_remaining = 0;
+    }
+
+    /// Tell the other side our Game Boy is not waiting for a transfer.
+    fn decline_sync1(&mut self) -> Result<(), BGBLinkError> {
+        self.send(BGBPacket::new(COMMAND_SYNC3, [SYNC3_NOT_READY, 0, 0], self.timestamp))
+    }
+
+    /// Send sync1 and wait for the reply.
+    fn master_transfer(&mut self, outgoing: u8, high_speed: bool) -> Result<u8, BGBLinkError> {
+        if self.remote_sync1.take().is_some() {
+            self.decline_sync1()?;
+        }
+
+        let mut control = SYNC1_CONTROL;
+        if high_speed {
+            control |= SYNC1_HIGH_SPEED;
+        }
+        if self.double_speed {
+            control |= SYNC1_DOUBLE_SPEED;
+        }
+        self.send(BGBPacket::new(COMMAND_SYNC1, [outgoing, control, 0], self.timestamp))?;
+        let deadline = Instant::now() + SYNC1_REPLY_TIMEOUT;
+        loop {
+            let Some(packet) = self.receive()? else {
+                if Instant::now() >= deadline {
+                    return Err(BGBLinkError::TimedOut)
+                }
+                std::thread::sleep(REPLY_POLL_DELAY);
+                continue
+            };
+            let Some(packet) = self.handle_packet(packet)? else { continue };
+            match packet.command {
+                COMMAND_SYNC2 => return Ok(packet.arguments[0]),
+                COMMAND_SYNC3 => return Ok(0xFF),
+
+                // Both sides tried to be master at once, so tell the other side we are not ready.
+                COMMAND_SYNC1 => self.decline_sync1()?,
+
+                c => return Err(BGBLinkError::UnexpectedPacket(c))
+            }
+        }
+    }
+
+    /// Send our timestamp if it is time to, and handle packets from the other side without blocking.
+    fn poll(&mut self) -> Result<(), BGBLinkError> {
+        // A sync1 that was not taken by a transfer on the external clock in time is declined.
+        if let Some((_, _, received)) = self.remote_sync1 {
+            if self.timestamp.wrapping_sub(received) & 0x7FFFFFFF >= SYNC1_HOLD_TIME {
+                self.remote_sync1 = None;
+                self.decline_sync1()?;
+            }
+        }
+
+        if self.timestamp % TIMESTAMP_SYNC_INTERVAL == 0 {
+            self.send(BGBPacket::new(COMMAND_SYNC3, [SYNC3_TIMESTAMP, 0, 0], self.timestamp))?;
+        }
+
+        while let Some(packet) = self.receive()? {
+            let Some(packet) = self.handle_packet(packet)? else { continue };
+            match packet.command {
+                COMMAND_SYNC1 => self.remote_sync1 = Some((packet.arguments[0], packet.arguments[1], self.timestamp)),
+                c => return Err(BGBLinkError::UnexpectedPacket(c))
+            }
+        }
+        Ok(())
+    }
+
+}
+
+/// Get the SoC clocks between external clock pulses for the control bits of a sync1, with
+/// `double_speed` set if our Game Boy is in double speed mode.
+fn external_pulse_period(control: u8, double_speed: bool) -> u32 {
+    let mut period = SOC_CLOCKS_PER_EXTERNAL_PULSE;
+    if (control & SYNC1_HIGH_SPEED) != 0 {
+        period /= 32;
+    }
+    if (control & SYNC1_DOUBLE_SPEED) != 0 {
+        period /= 2;
+    }
+    if double_speed {
+        period *= 2;
+    }
+    period
+}
+
+impl SerialDevice for BGBLink {
+    fn tick(&mut self, double_speed: bool) {
+        // 2 MiHz timestamps advance every other SoC clock, or every fourth in double speed mode.
+        self.double_speed = double_speed;
+        self.soc_clocks = self.soc_clocks.wrapping_add(1);
+        let clocks_per_tick = if double_speed { 4 } else { 2 };
+        if self.soc_clocks % clocks_per_tick != 0 {
+            return
+        }
+        self.timestamp = self.timestamp.wrapping_add(1) & 0x7FFFFFFF;
+
+        if self.timestamp % POLL_INTERVAL == 0 && self.stream.is_some() && self.external_transfer.is_none() {
+            if let Err(e) = self.poll() {
+                self.fail(e);
+            }
+        }
+    }
+
+    fn begin_transfer(&mut self, outgoing: u8, high_speed: bool) {
+        if self.stream.is_none() {
+            return
+        }
+        match self.master_transfer(outgoing, high_speed) {
+            Ok(incoming) => {
+                self.internal_incoming = incoming;
+                self.internal_bits_remaining = 8;
+            },
+            Err(e) => self.fail(e)
+        }
+    }
+
+    fn exchange_bit(&mut self, _outgoing: bool) -> bool {
+        if self.internal_bits_remaining == 0 {
+            return true
+        }
+        self.internal_bits_remaining -= 1;
+        (self.internal_incoming >> self.internal_bits_remaining) & 1 != 0
+    }
+
+    fn poll_external_clock(&mut self, outgoing: bool, transferring: bool) -> Option<bool> {
+        self.stream.as_ref()?;
+
+        if self.external_transfer.is_none() {
+            // Only take the byte if the Game Boy is waiting for it.
+            let (incoming, control, _) = self.remote_sync1.filter(|_| transferring)?;
+            let clocks_per_pulse = external_pulse_period(control, self.double_speed);
+            self.remote_sync1 = None;
+            self.external_transfer = Some(ExternalTransfer {
+                incoming,
+                outgoing: 0,
+                bits_remaining: 8,
+                clocks_per_pulse,
+                clocks_until_pulse: clocks_per_pulse
+            });
+            return None
+        }
+
+        let transfer = self.external_transfer.as_mut().unwrap();
+        transfer.clocks_until_pulse -= 1;
+        if transfer.clocks_until_pulse > 0 {
+            return None
+        }
+        transfer.clocks_until_pulse = transfer.clocks_per_pulse;
+        transfer.bits_remaining -= 1;
+        transfer.outgoing = (transfer.outgoing << 1) | (outgoing as u8);
+        let incoming = (transfer.incoming >> transfer.bits_remaining) & 1 != 0;
+
+        if transfer.bits_remaining == 0 {
+            let reply = transfer.outgoing;
+            self.external_transfer = None;
+            if let Err(e) = self.send(BGBPacket::new(COMMAND_SYNC2, [reply, SYNC2_CONTROL, 0], self.timestamp)) {
+                self.fail(e);
+            }
+        }
+
+        Some(incoming)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, Receiver, TryRecvError};
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+    use std::vec::Vec;
+    use crate::instance::Model;
+    use crate::instance::tests::{new_emulator, run};
+
+    const SB: u16 = 0xFF01;
+    const SC: u16 = 0xFF02;
+
+    /// Stop an instance after this many SoC clocks (about 8 seconds) in case the test hangs.
+    const TIMEOUT_CLOCKS: u32 = 32 * 1024 * 1024;
+
+    /// Run an instance connected to `address`, writing each value received from `sc` to SC until it
+    /// is closed and no transfer is in progress. Returns SB.
+    fn spawn_instance(address: std::net::SocketAddr, model: Model, sb: u8, sc: Receiver<u8>) -> JoinHandle<u8> {
+        std::thread::spawn(move || {
+            let mut emulator = new_emulator(BGBLink::connect(address).unwrap(), (), model);
+            emulator.write(SB, sb);
+            for _ in 0..TIMEOUT_CLOCKS / 256 {
+                match sc.try_recv() {
+                    Ok(data) => emulator.write(SC, data),
+                    Err(TryRecvError::Empty) => (),
+                    Err(TryRecvError::Disconnected) if emulator.read(SC) & 0x80 == 0 => break,
+                    Err(TryRecvError::Disconnected) => ()
+                }
+                run(&mut emulator, 256);
+            }
+            assert!(emulator.get_serial_device().error().is_none());
+            emulator.read(SB)
+        })
+    }
+
+    /// Stands in for the other emulator, recording the timestamps it is sent.
+    struct Peer {
+        stream: TcpStream,
+        timestamps: Vec<u32>
+    }
+
+    impl Peer {
+        fn accept(listener: &TcpListener) -> Self {
+            let (stream, _) = listener.accept().unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+            let mut peer = Self { stream, timestamps: Vec::new() };
+            peer.send(BGBPacket::new(COMMAND_VERSION, [VERSION.0, VERSION.1, VERSION.2], 0));
+            assert_eq!(peer.receive().command, COMMAND_VERSION);
+            assert_eq!(peer.receive(), BGBPacket::new(COMMAND_STATUS, [STATUS_RUNNING, 0, 0], 0));
+            peer
+        }
+
+        fn send(&mut self, packet: BGBPacket) {
+            self.stream.write_all(&packet.to_bytes()).unwrap();
+        }
+
+        /// Receive the next packet, recording it if it is a timestamp sync.
+        fn receive_any(&mut self) -> BGBPacket {
+            let mut bytes = [0u8; 8];
+            self.stream.read_exact(&mut bytes).unwrap();
+            let packet = BGBPacket::from_bytes(bytes);
+            if packet.command == COMMAND_SYNC3 && packet.arguments[0] == SYNC3_TIMESTAMP {
+                self.timestamps.push(packet.timestamp);
+            }
+            packet
+        }
+
+        /// Receive the next packet that is not a timestamp sync.
+        fn receive(&mut self) -> BGBPacket {
+            loop {
+                let packet = self.receive_any();
+                if !self.timestamps.last().is_some_and(|t| packet == BGBPacket::new(COMMAND_SYNC3, [SYNC3_TIMESTAMP, 0, 0], *t)) {
+                    return packet
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exchanges_bytes_between_two_instances() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (master_sc, master_sc_receiver) = channel();
+        let (done, done_receiver) = channel::<()>();
+
+        let slave = std::thread::spawn(move || {
+            let mut emulator = new_emulator(BGBLink::accept(&listener).unwrap(), (), Model::DMG);
+            emulator.write(SB, 0x42);
+            emulator.write(SC, 0x80);
+            let mut clocks = 0;
+            while emulator.read(SC) & 0x80 != 0 && clocks < TIMEOUT_CLOCKS {
+                run(&mut emulator, 256);
+                clocks += 256;
+            }
+
+            // Stay connected until the other side is done.
+            let _ = done_receiver.recv();
+            emulator.read(SB)
+        });
+        let master = spawn_instance(address, Model::CGB, 0x99, master_sc_receiver);
+
+        master_sc.send(0x81).unwrap();
+        drop(master_sc);
+        assert_eq!(master.join().unwrap(), 0x42);
+        drop(done);
+        assert_eq!(slave.join().unwrap(), 0x99);
+    }
+
+    #[test]
+    fn sync1_is_declined_without_a_transfer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (sc, sc_receiver) = channel();
+        let instance = spawn_instance(listener.local_addr().unwrap(), Model::DMG, 0x24, sc_receiver);
+        let mut peer = Peer::accept(&listener);
+
+        // External clock, but no transfer started.
+        sc.send(0x00).unwrap();
+        peer.send(BGBPacket::new(COMMAND_SYNC1, [0x5A, SYNC1_CONTROL, 0], 0));
+        let reply = peer.receive();
+        assert_eq!((reply.command, reply.arguments[0]), (COMMAND_SYNC3, SYNC3_NOT_READY));
+
+        // Internal clock, so the external clock is not even polled.
+        sc.send(0x01).unwrap();
+        peer.send(BGBPacket::new(COMMAND_SYNC1, [0x5A, SYNC1_CONTROL, 0], 0));
+        let reply = peer.receive();
+        assert_eq!((reply.command, reply.arguments[0]), (COMMAND_SYNC3, SYNC3_NOT_READY));
+
+        drop(sc);
+        assert_eq!(instance.join().unwrap(), 0x24);
+    }
+
+    #[test]
+    fn sync1_is_held_until_a_transfer_starts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (sc, sc_receiver) = channel();
+        let instance = spawn_instance(listener.local_addr().unwrap(), Model::DMG, 0x3C, sc_receiver);
+        let mut peer = Peer::accept(&listener);
+
+        // The transfer is only started after sync1 may already have arrived.
+        peer.send(BGBPacket::new(COMMAND_SYNC1, [0xA5, SYNC1_CONTROL | SYNC1_HIGH_SPEED, 0], 0));
+        sc.send(0x80).unwrap();
+        let sync2 = peer.receive();
+        assert_eq!(sync2.command, COMMAND_SYNC2);
+        assert_eq!(sync2.arguments[..2], [0x3C, SYNC2_CONTROL]);
+
+        drop(sc);
+        assert_eq!(instance.join().unwrap(), 0xA5);
+    }
+
+    #[test]
+    fn silent_peer_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let instance = std::thread::spawn(move || {
+            let mut emulator = new_emulator(BGBLink::connect(address).unwrap(), (), Model::DMG);
+            emulator.write(SC, 0x81);
+            run(&mut emulator, 256);
+            matches!(emulator.get_serial_device().error(), Some(BGBLinkError::TimedOut))
+        });
+        let mut peer = Peer::accept(&listener);
+
+        // Never reply to sync1.
+        assert_eq!(peer.receive().command, COMMAND_SYNC1);
+        assert!(instance.join().unwrap());
+    }
+
+    #[test]
+    fn sync1_carries_the_clock_speed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (sc, sc_receiver) = channel();
+        let instance = spawn_instance(listener.local_addr().unwrap(), Model::CGB, 0x66, sc_receiver);
+        let mut peer = Peer::accept(&listener);
+
+        sc.send(0x83).unwrap();
+        let sync1 = peer.receive();
+        assert_eq!(sync1.command, COMMAND_SYNC1);
+        assert_eq!(sync1.arguments[..2], [0x66, SYNC1_CONTROL | SYNC1_HIGH_SPEED]);
+        peer.send(BGBPacket::new(COMMAND_SYNC2, [0xC3, SYNC2_CONTROL, 0], sync1.timestamp));
+
+        // Timestamps keep being sent after the transfer.
+        let sent = peer.timestamps.len();
+        while peer.timestamps.len() < sent + 2 {
+            peer.receive_any();
+        }
+        assert_eq!(peer.timestamps[sent + 1] - peer.timestamps[sent], TIMESTAMP_SYNC_INTERVAL);
+
+        drop(sc);
+        assert_eq!(instance.join().unwrap(), 0xC3);
+    }
+
+    #[test]
+    fn external_clock_follows_the_sync1_control_bits() {
+        assert_eq!(external_pulse_period(SYNC1_CONTROL, false), 512);
+        assert_eq!(external_pulse_period(SYNC1_CONTROL | SYNC1_HIGH_SPEED, false), 16);
+        assert_eq!(external_pulse_period(SYNC1_CONTROL | SYNC1_DOUBLE_SPEED, false), 256);
+        assert_eq!(external_pulse_period(SYNC1_CONTROL, true), 1024);
+        assert_eq!(external_pulse_period(SYNC1_CONTROL | SYNC1_HIGH_SPEED | SYNC1_DOUBLE_SPEED, true), 16);
+    }
+
+    #[test]
+    fn external_transfer_replies_with_sync2() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (sc, sc_receiver) = channel();
+        let instance = spawn_instance(listener.local_addr().unwrap(), Model::DMG, 0x77, sc_receiver);
+        let mut peer = Peer::accept(&listener);
+
+        sc.send(0x80).unwrap();
+
+        // Wait for a timestamp so the transfer has started before sync1 is sent.
+        while peer.timestamps.len() < 2 {
+            peer.receive_any();
+        }
+        peer.send(BGBPacket::new(COMMAND_SYNC1, [0x18, SYNC1_CONTROL | SYNC1_HIGH_SPEED, 0], 0));
+        let sync2 = peer.receive();
+        assert_eq!(sync2.command, COMMAND_SYNC2);
+        assert_eq!(sync2.arguments[..2], [0x77, SYNC2_CONTROL]);
+
+        drop(sc);
+        assert_eq!(instance.join().unwrap(), 0x18);
+    }
+}
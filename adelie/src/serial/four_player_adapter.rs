@@ -0,0 +1,417 @@
+//! DMG-07 four-player adapter emulation.
+//!
+//! The adapter is always the clock master. It starts in the ping phase, repeatedly sending this
+//! packet to every connected Game Boy at the same time:
+//!
+//! | Adapter sends      | Game Boy replies |
+//! |--------------------|------------------|
+//! | 0xFE               | 0x88 (ACK1)      |
+//! | STAT1              | 0x88 (ACK2)      |
+//! | STAT2              | RATE             |
+//! | STAT3              | SIZE             |
+//!
+//! The three STAT bytes are identical: bits 0-2 hold the player ID (1-4) and bits 4-7 are set for
+//! each player that acknowledged the previous ping packet. Only player 1's RATE and SIZE are used.
+//!
+//! Player 1 starts the transmission phase by replying 0xAA to a whole ping packet. The adapter
+//! then sends 0xCC four times before repeatedly exchanging packets: each Game Boy sends SIZE bytes
+//! at the start of a cycle of 4 × SIZE bytes, during which the adapter sends the packets of players
+//! 1-4 from the previous cycle to everyone. Player 1 returns to the ping phase by sending 0xFF four
+//! times in a row.
+
+use crate::cartridge::Cartridge;
+use crate::instance::{Emulator, EmulatorCallbacks};
+use crate::serial::SerialDevice;
+
+const PING_HEADER: u8 = 0xFE;
+const ACK: u8 = 0x88;
+const START_TRANSMISSION: u8 = 0xAA;
+const TRANSMISSION_STARTING: u8 = 0xCC;
+const RETURN_TO_PING: u8 = 0xFF;
+
+/// SoC clocks (at base speed) per clock pulse.
+const BIT_PERIOD: u32 = 512;
+
+/// Minimum SoC clocks (at base speed) between the end of one byte and the start of the next.
+const BASE_BYTE_GAP: u32 = 0x6FA * 4;
+
+/// Additional SoC clocks between bytes for each step of the RATE byte.
+const RATE_BYTE_GAP: u32 = 196 * 4;
+
+/// Largest packet size accepted from player 1.
+pub const MAX_PACKET_SIZE: usize = 16;
+
+/// One of the four ports on a [`FourPlayerAdapter`].
+///
+/// Pass this to [`Emulator::new`] as the serial device for each player.
+#[derive(Copy, Clone, Default)]
+pub struct FourPlayerAdapterPort {
+    /// Last known state of this Game Boy's SO line.
+    so: bool,
+
+    /// A clock pulse generated by the adapter, with the bit it shifted out.
+    pulse: Option<bool>
+}
+
+impl SerialDevice for FourPlayerAdapterPort {
+    fn exchange_bit(&mut self, outgoing: bool) -> bool {
+        // The adapter never listens to a Game Boy's clock.
+        self.so = outgoing;
+        true
+    }
+
+    fn poll_external_clock(&mut self, outgoing: bool, _transferring: bool) -> Option<bool> {
+        self.so = outgoing;
+        self.pulse.take()
+    }
+}
+
+/// Phase of the adapter.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FourPlayerAdapterPhase {
+    /// Sending ping packets and waiting for player 1 to start a transmission.
+    Ping,
+
+    /// Sending 0xCC before the transmission phase.
+    Starting,
+
+    /// Relaying packets between players.
+    Transmission
+}
+
+/// Connects the serial ports of up to four instances in the same process through a DMG-07, running
+/// them in lockstep.
+pub struct FourPlayerAdapter<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart, FourPlayerAdapterPort>> {
+    players: [Option<Emulator<Cart, Callbacks, FourPlayerAdapterPort>>; 4],
+
+    phase: FourPlayerAdapterPhase,
+
+    /// Index of the current byte in the ping packet, start sequence, or transmission cycle.
+    byte_index: usize,
+
+    /// Bit of the current byte being shifted (8 while waiting for the next byte).
+    bit_index: u8,
+
+    /// SoC clocks until the next clock pulse or byte.
+    countdown: u32,
+
+    /// Bytes being sent to each player.
+    outgoing: [u8; 4],
+
+    /// Bytes being received from each player.
+    incoming: [u8; 4],
+
+    /// Players that acknowledged the last ping packet.
+    acknowledged: [bool; 4],
+
+    /// Players that acknowledged the current ping packet so far.
+    acknowledging: [bool; 4],
+
+    /// Number of consecutive 0xAA (ping) or 0xFF (transmission) bytes from player 1.
+    command_count: usize,
+
+    rate: u8,
+    size: usize,
+
+    /// Packets received from each player during the current cycle.
+    received_packets: [[u8; MAX_PACKET_SIZE]; 4],
+
+    /// Packets being relayed during the current cycle.
+    relayed_packets: [[u8; MAX_PACKET_SIZE]; 4]
+}
+
+impl<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart, FourPlayerAdapterPort>> FourPlayerAdapter<Cart, Callbacks> {
+    /// Connect up to four instances. `None` leaves the port empty.
+    pub fn new(players: [Option<Emulator<Cart, Callbacks, FourPlayerAdapterPort>>; 4]) -> Self {
+        let mut adapter = Self {
+            players,
+            phase: FourPlayerAdapterPhase::Ping,
+            byte_index: 0,
+            bit_index: 8,
+            countdown: BASE_BYTE_GAP,
+            outgoing: [0; 4],
+            incoming: [0; 4],
+            acknowledged: [false; 4],
+            acknowledging: [false; 4],
+            command_count: 0,
+            rate: 0,
+            size: 1,
+            received_packets: [[0; MAX_PACKET_SIZE]; 4],
+            relayed_packets: [[0; MAX_PACKET_SIZE]; 4]
+        };
+        adapter.prepare_bytes();
+        adapter
+    }
+
+    /// Run one SoC clock cycle at base speed (4 MiHz) on all instances.
+    ///
+    /// An instance in double speed mode runs two SoC clock cycles so all stay in sync in real time.
+    pub fn tick(&mut self) {
+        self.countdown -= 1;
+        if self.countdown == 0 {
+            self.clock_adapter();
+        }
+
+        for emulator in self.players.iter_mut().flatten() {
+            let cycles = if emulator.in_double_speed_mode() { 2 } else { 1 };
+            for _ in 0..cycles {
+                emulator.tick_soc(true);
+                emulator.tick_soc(false);
+            }
+        }
+    }
+
+    /// Get the current phase.
+    pub fn phase(&self) -> FourPlayerAdapterPhase {
+        self.phase
+    }
+
+    /// Get the packet size negotiated with player 1.
+    pub fn packet_size(&self) -> usize {
+        self.size
+    }
+
+    /// Get the RATE byte negotiated with player 1.
+    pub fn rate(&self) -> u8 {
+        self.rate
+    }
+
+    /// Access a player (0-3).
+    pub fn player(&self, player: usize) -> Option<&Emulator<Cart, Callbacks, FourPlayerAdapterPort>> {
+        self.players[player].as_ref()
+    }
+
+    /// Access a player (0-3).
+    pub fn player_mut(&mut self, player: usize) -> Option<&mut Emulator<Cart, Callbacks, FourPlayerAdapterPort>> {
+        self.players[player].as_mut()
+    }
+
+    /// Plug an instance into a port (0-3), returning the instance that was there.
+    pub fn connect(&mut self, player: usize, emulator: Emulator<Cart, Callbacks, FourPlayerAdapterPort>) -> Option<Emulator<Cart, Callbacks, FourPlayerAdapterPort>> {
+        self.players[player].replace(emulator)
+    }
+
+    /// Unplug the instance from a port (0-3).
+    pub fn disconnect(&mut self, player: usize) -> Option<Emulator<Cart, Callbacks, FourPlayerAdapterPort>> {
+        self.acknowledged[player] = false;
+        self.acknowledging[player] = false;
+        self.players[player].take()
+    }
+
+    /// Disconnect all instances.
+    pub fn into_players(self) -> [Option<Emulator<Cart, Callbacks, FourPlayerAdapterPort>>; 4] {
+        self.players
+    }
+
+    /// Send the next clock pulse, or start the next byte.
+    fn clock_adapter(&mut self) {
+        if self.bit_index == 8 {
+            self.bit_index = 0;
+        }
+
+        // Sample each Game Boy's SO, then shift out the next bit.
+        let shift = 7 - self.bit_index;
+        for (player, emulator) in self.players.iter_mut().enumerate() {
+            let (so, port) = match emulator {
+                Some(e) => {
+                    let port = e.get_serial_device_mut();
+                    (port.so, Some(port))
+                },
+                None => (true, None)
+            };
+            self.incoming[player] = (self.incoming[player] << 1) | (so as u8);
+            if let Some(port) = port {
+                port.pulse = Some((self.outgoing[player] >> shift) & 1 != 0);
+            }
+        }
+
+        self.bit_index += 1;
+        if self.bit_index < 8 {
+            self.countdown = BIT_PERIOD;
+            return
+        }
+
+        self.countdown = BASE_BYTE_GAP + (self.rate & 0xF) as u32 * RATE_BYTE_GAP;
+        self.receive_bytes();
+        self.prepare_bytes();
+    }
+
+    /// Handle the bytes received from each player.
+    fn receive_bytes(&mut self) {
+        let incoming = self.incoming;
+        match self.phase {
+            FourPlayerAdapterPhase::Ping => {
+                for (player, acknowledging) in self.acknowledging.iter_mut().enumerate() {
+                    let ack = incoming[player] == ACK;
+                    if self.players[player].is_none() {
+                        *acknowledging = false;
+                    }
+                    else if self.byte_index == 0 {
+                        *acknowledging = ack;
+                    }
+                    else if self.byte_index == 1 {
+                        *acknowledging &= ack;
+                    }
+                }
+
+                match self.byte_index {
+                    2 if self.acknowledging[0] => self.rate = incoming[0],
+                    3 if self.acknowledging[0] => self.size = (incoming[0] as usize).clamp(1, MAX_PACKET_SIZE),
+                    _ => ()
+                }
+
+                if incoming[0] == START_TRANSMISSION {
+                    self.command_count += 1;
+                }
+                else {
+                    self.command_count = 0;
+                }
+
+                self.byte_index += 1;
+                if self.byte_index == 4 {
+                    self.byte_index = 0;
+                    self.acknowledged = self.acknowledging;
+                    if self.command_count >= 4 {
+                        self.command_count = 0;
+                        self.phase = FourPlayerAdapterPhase::Starting;
+                    }
+                }
+            },
+            FourPlayerAdapterPhase::Starting => {
+                self.byte_index += 1;
+                if self.byte_index == 4 {
+                    self.byte_index = 0;
+                    self.received_packets = [[0; MAX_PACKET_SIZE]; 4];
+                    self.relayed_packets = [[0; MAX_PACKET_SIZE]; 4];
+                    self.phase = FourPlayerAdapterPhase::Transmission;
+                }
+            },
+            FourPlayerAdapterPhase::Transmission => {
+                if self.byte_index < self.size {
+                    for (player, packet) in self.received_packets.iter_mut().enumerate() {
+                        packet[self.byte_index] = if self.players[player].is_some() { incoming[player] } else { 0 };
+                    }
+                }
+
+                if incoming[0] == RETURN_TO_PING {
+                    self.command_count += 1;
+                }
+                else {
+                    self.command_count = 0;
+                }
+
+                if self.command_count >= 4 {
+                    self.command_count = 0;
+                    self.byte_index = 0;
+                    self.acknowledged = [false; 4];
+                    self.acknowledging = [false; 4];
+                    self.phase = FourPlayerAdapterPhase::Ping;
+                    return
+                }
+
+                self.byte_index += 1;
+                if self.byte_index == self.size * 4 {
+                    self.byte_index = 0;
+                    self.relayed_packets = self.received_packets;
+                }
+            }
+        }
+    }
+
+    /// Load the next byte to send to each player.
+    fn prepare_bytes(&mut self) {
+        let connected = self.acknowledged
+            .iter()
+            .enumerate()
+            .fold(0u8, |bits, (player, &ack)| bits | ((ack as u8) << (4 + player)));
+
+        for player in 0..4 {
+            self.outgoing[player] = match self.phase {
+                FourPlayerAdapterPhase::Ping if self.byte_index == 0 => PING_HEADER,
+                FourPlayerAdapterPhase::Ping => connected | (player as u8 + 1),
+                FourPlayerAdapterPhase::Starting => TRANSMISSION_STARTING,
+                FourPlayerAdapterPhase::Transmission => {
+                    self.relayed_packets[self.byte_index / self.size][self.byte_index % self.size]
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{EmulatedCartridge, NullCartridge};
+    use crate::instance::Model;
+    use crate::instance::tests::{new_emulator, with_large_stack};
+
+    const SB: u16 = 0xFF01;
+    const SC: u16 = 0xFF02;
+
+    type TestAdapter = FourPlayerAdapter<EmulatedCartridge<NullCartridge>, ()>;
+
+    /// Have each player send a byte with the external clock, returning the bytes they received.
+    fn exchange(adapter: &mut TestAdapter, outgoing: [u8; 2]) -> [u8; 2] {
+        for (player, byte) in outgoing.into_iter().enumerate() {
+            let emulator = adapter.player_mut(player).unwrap();
+            emulator.write(SB, byte);
+            emulator.write(SC, 0x80);
+        }
+
+        let mut clocks = 0;
+        while adapter.player_mut(0).unwrap().read(SC) & 0x80 != 0 {
+            adapter.tick();
+            clocks += 1;
+            assert!(clocks < 0x8000, "the adapter stopped clocking");
+        }
+        core::array::from_fn(|player| adapter.player_mut(player).unwrap().read(SB))
+    }
+
+    #[test]
+    fn pings_then_relays_packets() {
+        with_large_stack(pings_then_relays_packets_test)
+    }
+
+    fn pings_then_relays_packets_test() {
+        let players = [
+            Some(new_emulator(FourPlayerAdapterPort::default(), (), Model::DMG)),
+            Some(new_emulator(FourPlayerAdapterPort::default(), (), Model::DMG)),
+            None,
+            None
+        ];
+        let mut adapter = FourPlayerAdapter::new(players);
+
+        // Both acknowledge the first ping packet, and player 1 sets RATE and SIZE.
+        assert_eq!(exchange(&mut adapter, [ACK, ACK]), [PING_HEADER, PING_HEADER]);
+        assert_eq!(exchange(&mut adapter, [ACK, ACK]), [0x01, 0x02]);
+        assert_eq!(exchange(&mut adapter, [0x00, 0x00]), [0x01, 0x02]);
+        assert_eq!(exchange(&mut adapter, [0x01, 0x00]), [0x01, 0x02]);
+        assert_eq!(adapter.rate(), 0x00);
+        assert_eq!(adapter.packet_size(), 1);
+
+        // The next ping packet reports both as connected, and player 1 starts the transmission.
+        assert_eq!(exchange(&mut adapter, [START_TRANSMISSION; 2]), [PING_HEADER, PING_HEADER]);
+        for _ in 0..3 {
+            assert_eq!(exchange(&mut adapter, [START_TRANSMISSION; 2]), [0x31, 0x32]);
+        }
+        assert_eq!(adapter.phase(), FourPlayerAdapterPhase::Starting);
+
+        for _ in 0..4 {
+            assert_eq!(exchange(&mut adapter, [0x00, 0x00]), [TRANSMISSION_STARTING; 2]);
+        }
+        assert_eq!(adapter.phase(), FourPlayerAdapterPhase::Transmission);
+
+        // Each player sends its packet at the start of a cycle, which is relayed during the next one.
+        for (i, outgoing) in [[0x11, 0x22], [0x00, 0x00], [0x00, 0x00], [0x00, 0x00]].into_iter().enumerate() {
+            assert_eq!(exchange(&mut adapter, outgoing), [0x00, 0x00], "byte {i}");
+        }
+        for expected in [0x11, 0x22, 0x00, 0x00] {
+            assert_eq!(exchange(&mut adapter, [0x00, 0x00]), [expected; 2]);
+        }
+
+        for _ in 0..4 {
+            exchange(&mut adapter, [RETURN_TO_PING, 0x00]);
+        }
+        assert_eq!(adapter.phase(), FourPlayerAdapterPhase::Ping);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::cartridge::Cartridge;
+use crate::instance::{Emulator, EmulatorCallbacks};
+use crate::serial::SerialDevice;
+
+/// One end of a [`LinkCable`].
+///
+/// Pass this to [`Emulator::new`] as the serial device for each instance being linked.
+#[derive(Copy, Clone, Default)]
+pub struct LinkCablePort {
+    /// Last known state of this Game Boy's SO line.
+    so: bool,
+
+    /// State of the other Game Boy's SO line.
+    remote_so: bool,
+
+    /// A clock pulse generated by this Game Boy, with the bit it shifted out.
+    outgoing_pulse: Option<bool>,
+
+    /// A clock pulse generated by the other Game Boy, with the bit it shifted out.
+    incoming_pulse: Option<bool>
+}
+
+impl SerialDevice for LinkCablePort {
+    fn exchange_bit(&mut self, outgoing: bool) -> bool {
+        self.so = outgoing;
+        self.outgoing_pulse = Some(outgoing);
+        self.remote_so
+    }
+
+    fn poll_external_clock(&mut self, outgoing: bool, _transferring: bool) -> Option<bool> {
+        self.so = outgoing;
+        self.incoming_pulse.take()
+    }
+}
+
+impl LinkCablePort {
+    /// Pass clock pulses and SO lines between both ends.
+    fn connect(a: &mut LinkCablePort, b: &mut LinkCablePort) {
+        if let Some(bit) = a.outgoing_pulse.take() {
+            b.incoming_pulse = Some(bit);
+        }
+        if let Some(bit) = b.outgoing_pulse.take() {
+            a.incoming_pulse = Some(bit);
+        }
+        a.remote_so = b.so;
+        b.remote_so = a.so;
+    }
+}
+
+/// Connects the serial ports of two instances in the same process, running them in lockstep.
+///
+/// Either instance may use its internal clock to drive the transfer. Since both instances are
+/// ticked together, the result is fully deterministic.
+pub struct LinkCable<
+    CartA: Cartridge,
+    CallbacksA: EmulatorCallbacks<CartA, LinkCablePort>,
+    CartB: Cartridge,
+    CallbacksB: EmulatorCallbacks<CartB, LinkCablePort>
+> {
+    a: Emulator<CartA, CallbacksA, LinkCablePort>,
+    b: Emulator<CartB, CallbacksB, LinkCablePort>
+}
+
+impl<
+    CartA: Cartridge,
+    CallbacksA: EmulatorCallbacks<CartA, LinkCablePort>,
+    CartB: Cartridge,
+    CallbacksB: EmulatorCallbacks<CartB, LinkCablePort>
+> LinkCable<CartA, CallbacksA, CartB, CallbacksB> {
+    /// Connect two instances.
+    pub fn new(a: Emulator<CartA, CallbacksA, LinkCablePort>, b: Emulator<CartB, CallbacksB, LinkCablePort>) -> Self {
+        Self { a, b }
+    }
+
+    /// Run one SoC clock cycle at base speed (4 MiHz) on both instances.
+    ///
+    /// An instance in double speed mode runs two SoC clock cycles so both stay in sync in real time.
+    pub fn tick(&mut self) {
+        Self::tick_instance(&mut self.a);
+        Self::tick_instance(&mut self.b);
+        LinkCablePort::connect(self.a.get_serial_device_mut(), self.b.get_serial_device_mut());
+    }
+
+    fn tick_instance<Cart: Cartridge, Callbacks: EmulatorCallbacks<Cart, LinkCablePort>>(emulator: &mut Emulator<Cart, Callbacks, LinkCablePort>) {
+        let cycles = if emulator.in_double_speed_mode() { 2 } else { 1 };
+        for _ in 0..cycles {
+            emulator.tick_soc(true);
+            emulator.tick_soc(false);
+        }
+    }
+
+    /// Access the first instance.
+    pub fn first(&self) -> &Emulator<CartA, CallbacksA, LinkCablePort> {
+        &self.a
+    }
+
+    /// Access the first instance.
+    pub fn first_mut(&mut self) -> &mut Emulator<CartA, CallbacksA, LinkCablePort> {
+        &mut self.a
+    }
+
+    /// Access the second instance.
+    pub fn second(&self) -> &Emulator<CartB, CallbacksB, LinkCablePort> {
+        &self.b
+    }
+
+    /// Access the second instance.
+    pub fn second_mut(&mut self) -> &mut Emulator<CartB, CallbacksB, LinkCablePort> {
+        &mut self.b
+    }
+
+    /// Disconnect the cable to get both instances back.
+    pub fn disconnect(self) -> (Emulator<CartA, CallbacksA, LinkCablePort>, Emulator<CartB, CallbacksB, LinkCablePort>) {
+        (self.a, self.b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::Model;
+    use crate::instance::tests::new_emulator;
+
+    const SB: u16 = 0xFF01;
+    const SC: u16 = 0xFF02;
+    const IF: u16 = 0xFF0F;
+    const SERIAL_INTERRUPT: u8 = 0b01000;
+
+    #[test]
+    fn exchanges_bytes_with_either_side_as_clock_master() {
+        let a = new_emulator(LinkCablePort::default(), (), Model::DMG);
+        let b = new_emulator(LinkCablePort::default(), (), Model::CGB);
+        let mut cable = LinkCable::new(a, b);
+
+        for (master_is_first, (byte_a, byte_b)) in [(true, (0x55, 0xA3)), (false, (0x0F, 0xC0))] {
+            let (control_a, control_b) = if master_is_first { (0x81, 0x80) } else { (0x80, 0x81) };
+            cable.first_mut().write(SB, byte_a);
+            cable.second_mut().write(SB, byte_b);
+            cable.first_mut().write(IF, 0);
+            cable.second_mut().write(IF, 0);
+            cable.second_mut().write(SC, control_b);
+            cable.first_mut().write(SC, control_a);
+
+            for _ in 0..512 * 9 {
+                cable.tick();
+            }
+
+            assert_eq!(cable.first_mut().read(SB), byte_b);
+            assert_eq!(cable.second_mut().read(SB), byte_a);
+            for emulator_if in [cable.first_mut().read(IF), cable.second_mut().read(IF)] {
+                assert_eq!(emulator_if & SERIAL_INTERRUPT, SERIAL_INTERRUPT);
+            }
+            assert_eq!(cable.first_mut().read(SC) & 0x80, 0);
+            assert_eq!(cable.second_mut().read(SC) & 0x80, 0);
+        }
+    }
+
+    #[test]
+    fn external_clock_side_waits_for_master() {
+        let a = new_emulator(LinkCablePort::default(), (), Model::DMG);
+        let b = new_emulator(LinkCablePort::default(), (), Model::DMG);
+        let mut cable = LinkCable::new(a, b);
+        cable.second_mut().write(SB, 0x42);
+        cable.second_mut().write(SC, 0x80);
+
+        for _ in 0..512 * 16 {
+            cable.tick();
+        }
+        assert_eq!(cable.second_mut().read(SC) & 0x80, 0x80);
+        assert_eq!(cable.second_mut().read(SB), 0x42);
+    }
+}
@@ -0,0 +1,856 @@
+//! Mobile Adapter GB emulation.
+//!
+//! The Game Boy is the clock master and exchanges packets with the adapter in this format:
+//!
+//! | Size | Field                                                             |
+//! |------|-------------------------------------------------------------------|
+//! | 2    | Magic bytes (0x99, 0x66)                                          |
+//! | 1    | Command                                                           |
+//! | 1    | Unused (0x00)                                                     |
+//! | 2    | Data length (big endian)                                          |
+//! | n    | Data                                                              |
+//! | 2    | Checksum: sum of command, unused, length and data (BE)            |
+//! | 1    | Device ID of the sender (0x80 for the Game Boy)                   |
+//! | 1    | Acknowledgement sent back by the receiver (command ^ 0x80)        |
+//!
+//! While receiving a packet, the adapter sends 0xD2 until the last two bytes. Its reply packet
+//! uses the command with bit 7 set, and is clocked out while the Game Boy sends 0x4B.
+//!
+//! Network operations are handled by a [`MobileBackend`]. [`MockMobileServer`] implements one
+//! entirely in-process for testing without a network connection.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::serial::ByteSerialDevice;
+
+const MAGIC: [u8; 2] = [0x99, 0x66];
+const IDLE: u8 = 0xD2;
+const GAME_BOY_ID: u8 = 0x80;
+
+const ACK_ERROR_UNKNOWN_COMMAND: u8 = 0xF0;
+const ACK_ERROR_CHECKSUM: u8 = 0xF1;
+
+/// Sent for a packet that does not come from a Game Boy.
+const ACK_ERROR_INTERNAL: u8 = 0xF2;
+
+const COMMAND_BEGIN_SESSION: u8 = 0x10;
+const COMMAND_END_SESSION: u8 = 0x11;
+const COMMAND_DIAL: u8 = 0x12;
+const COMMAND_HANG_UP: u8 = 0x13;
+const COMMAND_TRANSFER_DATA: u8 = 0x15;
+const COMMAND_TELEPHONE_STATUS: u8 = 0x17;
+const COMMAND_SIO32_MODE: u8 = 0x18;
+const COMMAND_READ_CONFIG: u8 = 0x19;
+const COMMAND_WRITE_CONFIG: u8 = 0x1A;
+const COMMAND_CONNECTION_CLOSED: u8 = 0x1F;
+const COMMAND_ISP_LOGIN: u8 = 0x21;
+const COMMAND_ISP_LOGOUT: u8 = 0x22;
+const COMMAND_OPEN_TCP: u8 = 0x23;
+const COMMAND_CLOSE_TCP: u8 = 0x24;
+const COMMAND_DNS_QUERY: u8 = 0x28;
+const COMMAND_ERROR: u8 = 0x6E;
+
+/// Error codes sent with an error (0x6E) reply.
+const ERROR_INVALID_DATA: u8 = 0x01;
+const ERROR_FAILED: u8 = 0x02;
+const ERROR_NOT_CONNECTED: u8 = 0x03;
+
+const SESSION_MAGIC: &[u8] = b"NINTENDO";
+
+/// Size of the configuration EEPROM in bytes.
+pub const MOBILE_CONFIG_SIZE: usize = 0xC0;
+
+/// Largest amount of data the adapter will transfer in one packet.
+const MAX_TRANSFER_SIZE: usize = 0xFE;
+
+/// Largest amount of configuration data that can be read or written in one packet.
+const MAX_CONFIG_TRANSFER_SIZE: usize = 0x80;
+
+/// Connection ID used for data sent over a telephone call.
+const TELEPHONE_CONNECTION_ID: u8 = 0xFF;
+
+/// Number of TCP connections that can be open at once.
+const MAX_TCP_CONNECTIONS: usize = 2;
+
+/// Telephone status reported by the adapter.
+const TELEPHONE_IDLE: u8 = 0x00;
+const TELEPHONE_CALLING: u8 = 0x04;
+
+/// Type of adapter, reported in the device ID byte.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MobileAdapterType {
+    /// Blue adapter for PDC phones.
+    Blue = 0x88,
+
+    /// Yellow adapter for cdmaOne phones.
+    Yellow = 0x89,
+
+    /// Green adapter for PHS phones.
+    Green = 0x8A,
+
+    /// Red adapter for DDI phones.
+    Red = 0x8B
+}
+
+/// A connection handled by a [`MobileBackend`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum MobileConnection {
+    /// The telephone call.
+    Telephone,
+
+    /// A TCP connection, identified by the ID given to the Game Boy.
+    TCP(u8)
+}
+
+/// Handles network operations on behalf of a [`MobileAdapter`].
+///
+/// Methods are called synchronously while the Game Boy is transferring a packet, so they should
+/// not block for long.
+pub trait MobileBackend {
+    /// Dial a telephone number. Return `true` if the call was connected.
+    fn dial(&mut self, number: &str) -> bool;
+
+    /// End the telephone call.
+    fn hang_up(&mut self);
+
+    /// Log into the ISP with the given credentials and DNS servers. Return the assigned IP
+    /// address, or `None` if logging in failed.
+    fn login(&mut self, id: &[u8], password: &[u8], dns: [[u8; 4]; 2]) -> Option<[u8; 4]>;
+
+    /// Log out from the ISP.
+    fn logout(&mut self);
+
+    /// Resolve a domain name to an IPv4 address.
+    fn dns_query(&mut self, domain: &str) -> Option<[u8; 4]>;
+
+    /// Open a TCP connection. Return `false` if the connection could not be opened.
+    fn open_tcp(&mut self, connection: u8, address: [u8; 4], port: u16) -> bool;
+
+    /// Close a TCP connection.
+    fn close_tcp(&mut self, connection: u8);
+
+    /// Send data. Return `false` if the connection is closed.
+    fn send(&mut self, connection: MobileConnection, data: &[u8]) -> bool;
+
+    /// Receive any data that is available into `buffer`, returning the number of bytes received,
+    /// or `None` if the connection was closed by the remote end.
+    fn receive(&mut self, connection: MobileConnection, buffer: &mut [u8]) -> Option<usize>;
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum PacketState {
+    Magic(usize),
+    Command,
+    Unused,
+    LengthHigh,
+    LengthLow,
+    Data,
+    ChecksumHigh,
+    ChecksumLow,
+    DeviceID,
+    Acknowledge,
+    Responding
+}
+
+/// Emulates the Mobile Adapter GB.
+///
+/// Connect it to the serial port with [`ByteSerialAdapter`](crate::serial::ByteSerialAdapter).
+pub struct MobileAdapter<B: MobileBackend> {
+    backend: B,
+    adapter_type: MobileAdapterType,
+    config: [u8; MOBILE_CONFIG_SIZE],
+
+    state: PacketState,
+    command: u8,
+    length: u16,
+    checksum: u16,
+    received_checksum: u16,
+    packet_data: Vec<u8>,
+    acknowledgement: u8,
+    response: VecDeque<u8>,
+
+    in_session: bool,
+    in_call: bool,
+    logged_in: bool,
+    tcp_connections: [bool; MAX_TCP_CONNECTIONS]
+}
+
+impl<B: MobileBackend> MobileAdapter<B> {
+    /// Create an adapter with the given configuration EEPROM contents.
+    pub fn new(backend: B, adapter_type: MobileAdapterType, config: [u8; MOBILE_CONFIG_SIZE]) -> Self {
+        Self {
+            backend,
+            adapter_type,
+            config,
+            state: PacketState::Magic(0),
+            command: 0,
+            length: 0,
+            checksum: 0,
+            received_checksum: 0,
+            packet_data: Vec::new(),
+            acknowledgement: 0,
+            response: VecDeque::new(),
+            in_session: false,
+            in_call: false,
+            logged_in: false,
+            tcp_connections: [false; MAX_TCP_CONNECTIONS]
+        }
+    }
+
+    /// Get the configuration EEPROM contents, e.g. to save them.
+    pub fn config(&self) -> &[u8; MOBILE_CONFIG_SIZE] {
+        &self.config
+    }
+
+    /// Get the configuration EEPROM contents mutably.
+    pub fn config_mut(&mut self) -> &mut [u8; MOBILE_CONFIG_SIZE] {
+        &mut self.config
+    }
+
+    /// Get a reference to the backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Get a mutable reference to the backend.
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Return `true` if the Game Boy has begun a session.
+    pub fn in_session(&self) -> bool {
+        self.in_session
+    }
+
+    fn device_id(&self) -> u8 {
+        self.adapter_type as u8
+    }
+
+    fn receive(&mut self, byte: u8) {
+        self.state = match self.state {
+            PacketState::Magic(i) => {
+                if byte != MAGIC[i] {
+                    PacketState::Magic(if byte == MAGIC[0] { 1 } else { 0 })
+                }
+                else if i + 1 == MAGIC.len() {
+                    self.checksum = 0;
+                    self.packet_data.clear();
+                    PacketState::Command
+                }
+                else {
+                    PacketState::Magic(i + 1)
+                }
+            },
+            PacketState::Command => {
+                self.command = byte;
+                self.add_to_checksum(byte);
+                PacketState::Unused
+            },
+            PacketState::Unused => {
+                self.add_to_checksum(byte);
+                PacketState::LengthHigh
+            },
+            PacketState::LengthHigh => {
+                self.length = (byte as u16) << 8;
+                self.add_to_checksum(byte);
+                PacketState::LengthLow
+            },
+            PacketState::LengthLow => {
+                self.length |= byte as u16;
+                self.add_to_checksum(byte);
+                if self.length == 0 { PacketState::ChecksumHigh } else { PacketState::Data }
+            },
+            PacketState::Data => {
+                self.packet_data.push(byte);
+                self.add_to_checksum(byte);
+                if self.packet_data.len() == self.length as usize { PacketState::ChecksumHigh } else { PacketState::Data }
+            },
+            PacketState::ChecksumHigh => {
+                self.received_checksum = (byte as u16) << 8;
+                PacketState::ChecksumLow
+            },
+            PacketState::ChecksumLow => {
+                self.received_checksum |= byte as u16;
+                self.acknowledgement = if self.received_checksum != self.checksum {
+                    ACK_ERROR_CHECKSUM
+                }
+                else if Self::is_known_command(self.command) {
+                    self.command ^ 0x80
+                }
+                else {
+                    ACK_ERROR_UNKNOWN_COMMAND
+                };
+                PacketState::DeviceID
+            },
+            PacketState::DeviceID => {
+                if byte != GAME_BOY_ID {
+                    self.acknowledgement = ACK_ERROR_INTERNAL;
+                }
+                PacketState::Acknowledge
+            },
+            PacketState::Acknowledge => {
+                if self.acknowledgement == self.command ^ 0x80 {
+                    self.process_packet();
+                    PacketState::Responding
+                }
+                else {
+                    PacketState::Magic(0)
+                }
+            },
+            PacketState::Responding => {
+                if self.response.is_empty() { PacketState::Magic(0) } else { PacketState::Responding }
+            }
+        }
+    }
+
+    fn add_to_checksum(&mut self, byte: u8) {
+        self.checksum = self.checksum.wrapping_add(byte as u16);
+    }
+
+    fn is_known_command(command: u8) -> bool {
+        matches!(
+            command,
+            COMMAND_BEGIN_SESSION | COMMAND_END_SESSION | COMMAND_DIAL | COMMAND_HANG_UP | COMMAND_TRANSFER_DATA
+                | COMMAND_TELEPHONE_STATUS | COMMAND_SIO32_MODE | COMMAND_READ_CONFIG | COMMAND_WRITE_CONFIG
+                | COMMAND_ISP_LOGIN | COMMAND_ISP_LOGOUT | COMMAND_OPEN_TCP | COMMAND_CLOSE_TCP | COMMAND_DNS_QUERY
+        )
+    }
+
+    fn process_packet(&mut self) {
+        let command = self.command;
+        let data = core::mem::take(&mut self.packet_data);
+
+        if !self.in_session && command != COMMAND_BEGIN_SESSION {
+            self.respond_error(command, ERROR_NOT_CONNECTED);
+        }
+        else {
+            match command {
+                COMMAND_BEGIN_SESSION => {
+                    if data == SESSION_MAGIC && !self.in_session {
+                        self.in_session = true;
+                        self.respond(command, SESSION_MAGIC);
+                    }
+                    else {
+                        self.respond_error(command, ERROR_INVALID_DATA);
+                    }
+                },
+                COMMAND_END_SESSION => {
+                    self.reset_session();
+                    self.respond(command, &[]);
+                },
+                COMMAND_DIAL => self.dial(&data),
+                COMMAND_HANG_UP => {
+                    if self.in_call {
+                        self.hang_up();
+                        self.respond(command, &[]);
+                    }
+                    else {
+                        self.respond_error(command, ERROR_NOT_CONNECTED);
+                    }
+                },
+                COMMAND_TRANSFER_DATA => self.transfer_data(&data),
+                COMMAND_TELEPHONE_STATUS => {
+                    let status = if self.in_call { TELEPHONE_CALLING } else { TELEPHONE_IDLE };
+                    self.respond(command, &[status, 0x4D, 0x00]);
+                },
+                COMMAND_SIO32_MODE => {
+                    // Only 8-bit transfers are emulated, so stay in 8-bit mode.
+                    self.respond_error(command, ERROR_INVALID_DATA);
+                },
+                COMMAND_READ_CONFIG => self.read_config(&data),
+                COMMAND_WRITE_CONFIG => self.write_config(&data),
+                COMMAND_ISP_LOGIN => self.login(&data),
+                COMMAND_ISP_LOGOUT => {
+                    if self.logged_in {
+                        self.logout();
+                        self.respond(command, &[]);
+                    }
+                    else {
+                        self.respond_error(command, ERROR_NOT_CONNECTED);
+                    }
+                },
+                COMMAND_OPEN_TCP => self.open_tcp(&data),
+                COMMAND_CLOSE_TCP => {
+                    match data.first().map(|&c| c as usize) {
+                        Some(c) if c < MAX_TCP_CONNECTIONS && self.tcp_connections[c] => {
+                            self.tcp_connections[c] = false;
+                            self.backend.close_tcp(c as u8);
+                            self.respond(command, &data[..1]);
+                        },
+                        _ => self.respond_error(command, ERROR_INVALID_DATA)
+                    }
+                },
+                COMMAND_DNS_QUERY => {
+                    let address = if !self.logged_in {
+                        None
+                    }
+                    else {
+                        core::str::from_utf8(&data).ok().and_then(|domain| self.backend.dns_query(domain))
+                    };
+                    match address {
+                        Some(address) => self.respond(command, &address),
+                        None => self.respond_error(command, ERROR_FAILED)
+                    }
+                },
+                _ => unreachable!()
+            }
+        }
+
+        self.packet_data = data;
+    }
+
+    fn dial(&mut self, data: &[u8]) {
+        // The first byte is the dialing protocol, which does not matter here.
+        let number = data.get(1..).and_then(|n| core::str::from_utf8(n).ok());
+        match number {
+            _ if self.in_call => self.respond_error(COMMAND_DIAL, ERROR_FAILED),
+            Some(number) if self.backend.dial(number) => {
+                self.in_call = true;
+                self.respond(COMMAND_DIAL, &[]);
+            },
+            Some(_) => self.respond_error(COMMAND_DIAL, ERROR_FAILED),
+            None => self.respond_error(COMMAND_DIAL, ERROR_INVALID_DATA)
+        }
+    }
+
+    fn transfer_data(&mut self, data: &[u8]) {
+        let Some((&id, outgoing)) = data.split_first() else {
+            self.respond_error(COMMAND_TRANSFER_DATA, ERROR_INVALID_DATA);
+            return
+        };
+
+        let connection = if id == TELEPHONE_CONNECTION_ID && self.in_call && !self.logged_in {
+            MobileConnection::Telephone
+        }
+        else if (id as usize) < MAX_TCP_CONNECTIONS && self.tcp_connections[id as usize] {
+            MobileConnection::TCP(id)
+        }
+        else {
+            self.respond_error(COMMAND_TRANSFER_DATA, ERROR_NOT_CONNECTED);
+            return
+        };
+
+        let mut reply = [0u8; 1 + MAX_TRANSFER_SIZE];
+        reply[0] = id;
+        let received = if outgoing.is_empty() || self.backend.send(connection, outgoing) {
+            self.backend.receive(connection, &mut reply[1..])
+        }
+        else {
+            None
+        };
+
+        match received {
+            Some(length) => self.respond(COMMAND_TRANSFER_DATA, &reply[..1 + length.min(MAX_TRANSFER_SIZE)]),
+            None => {
+                match connection {
+                    MobileConnection::Telephone => self.hang_up(),
+                    MobileConnection::TCP(c) => {
+                        self.tcp_connections[c as usize] = false;
+                        self.backend.close_tcp(c);
+                    }
+                }
+                self.respond(COMMAND_CONNECTION_CLOSED, &[id]);
+            }
+        }
+    }
+
+    fn read_config(&mut self, data: &[u8]) {
+        match *data {
+            [offset, length] if (length as usize) <= MAX_CONFIG_TRANSFER_SIZE && offset as usize + length as usize <= MOBILE_CONFIG_SIZE => {
+                let mut reply = [0u8; 1 + MAX_CONFIG_TRANSFER_SIZE];
+                reply[0] = offset;
+                reply[1..][..length as usize].copy_from_slice(&self.config[offset as usize..][..length as usize]);
+                self.respond(COMMAND_READ_CONFIG, &reply[..1 + length as usize]);
+            },
+            _ => self.respond_error(COMMAND_READ_CONFIG, ERROR_INVALID_DATA)
+        }
+    }
+
+    fn write_config(&mut self, data: &[u8]) {
+        match data.split_first() {
+            Some((&offset, bytes)) if bytes.len() <= MAX_CONFIG_TRANSFER_SIZE && offset as usize + bytes.len() <= MOBILE_CONFIG_SIZE => {
+                self.config[offset as usize..][..bytes.len()].copy_from_slice(bytes);
+                self.respond(COMMAND_WRITE_CONFIG, &[offset, bytes.len() as u8]);
+            },
+            _ => self.respond_error(COMMAND_WRITE_CONFIG, ERROR_INVALID_DATA)
+        }
+    }
+
+    fn login(&mut self, data: &[u8]) {
+        // ID length, ID, password length, password, then two DNS servers
+        let parsed = (|| {
+            let (&id_length, data) = data.split_first()?;
+            let id = data.get(..id_length as usize)?;
+            let data = &data[id_length as usize..];
+            let (&password_length, data) = data.split_first()?;
+            let password = data.get(..password_length as usize)?;
+            let data = &data[password_length as usize..];
+            let dns: [u8; 8] = data.get(..8)?.try_into().ok()?;
+            Some((id, password, dns))
+        })();
+
+        let Some((id, password, dns)) = parsed else {
+            self.respond_error(COMMAND_ISP_LOGIN, ERROR_INVALID_DATA);
+            return
+        };
+        if !self.in_call || self.logged_in {
+            self.respond_error(COMMAND_ISP_LOGIN, ERROR_NOT_CONNECTED);
+            return
+        }
+
+        let dns_servers = [[dns[0], dns[1], dns[2], dns[3]], [dns[4], dns[5], dns[6], dns[7]]];
+        match self.backend.login(id, password, dns_servers) {
+            Some(address) => {
+                self.logged_in = true;
+                let mut reply = [0u8; 12];
+                reply[..4].copy_from_slice(&address);
+                reply[4..].copy_from_slice(&dns);
+                self.respond(COMMAND_ISP_LOGIN, &reply);
+            },
+            None => self.respond_error(COMMAND_ISP_LOGIN, ERROR_FAILED)
+        }
+    }
+
+    fn open_tcp(&mut self, data: &[u8]) {
+        let &[a, b, c, d, port_high, port_low] = data else {
+            self.respond_error(COMMAND_OPEN_TCP, ERROR_INVALID_DATA);
+            return
+        };
+        if !self.logged_in {
+            self.respond_error(COMMAND_OPEN_TCP, ERROR_NOT_CONNECTED);
+            return
+        }
+
+        let Some(connection) = self.tcp_connections.iter().position(|open| !open) else {
+            self.respond_error(COMMAND_OPEN_TCP, ERROR_FAILED);
+            return
+        };
+        if self.backend.open_tcp(connection as u8, [a, b, c, d], u16::from_be_bytes([port_high, port_low])) {
+            self.tcp_connections[connection] = true;
+            self.respond(COMMAND_OPEN_TCP, &[connection as u8]);
+        }
+        else {
+            self.respond_error(COMMAND_OPEN_TCP, ERROR_FAILED);
+        }
+    }
+
+    fn hang_up(&mut self) {
+        if self.logged_in {
+            self.logout();
+        }
+        self.in_call = false;
+        self.backend.hang_up();
+    }
+
+    fn logout(&mut self) {
+        for (connection, open) in self.tcp_connections.iter_mut().enumerate() {
+            if *open {
+                *open = false;
+                self.backend.close_tcp(connection as u8);
+            }
+        }
+        self.logged_in = false;
+        self.backend.logout();
+    }
+
+    fn reset_session(&mut self) {
+        if self.in_call {
+            self.hang_up();
+        }
+        self.in_session = false;
+    }
+
+    fn respond_error(&mut self, command: u8, error: u8) {
+        self.respond(COMMAND_ERROR, &[command, error]);
+    }
+
+    fn respond(&mut self, command: u8, data: &[u8]) {
+        let header = [command | 0x80, 0x00, (data.len() >> 8) as u8, data.len() as u8];
+        let checksum = header.iter().chain(data).fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+
+        self.response.clear();
+        self.response.extend(MAGIC);
+        self.response.extend(header);
+        self.response.extend(data);
+        self.response.extend(checksum.to_be_bytes());
+        self.response.extend([self.device_id(), 0x00]);
+    }
+}
+
+impl<B: MobileBackend> ByteSerialDevice for MobileAdapter<B> {
+    fn exchange_byte(&mut self, outgoing: u8) -> u8 {
+        let reply = match self.state {
+            PacketState::DeviceID => self.device_id(),
+            PacketState::Acknowledge => self.acknowledgement,
+            PacketState::Responding => self.response.pop_front().unwrap_or(IDLE),
+            _ => IDLE
+        };
+        self.receive(outgoing);
+        reply
+    }
+}
+
+/// Handles data sent to a TCP service on a [`MockMobileServer`].
+///
+/// Return the data to send back, or `None` to close the connection.
+pub type MockService = Box<dyn FnMut(&[u8]) -> Option<Vec<u8>>>;
+
+struct MockConnection {
+    service: ([u8; 4], u16),
+    pending: VecDeque<u8>,
+    closed: bool
+}
+
+/// An in-process [`MobileBackend`] for testing without a network connection.
+///
+/// Telephone calls are answered with an echo of the data sent. TCP connections are handled by
+/// [`MockService`] callbacks registered for an address and port.
+#[derive(Default)]
+pub struct MockMobileServer {
+    hosts: BTreeMap<String, [u8; 4]>,
+    services: BTreeMap<([u8; 4], u16), MockService>,
+    connections: BTreeMap<MobileConnection, MockConnection>,
+    address: [u8; 4],
+    numbers: Vec<String>
+}
+
+impl MockMobileServer {
+    /// Create a server that assigns `address` to the Game Boy when logging in.
+    pub fn new(address: [u8; 4]) -> Self {
+        Self { address, ..Default::default() }
+    }
+
+    /// Add a DNS entry.
+    pub fn add_host(&mut self, domain: &str, address: [u8; 4]) {
+        self.hosts.insert(String::from(domain), address);
+    }
+
+    /// Add a TCP service.
+    pub fn add_service(&mut self, address: [u8; 4], port: u16, service: MockService) {
+        self.services.insert((address, port), service);
+    }
+
+    /// Get every telephone number dialed so far.
+    pub fn dialed_numbers(&self) -> &[String] {
+        &self.numbers
+    }
+}
+
+impl MobileBackend for MockMobileServer {
+    fn dial(&mut self, number: &str) -> bool {
+        self.numbers.push(String::from(number));
+        self.connections.insert(MobileConnection::Telephone, MockConnection { service: ([0; 4], 0), pending: VecDeque::new(), closed: false });
+        true
+    }
+
+    fn hang_up(&mut self) {
+        self.connections.remove(&MobileConnection::Telephone);
+    }
+
+    fn login(&mut self, _id: &[u8], _password: &[u8], _dns: [[u8; 4]; 2]) -> Option<[u8; 4]> {
+        Some(self.address)
+    }
+
+    fn logout(&mut self) {}
+
+    fn dns_query(&mut self, domain: &str) -> Option<[u8; 4]> {
+        self.hosts.get(domain).copied()
+    }
+
+    fn open_tcp(&mut self, connection: u8, address: [u8; 4], port: u16) -> bool {
+        if !self.services.contains_key(&(address, port)) {
+            return false
+        }
+        self.connections.insert(MobileConnection::TCP(connection), MockConnection { service: (address, port), pending: VecDeque::new(), closed: false });
+        true
+    }
+
+    fn close_tcp(&mut self, connection: u8) {
+        self.connections.remove(&MobileConnection::TCP(connection));
+    }
+
+    fn send(&mut self, connection: MobileConnection, data: &[u8]) -> bool {
+        let Some(state) = self.connections.get_mut(&connection) else {
+            return false
+        };
+        if state.closed {
+            return false
+        }
+
+        if connection == MobileConnection::Telephone {
+            state.pending.extend(data);
+            return true
+        }
+
+        match self.services.get_mut(&state.service).and_then(|service| service(data)) {
+            Some(reply) => state.pending.extend(reply),
+            None => state.closed = true
+        }
+        true
+    }
+
+    fn receive(&mut self, connection: MobileConnection, buffer: &mut [u8]) -> Option<usize> {
+        let state = self.connections.get_mut(&connection)?;
+        if state.closed && state.pending.is_empty() {
+            return None
+        }
+        let length = buffer.len().min(state.pending.len());
+        for (byte, pending) in buffer.iter_mut().zip(state.pending.drain(..length)) {
+            *byte = pending;
+        }
+        Some(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    const ADAPTER_ID: u8 = MobileAdapterType::Blue as u8;
+    const ADDRESS: [u8; 4] = [10, 0, 0, 2];
+    const ECHO_SERVER: [u8; 4] = [192, 0, 2, 1];
+
+    fn new_adapter() -> MobileAdapter<MockMobileServer> {
+        let mut server = MockMobileServer::new(ADDRESS);
+        server.add_host("echo.example", ECHO_SERVER);
+        server.add_service(ECHO_SERVER, 7, Box::new(|data| Some(data.to_vec())));
+        MobileAdapter::new(server, MobileAdapterType::Blue, [0; MOBILE_CONFIG_SIZE])
+    }
+
+    /// Build a packet as the Game Boy sends it, up to and including the checksum.
+    fn packet(command: u8, data: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[command, 0x00]);
+        bytes.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(data);
+        let checksum = bytes[2..].iter().fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+        bytes.extend_from_slice(&checksum.to_be_bytes());
+        bytes
+    }
+
+    /// Send a packet from `sender`, returning the acknowledgement and, if it was accepted, the
+    /// command and data of the reply.
+    fn send_from(adapter: &mut MobileAdapter<MockMobileServer>, bytes: &[u8], sender: u8) -> (u8, Option<(u8, Vec<u8>)>) {
+        for &byte in bytes {
+            assert_eq!(adapter.exchange_byte(byte), IDLE);
+        }
+        assert_eq!(adapter.exchange_byte(sender), ADAPTER_ID);
+        let acknowledgement = adapter.exchange_byte(0x00);
+        if acknowledgement != bytes[2] ^ 0x80 {
+            return (acknowledgement, None)
+        }
+
+        let mut header = [0u8; 6];
+        for byte in &mut header {
+            *byte = adapter.exchange_byte(0x4B);
+        }
+        assert_eq!(header[..2], MAGIC);
+        let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        let data: Vec<u8> = (0..length).map(|_| adapter.exchange_byte(0x4B)).collect();
+        let checksum = u16::from_be_bytes([adapter.exchange_byte(0x4B), adapter.exchange_byte(0x4B)]);
+        assert_eq!(checksum, header[2..].iter().chain(&data).fold(0u16, |sum, &b| sum.wrapping_add(b as u16)));
+        assert_eq!(adapter.exchange_byte(GAME_BOY_ID), ADAPTER_ID);
+        assert_eq!(adapter.exchange_byte(header[2] ^ 0x80), 0x00);
+        (acknowledgement, Some((header[2], data)))
+    }
+
+    /// Send a command from the Game Boy that must be accepted, returning the reply.
+    fn send(adapter: &mut MobileAdapter<MockMobileServer>, command: u8, data: &[u8]) -> (u8, Vec<u8>) {
+        let (acknowledgement, reply) = send_from(adapter, &packet(command, data), GAME_BOY_ID);
+        assert_eq!(acknowledgement, command ^ 0x80);
+        reply.unwrap()
+    }
+
+    fn begin_session(adapter: &mut MobileAdapter<MockMobileServer>) {
+        assert_eq!(send(adapter, COMMAND_BEGIN_SESSION, SESSION_MAGIC), (COMMAND_BEGIN_SESSION | 0x80, SESSION_MAGIC.to_vec()));
+    }
+
+    fn dial_and_login(adapter: &mut MobileAdapter<MockMobileServer>) {
+        begin_session(adapter);
+        assert_eq!(send(adapter, COMMAND_DIAL, b"\x000755311973"), (COMMAND_DIAL | 0x80, Vec::new()));
+        let mut login = Vec::new();
+        login.extend_from_slice(b"\x04user\x04pass");
+        login.extend_from_slice(&[1, 1, 1, 1, 8, 8, 8, 8]);
+        let (command, reply) = send(adapter, COMMAND_ISP_LOGIN, &login);
+        assert_eq!(command, COMMAND_ISP_LOGIN | 0x80);
+        assert_eq!(reply[..4], ADDRESS);
+        assert_eq!(reply[4..], [1, 1, 1, 1, 8, 8, 8, 8]);
+    }
+
+    #[test]
+    fn session_begins_and_ends() {
+        let mut adapter = new_adapter();
+        assert_eq!(send(&mut adapter, COMMAND_TELEPHONE_STATUS, &[]), (COMMAND_ERROR | 0x80, vec![COMMAND_TELEPHONE_STATUS, ERROR_NOT_CONNECTED]));
+
+        begin_session(&mut adapter);
+        assert!(adapter.in_session());
+        assert_eq!(send(&mut adapter, COMMAND_BEGIN_SESSION, SESSION_MAGIC), (COMMAND_ERROR | 0x80, vec![COMMAND_BEGIN_SESSION, ERROR_INVALID_DATA]));
+
+        assert_eq!(send(&mut adapter, COMMAND_END_SESSION, &[]), (COMMAND_END_SESSION | 0x80, Vec::new()));
+        assert!(!adapter.in_session());
+    }
+
+    #[test]
+    fn dial_and_echo_over_the_telephone() {
+        let mut adapter = new_adapter();
+        begin_session(&mut adapter);
+        assert_eq!(send(&mut adapter, COMMAND_DIAL, b"\x000755311973"), (COMMAND_DIAL | 0x80, Vec::new()));
+        assert_eq!(adapter.backend().dialed_numbers(), ["0755311973"]);
+        assert_eq!(send(&mut adapter, COMMAND_TELEPHONE_STATUS, &[]).1[0], TELEPHONE_CALLING);
+
+        assert_eq!(send(&mut adapter, COMMAND_TRANSFER_DATA, b"\xFFhello"), (COMMAND_TRANSFER_DATA | 0x80, b"\xFFhello".to_vec()));
+
+        assert_eq!(send(&mut adapter, COMMAND_HANG_UP, &[]), (COMMAND_HANG_UP | 0x80, Vec::new()));
+        assert_eq!(send(&mut adapter, COMMAND_TELEPHONE_STATUS, &[]).1[0], TELEPHONE_IDLE);
+    }
+
+    #[test]
+    fn login_and_echo_over_tcp() {
+        let mut adapter = new_adapter();
+        dial_and_login(&mut adapter);
+
+        assert_eq!(send(&mut adapter, COMMAND_DNS_QUERY, b"echo.example"), (COMMAND_DNS_QUERY | 0x80, ECHO_SERVER.to_vec()));
+        assert_eq!(send(&mut adapter, COMMAND_DNS_QUERY, b"unknown.example"), (COMMAND_ERROR | 0x80, vec![COMMAND_DNS_QUERY, ERROR_FAILED]));
+
+        let mut open = ECHO_SERVER.to_vec();
+        open.extend_from_slice(&7u16.to_be_bytes());
+        assert_eq!(send(&mut adapter, COMMAND_OPEN_TCP, &open), (COMMAND_OPEN_TCP | 0x80, vec![0]));
+        assert_eq!(send(&mut adapter, COMMAND_TRANSFER_DATA, b"\x00ping"), (COMMAND_TRANSFER_DATA | 0x80, b"\x00ping".to_vec()));
+        assert_eq!(send(&mut adapter, COMMAND_TRANSFER_DATA, b"\x00"), (COMMAND_TRANSFER_DATA | 0x80, vec![0]));
+        assert_eq!(send(&mut adapter, COMMAND_CLOSE_TCP, &[0]), (COMMAND_CLOSE_TCP | 0x80, vec![0]));
+        assert_eq!(send(&mut adapter, COMMAND_TRANSFER_DATA, b"\x00ping"), (COMMAND_ERROR | 0x80, vec![COMMAND_TRANSFER_DATA, ERROR_NOT_CONNECTED]));
+
+        assert_eq!(send(&mut adapter, COMMAND_ISP_LOGOUT, &[]), (COMMAND_ISP_LOGOUT | 0x80, Vec::new()));
+    }
+
+    #[test]
+    fn config_round_trips() {
+        let mut adapter = new_adapter();
+        begin_session(&mut adapter);
+        assert_eq!(send(&mut adapter, COMMAND_WRITE_CONFIG, &[0x10, 0xAB, 0xCD]), (COMMAND_WRITE_CONFIG | 0x80, vec![0x10, 2]));
+        assert_eq!(send(&mut adapter, COMMAND_READ_CONFIG, &[0x0F, 4]), (COMMAND_READ_CONFIG | 0x80, vec![0x0F, 0x00, 0xAB, 0xCD, 0x00]));
+        assert_eq!(adapter.config()[0x10..0x12], [0xAB, 0xCD]);
+        assert_eq!(send(&mut adapter, COMMAND_READ_CONFIG, &[0xBF, 2]), (COMMAND_ERROR | 0x80, vec![COMMAND_READ_CONFIG, ERROR_INVALID_DATA]));
+    }
+
+    #[test]
+    fn bad_packets_are_not_acknowledged() {
+        let mut adapter = new_adapter();
+
+        let mut bytes = packet(COMMAND_BEGIN_SESSION, SESSION_MAGIC);
+        *bytes.last_mut().unwrap() ^= 1;
+        assert_eq!(send_from(&mut adapter, &bytes, GAME_BOY_ID), (ACK_ERROR_CHECKSUM, None));
+
+        assert_eq!(send_from(&mut adapter, &packet(0x7F, &[]), GAME_BOY_ID), (ACK_ERROR_UNKNOWN_COMMAND, None));
+
+        let bytes = packet(COMMAND_BEGIN_SESSION, SESSION_MAGIC);
+        assert_eq!(send_from(&mut adapter, &bytes, 0x88), (ACK_ERROR_INTERNAL, None));
+        assert!(!adapter.in_session());
+
+        // The adapter is back to waiting for a packet afterwards.
+        begin_session(&mut adapter);
+    }
+}
@@ -0,0 +1,486 @@
+//! Game Boy Printer emulation.
+//!
+//! The Game Boy sends packets to the printer in this format, with the printer replying 0x00 for
+//! every byte except the last two:
+//!
+//! | Size | Field                                                             |
+//! |------|-------------------------------------------------------------------|
+//! | 2    | Magic bytes (0x88, 0x33)                                          |
+//! | 1    | Command                                                           |
+//! | 1    | Compression (1 = compressed)                                      |
+//! | 2    | Data length (little endian)                                       |
+//! | n    | Data                                                              |
+//! | 2    | Checksum: sum of command, compression, length and data (LE)       |
+//! | 1    | 0x00; the printer replies 0x81                                    |
+//! | 1    | 0x00; the printer replies with its status                         |
+
+use alloc::vec::Vec;
+use crate::serial::ByteSerialDevice;
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const ALIVE: u8 = 0x81;
+
+const COMMAND_INITIALIZE: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_STATUS: u8 = 0x0F;
+
+/// Status bits reported by the printer.
+pub const STATUS_CHECKSUM_ERROR: u8 = 0b0000_0001;
+pub const STATUS_PRINTING: u8 = 0b0000_0010;
+pub const STATUS_IMAGE_DATA_FULL: u8 = 0b0000_0100;
+pub const STATUS_UNPROCESSED_DATA: u8 = 0b0000_1000;
+pub const STATUS_PACKET_ERROR: u8 = 0b0001_0000;
+pub const STATUS_PAPER_JAM: u8 = 0b0010_0000;
+pub const STATUS_OTHER_ERROR: u8 = 0b0100_0000;
+pub const STATUS_BATTERY_LOW: u8 = 0b1000_0000;
+
+/// Width of a printed image in pixels.
+pub const PRINTER_WIDTH: usize = 160;
+
+/// Maximum amount of image data the printer can hold (9 bands of 2 tile rows).
+const BUFFER_SIZE: usize = 0x2000;
+
+/// Bytes of image data per 8 pixel tall row of 20 tiles.
+const TILE_ROW_SIZE: usize = 20 * 16;
+
+/// Number of status packets the printer reports as busy after a print command.
+const PRINT_BUSY_STATUS_COUNT: u8 = 8;
+
+/// A page printed by the Game Boy Printer.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PrintedPage {
+    /// Grayscale pixels, from 0 (black) to 255 (white), [`PRINTER_WIDTH`] pixels per row.
+    pub pixels: Vec<u8>,
+
+    /// Height of the image in pixels.
+    pub height: usize,
+
+    /// Number of line feeds before the image (upper nibble of the margin byte).
+    pub margin_before: u8,
+
+    /// Number of line feeds after the image (lower nibble of the margin byte).
+    ///
+    /// If this is 0, the next page is meant to continue directly after this one.
+    pub margin_after: u8,
+
+    /// Palette mapping each 2-bit color to a shade, the same as BGP. A palette of 0 in the print
+    /// command is treated as 0xE4, as the printer does.
+    pub palette: u8,
+
+    /// Exposure (darkness), from 0x00 (lightest) to 0x7F (darkest); 0x40 is normal.
+    pub exposure: u8
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum PacketState {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status
+}
+
+/// Emulates the Game Boy Printer.
+///
+/// Connect it to the serial port with [`ByteSerialAdapter`](crate::serial::ByteSerialAdapter).
+/// `on_print` is called with each printed page. Print commands with nothing to print, such as the
+/// ones games use to feed paper, do not produce a page.
+pub struct GameBoyPrinter<F: FnMut(PrintedPage)> {
+    on_print: F,
+
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    checksum: u16,
+    received_checksum: u16,
+    packet_data: Vec<u8>,
+
+    image_data: Vec<u8>,
+    status: u8,
+    busy_status_count: u8
+}
+
+impl<F: FnMut(PrintedPage)> GameBoyPrinter<F> {
+    pub fn new(on_print: F) -> Self {
+        Self {
+            on_print,
+            state: PacketState::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            checksum: 0,
+            received_checksum: 0,
+            packet_data: Vec::new(),
+            image_data: Vec::with_capacity(BUFFER_SIZE),
+            status: 0,
+            busy_status_count: 0
+        }
+    }
+
+    /// Get the current status byte.
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    fn receive(&mut self, byte: u8) {
+        self.state = match self.state {
+            PacketState::Magic(i) => {
+                if byte != MAGIC[i] {
+                    PacketState::Magic(if byte == MAGIC[0] { 1 } else { 0 })
+                }
+                else if i + 1 == MAGIC.len() {
+                    self.checksum = 0;
+                    self.packet_data.clear();
+                    PacketState::Command
+                }
+                else {
+                    PacketState::Magic(i + 1)
+                }
+            },
+            PacketState::Command => {
+                self.command = byte;
+                self.add_to_checksum(byte);
+                PacketState::Compression
+            },
+            PacketState::Compression => {
+                self.compressed = (byte & 1) != 0;
+                self.add_to_checksum(byte);
+                PacketState::LengthLow
+            },
+            PacketState::LengthLow => {
+                self.length = byte as u16;
+                self.add_to_checksum(byte);
+                PacketState::LengthHigh
+            },
+            PacketState::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.add_to_checksum(byte);
+                if self.length == 0 { PacketState::ChecksumLow } else { PacketState::Data }
+            },
+            PacketState::Data => {
+                self.packet_data.push(byte);
+                self.add_to_checksum(byte);
+                if self.packet_data.len() == self.length as usize { PacketState::ChecksumLow } else { PacketState::Data }
+            },
+            PacketState::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                PacketState::ChecksumHigh
+            },
+            PacketState::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                self.process_packet();
+                PacketState::Alive
+            },
+            PacketState::Alive => PacketState::Status,
+            PacketState::Status => PacketState::Magic(0)
+        }
+    }
+
+    fn add_to_checksum(&mut self, byte: u8) {
+        self.checksum = self.checksum.wrapping_add(byte as u16);
+    }
+
+    fn process_packet(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.command {
+            COMMAND_INITIALIZE => {
+                self.image_data.clear();
+                self.status = 0;
+                self.busy_status_count = 0;
+            },
+            COMMAND_DATA => {
+                let data = core::mem::take(&mut self.packet_data);
+                if self.compressed {
+                    self.decompress(&data);
+                }
+                else {
+                    self.append_image_data(&data);
+                }
+                self.packet_data = data;
+                if !self.image_data.is_empty() {
+                    self.status |= STATUS_UNPROCESSED_DATA;
+                }
+                if self.image_data.len() >= BUFFER_SIZE {
+                    self.status |= STATUS_IMAGE_DATA_FULL;
+                }
+            },
+            COMMAND_PRINT => {
+                if self.packet_data.len() != 4 {
+                    self.status |= STATUS_PACKET_ERROR;
+                    return
+                }
+                self.print();
+            },
+            COMMAND_STATUS => {
+                if self.busy_status_count > 0 {
+                    self.busy_status_count -= 1;
+                    if self.busy_status_count == 0 {
+                        self.status &= !(STATUS_PRINTING | STATUS_IMAGE_DATA_FULL);
+                    }
+                }
+            },
+            _ => self.status |= STATUS_PACKET_ERROR
+        }
+    }
+
+    fn append_image_data(&mut self, data: &[u8]) {
+        let space = BUFFER_SIZE - self.image_data.len();
+        self.image_data.extend_from_slice(&data[..data.len().min(space)]);
+    }
+
+    /// Decompress run-length encoded data.
+    ///
+    /// A control byte with bit 7 set repeats the next byte `(control & 0x7F) + 2` times. Otherwise,
+    /// `control + 1` bytes are copied as-is.
+    fn decompress(&mut self, data: &[u8]) {
+        let mut i = 0;
+        while i < data.len() {
+            let control = data[i];
+            i += 1;
+            if (control & 0x80) != 0 {
+                let Some(&byte) = data.get(i) else { break };
+                i += 1;
+                for _ in 0..(control & 0x7F) as usize + 2 {
+                    self.append_image_data(&[byte]);
+                }
+            }
+            else {
+                let end = (i + control as usize + 1).min(data.len());
+                let run = &data[i..end];
+                i = end;
+                self.append_image_data(run);
+            }
+        }
+    }
+
+    fn print(&mut self) {
+        let [sheets, margins, palette, exposure] = [self.packet_data[0], self.packet_data[1], self.packet_data[2], self.packet_data[3]];
+        let exposure = exposure & 0x7F;
+        let palette = if palette == 0 { 0xE4 } else { palette };
+
+        // Incomplete tile rows are not printed.
+        let tile_rows = self.image_data.len() / TILE_ROW_SIZE;
+        let height = tile_rows * 8;
+        let mut pixels = alloc::vec![0xFFu8; PRINTER_WIDTH * height];
+
+        for tile_row in 0..tile_rows {
+            for tile in 0..20 {
+                let tile_data = &self.image_data[(tile_row * 20 + tile) * 16..][..16];
+                for y in 0..8 {
+                    let low = tile_data[y * 2];
+                    let high = tile_data[y * 2 + 1];
+                    for x in 0..8 {
+                        let color = (((high >> (7 - x)) & 1) << 1) | ((low >> (7 - x)) & 1);
+                        let shade = (palette >> (color * 2)) & 3;
+                        pixels[(tile_row * 8 + y) * PRINTER_WIDTH + tile * 8 + x] = Self::shade_to_gray(shade, exposure);
+                    }
+                }
+            }
+        }
+
+        self.image_data.clear();
+        self.status &= !STATUS_UNPROCESSED_DATA;
+        self.status |= STATUS_PRINTING | STATUS_IMAGE_DATA_FULL;
+        self.busy_status_count = PRINT_BUSY_STATUS_COUNT;
+
+        // A sheet count of 0 only feeds paper.
+        if sheets == 0 || height == 0 {
+            return
+        }
+        let page = PrintedPage {
+            pixels,
+            height,
+            margin_before: margins >> 4,
+            margin_after: margins & 0xF,
+            palette,
+            exposure
+        };
+        (self.on_print)(page);
+    }
+
+    /// Convert a shade (0 = white, 3 = black) to grayscale, darkened or lightened by up to 25%
+    /// according to the exposure.
+    fn shade_to_gray(shade: u8, exposure: u8) -> u8 {
+        let gray = 255 - (shade as i32) * 85;
+        let adjustment = exposure as i32 - 0x40;
+        (gray - adjustment).clamp(0, 255) as u8
+    }
+}
+
+impl<F: FnMut(PrintedPage)> ByteSerialDevice for GameBoyPrinter<F> {
+    fn exchange_byte(&mut self, outgoing: u8) -> u8 {
+        let reply = match self.state {
+            PacketState::Alive => ALIVE,
+            PacketState::Status => self.status,
+            _ => 0x00
+        };
+        self.receive(outgoing);
+        reply
+    }
+}
+
+#[cfg(feature = "std")]
+impl PrintedPage {
+    /// Write the page as an 8-bit grayscale PNG.
+    ///
+    /// The image data is stored uncompressed. Fails with [`InvalidInput`](std::io::ErrorKind::InvalidInput)
+    /// if the page has no rows, since PNG does not allow empty images.
+    pub fn write_png<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        use crate::util::{crc32, crc32_update};
+
+        if self.height == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "the page is empty"))
+        }
+
+        fn write_chunk<W: std::io::Write>(writer: &mut W, chunk_type: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+            writer.write_all(&(data.len() as u32).to_be_bytes())?;
+            writer.write_all(chunk_type)?;
+            writer.write_all(data)?;
+            writer.write_all(&crc32_update(crc32(chunk_type), data).to_be_bytes())
+        }
+
+        writer.write_all(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A])?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(PRINTER_WIDTH as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        header.extend_from_slice(&[8, 0, 0, 0, 0]); // 8-bit, grayscale, deflate, no filter, no interlace
+        write_chunk(writer, b"IHDR", &header)?;
+
+        // Each row is prefixed with filter type 0 (none).
+        let mut raw = Vec::with_capacity((PRINTER_WIDTH + 1) * self.height);
+        for row in self.pixels.chunks(PRINTER_WIDTH) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        // zlib stream of stored (uncompressed) deflate blocks
+        let mut zlib = alloc::vec![0x78, 0x01];
+        let mut blocks = raw.chunks(0xFFFF).peekable();
+        while let Some(block) = blocks.next() {
+            let last = blocks.peek().is_none();
+            let length = block.len() as u16;
+            zlib.push(last as u8);
+            zlib.extend_from_slice(&length.to_le_bytes());
+            zlib.extend_from_slice(&(!length).to_le_bytes());
+            zlib.extend_from_slice(block);
+        }
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in &raw {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        zlib.extend_from_slice(&((b << 16) | a).to_be_bytes());
+        write_chunk(writer, b"IDAT", &zlib)?;
+
+        write_chunk(writer, b"IEND", &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a packet as the Game Boy sends it, including the two bytes the printer replies to.
+    fn packet(command: u8, data: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        let length = (data.len() as u16).to_le_bytes();
+        bytes.extend_from_slice(&[command, 0, length[0], length[1]]);
+        bytes.extend_from_slice(data);
+        let checksum = bytes[2..].iter().fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes
+    }
+
+    /// Send a packet, returning the alive and status bytes the printer replied with.
+    fn send<F: FnMut(PrintedPage)>(printer: &mut GameBoyPrinter<F>, bytes: &[u8]) -> (u8, u8) {
+        let replies: Vec<u8> = bytes.iter().map(|&b| printer.exchange_byte(b)).collect();
+        (replies[replies.len() - 2], replies[replies.len() - 1])
+    }
+
+    /// One tile row where every pixel is color 1.
+    fn color_1_row() -> Vec<u8> {
+        [0xFF, 0x00].repeat(TILE_ROW_SIZE / 2)
+    }
+
+    fn print_with_palette(palette: u8) -> Vec<PrintedPage> {
+        let mut pages = Vec::new();
+        let mut printer = GameBoyPrinter::new(|page| pages.push(page));
+        send(&mut printer, &packet(COMMAND_INITIALIZE, &[]));
+        send(&mut printer, &packet(COMMAND_DATA, &color_1_row()));
+        send(&mut printer, &packet(COMMAND_DATA, &[]));
+        send(&mut printer, &packet(COMMAND_PRINT, &[1, 0x13, palette, 0x40]));
+        drop(printer);
+        pages
+    }
+
+    #[test]
+    fn prints_a_page() {
+        let pages = print_with_palette(0b11_10_01_00);
+        assert_eq!(pages.len(), 1);
+        let page = &pages[0];
+        assert_eq!(page.height, 8);
+        assert_eq!((page.margin_before, page.margin_after), (1, 3));
+        assert!(page.pixels.iter().all(|&p| p == 255 - 85));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn pages_are_written_as_png() {
+        let pages = print_with_palette(0xE4);
+        let mut png = Vec::new();
+        pages[0].write_png(&mut png).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+
+        let page = PrintedPage { pixels: Vec::new(), height: 0, margin_before: 0, margin_after: 0, palette: 0xE4, exposure: 0x40 };
+        assert_eq!(page.write_png(&mut Vec::new()).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn palette_0_prints_as_e4() {
+        let pages = print_with_palette(0x00);
+        assert_eq!(pages, print_with_palette(0xE4));
+        assert_eq!(pages[0].palette, 0xE4);
+    }
+
+    #[test]
+    fn empty_prints_produce_no_page() {
+        let mut pages = Vec::new();
+        let mut printer = GameBoyPrinter::new(|page| pages.push(page));
+
+        // No image data.
+        send(&mut printer, &packet(COMMAND_INITIALIZE, &[]));
+        send(&mut printer, &packet(COMMAND_PRINT, &[1, 0x00, 0xE4, 0x40]));
+        assert_eq!(send(&mut printer, &packet(COMMAND_STATUS, &[])), (ALIVE, STATUS_PRINTING | STATUS_IMAGE_DATA_FULL));
+
+        // No sheets, which only feeds paper.
+        send(&mut printer, &packet(COMMAND_INITIALIZE, &[]));
+        send(&mut printer, &packet(COMMAND_DATA, &color_1_row()));
+        send(&mut printer, &packet(COMMAND_PRINT, &[0, 0x03, 0xE4, 0x40]));
+        drop(printer);
+        assert!(pages.is_empty());
+    }
+
+    #[test]
+    fn bad_checksum_is_reported() {
+        let mut printer = GameBoyPrinter::new(|_| ());
+        let mut bytes = packet(COMMAND_DATA, &[1, 2, 3]);
+        let checksum = bytes.len() - 4;
+        bytes[checksum] ^= 1;
+        assert_eq!(send(&mut printer, &bytes), (ALIVE, STATUS_CHECKSUM_ERROR));
+        assert_eq!(send(&mut printer, &packet(COMMAND_STATUS, &[])), (ALIVE, 0));
+    }
+}
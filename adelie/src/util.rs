@@ -0,0 +1,100 @@
+use core::f64::consts::{FRAC_PI_2, PI};
+
+/// Large state that is kept on the heap when an allocator is available, so an emulator stays a
+/// reasonable size on the stack.
+#[cfg(feature = "alloc")]
+pub(crate) type Boxed<T> = alloc::boxed::Box<T>;
+#[cfg(not(feature = "alloc"))]
+pub(crate) type Boxed<T> = T;
+
+/// Move `value` into a [`Boxed`].
+#[cfg(feature = "alloc")]
+pub(crate) fn boxed<T>(value: T) -> Boxed<T> {
+    alloc::boxed::Box::new(value)
+}
+
+/// Move `value` into a [`Boxed`].
+#[cfg(not(feature = "alloc"))]
+pub(crate) fn boxed<T>(value: T) -> Boxed<T> {
+    value
+}
+
+/// Compute the sine of `x` (in radians).
+///
+/// `core` does not provide trigonometric functions without `std`, so this is used instead to keep
+/// results identical regardless of features.
+pub(crate) fn sin(x: f64) -> f64 {
+    // Reduce to -pi..pi, then to -pi/2..pi/2 where the series converges quickly.
+    let mut x = x % (2.0 * PI);
+    if x > PI {
+        x -= 2.0 * PI;
+    }
+    else if x < -PI {
+        x += 2.0 * PI;
+    }
+    if x > FRAC_PI_2 {
+        x = PI - x;
+    }
+    else if x < -FRAC_PI_2 {
+        x = -PI - x;
+    }
+
+    let x2 = x * x;
+    let mut term = x;
+    let mut result = x;
+    for n in 1..12 {
+        term *= -x2 / (((2 * n) * (2 * n + 1)) as f64);
+        result += term;
+    }
+    result
+}
+
+/// Compute the cosine of `x` (in radians).
+pub(crate) fn cos(x: f64) -> f64 {
+    sin(x + FRAC_PI_2)
+}
+
+/// Round `x` down to the nearest integer.
+///
+/// Like [`sin`], this is provided since `core` does not have it without `std`.
+pub(crate) fn floor(x: f64) -> f64 {
+    let truncated = x as i64 as f64;
+    if truncated > x { truncated - 1.0 } else { truncated }
+}
+
+/// Round `x` up to the nearest integer.
+pub(crate) fn ceil(x: f64) -> f64 {
+    -floor(-x)
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Continue a CRC-32 (as used by zlib and PNG) with more data.
+///
+/// Start with `0` and pass the result of the previous call to continue.
+pub(crate) fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc = CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// Compute the CRC-32 (as used by zlib and PNG) of the data.
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}